@@ -1,11 +1,27 @@
 use crate::git as git_service;
+use tauri::{AppHandle, Emitter};
+
+/// Event carrying a git_service::RemoteProgress during fetch, pull and push
+const REMOTE_PROGRESS_EVENT: &str = "git://remote-progress";
 
 #[tauri::command]
 pub fn list_commits(
     repo_path: String,
     limit: Option<usize>,
+    revision: Option<String>,
 ) -> Result<Vec<git_service::Commit>, String> {
-    git_service::list_commits(&repo_path, limit)
+    match revision {
+        Some(revision) => git_service::list_commits_from(&repo_path, &revision, limit),
+        None => git_service::list_commits(&repo_path, limit),
+    }
+}
+
+#[tauri::command]
+pub fn verify_commit_signatures(
+    repo_path: String,
+    commit_ids: Vec<String>,
+) -> Result<Vec<git_service::SignatureVerification>, String> {
+    git_service::verify_commit_signatures(&repo_path, &commit_ids)
 }
 
 #[tauri::command]
@@ -33,6 +49,25 @@ pub fn get_file_diff(
     git_service::get_file_diff(&repo_path, &commit_id, &file_path)
 }
 
+#[tauri::command]
+pub fn compare_revisions(
+    repo_path: String,
+    base: String,
+    head: String,
+) -> Result<Vec<git_service::ChangedFile>, String> {
+    git_service::compare_revisions(&repo_path, &base, &head)
+}
+
+#[tauri::command]
+pub fn get_compare_file_diff(
+    repo_path: String,
+    base: String,
+    head: String,
+    file_path: String,
+) -> Result<git_service::FileDiff, String> {
+    git_service::get_compare_file_diff(&repo_path, &base, &head, &file_path)
+}
+
 #[tauri::command]
 pub fn get_file_contents(
     repo_path: String,
@@ -62,8 +97,95 @@ pub fn list_branches(repo_path: String) -> Result<Vec<git_service::Branch>, Stri
 }
 
 #[tauri::command]
-pub fn checkout_branch(repo_path: String, branch_name: String) -> Result<(), String> {
-    git_service::checkout_branch(&repo_path, &branch_name)
+pub fn checkout_branch(
+    repo_path: String,
+    branch_name: String,
+    mode: Option<git_service::CheckoutMode>,
+) -> Result<git_service::CheckoutResult, String> {
+    git_service::checkout_branch(
+        &repo_path,
+        &branch_name,
+        mode.unwrap_or(git_service::CheckoutMode::RequireClean),
+    )
+}
+
+#[tauri::command]
+pub fn checkout_remote_branch(
+    repo_path: String,
+    remote_branch: String,
+    mode: Option<git_service::CheckoutMode>,
+) -> Result<git_service::CheckoutResult, String> {
+    git_service::checkout_remote_branch(
+        &repo_path,
+        &remote_branch,
+        mode.unwrap_or(git_service::CheckoutMode::RequireClean),
+    )
+}
+
+#[tauri::command]
+pub fn checkout_detached(
+    repo_path: String,
+    revision: String,
+    mode: Option<git_service::CheckoutMode>,
+) -> Result<git_service::CheckoutResult, String> {
+    git_service::checkout_detached(
+        &repo_path,
+        &revision,
+        mode.unwrap_or(git_service::CheckoutMode::RequireClean),
+    )
+}
+
+#[tauri::command]
+pub fn create_branch(
+    repo_path: String,
+    branch_name: String,
+    start_point: Option<String>,
+    checkout: bool,
+) -> Result<git_service::Branch, String> {
+    git_service::create_branch(&repo_path, &branch_name, start_point.as_deref(), checkout)
+}
+
+#[tauri::command]
+pub fn rename_branch(
+    repo_path: String,
+    branch_name: String,
+    new_name: String,
+) -> Result<git_service::Branch, String> {
+    git_service::rename_branch(&repo_path, &branch_name, &new_name)
+}
+
+#[tauri::command]
+pub fn delete_branch(repo_path: String, branch_name: String, force: bool) -> Result<(), String> {
+    git_service::delete_branch(&repo_path, &branch_name, force)
+}
+
+#[tauri::command]
+pub fn set_branch_upstream(
+    repo_path: String,
+    branch_name: String,
+    upstream: Option<String>,
+) -> Result<(), String> {
+    git_service::set_branch_upstream(&repo_path, &branch_name, upstream.as_deref())
+}
+
+#[tauri::command]
+pub fn list_tags(repo_path: String) -> Result<Vec<git_service::Tag>, String> {
+    git_service::list_tags(&repo_path)
+}
+
+#[tauri::command]
+pub fn create_tag(
+    repo_path: String,
+    tag_name: String,
+    target: Option<String>,
+    message: Option<String>,
+) -> Result<git_service::Tag, String> {
+    git_service::create_tag(&repo_path, &tag_name, target.as_deref(), message.as_deref())
+}
+
+#[tauri::command]
+pub fn delete_tag(repo_path: String, tag_name: String) -> Result<(), String> {
+    git_service::delete_tag(&repo_path, &tag_name)
 }
 
 #[tauri::command]
@@ -71,6 +193,31 @@ pub fn validate_repo(path: String) -> Result<git_service::RepoInfo, String> {
     git_service::validate_repo(&path)
 }
 
+#[tauri::command]
+pub fn list_worktrees(repo_path: String) -> Result<Vec<git_service::Worktree>, String> {
+    git_service::list_worktrees(&repo_path)
+}
+
+#[tauri::command]
+pub fn add_worktree(
+    repo_path: String,
+    path: String,
+    branch: String,
+    create_branch: Option<bool>,
+) -> Result<git_service::RepoInfo, String> {
+    git_service::add_worktree(&repo_path, &path, &branch, create_branch.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn remove_worktree(repo_path: String, name: String, force: Option<bool>) -> Result<(), String> {
+    git_service::remove_worktree(&repo_path, &name, force.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn prune_worktrees(repo_path: String) -> Result<Vec<String>, String> {
+    git_service::prune_worktrees(&repo_path)
+}
+
 #[tauri::command]
 pub fn get_working_changes(repo_path: String) -> Result<Vec<git_service::ChangedFile>, String> {
     git_service::get_working_changes(&repo_path)
@@ -131,12 +278,342 @@ pub fn get_unstaged_file_contents(
     git_service::get_unstaged_file_contents(&repo_path, &file_path)
 }
 
+#[tauri::command]
+pub fn stage_file(repo_path: String, file_path: String) -> Result<(), String> {
+    git_service::stage_file(&repo_path, &file_path)
+}
+
+#[tauri::command]
+pub fn stage_all(repo_path: String) -> Result<(), String> {
+    git_service::stage_all(&repo_path)
+}
+
 #[tauri::command]
 pub fn unstage_file(repo_path: String, file_path: String) -> Result<(), String> {
     git_service::unstage_file(&repo_path, &file_path)
 }
 
 #[tauri::command]
-pub fn discard_file(repo_path: String, file_path: String) -> Result<(), String> {
-    git_service::discard_file(&repo_path, &file_path)
+pub fn unstage_all(repo_path: String) -> Result<(), String> {
+    git_service::unstage_all(&repo_path)
+}
+
+#[tauri::command]
+pub fn unstage_paths(repo_path: String, pathspecs: Vec<String>) -> Result<(), String> {
+    git_service::unstage_paths(&repo_path, &pathspecs)
+}
+
+#[tauri::command]
+pub fn stage_hunk(
+    repo_path: String,
+    file_path: String,
+    hunk_header: String,
+    hunk_hash: String,
+) -> Result<(), String> {
+    git_service::stage_hunk(&repo_path, &file_path, &hunk_header, &hunk_hash)
+}
+
+#[tauri::command]
+pub fn unstage_hunk(
+    repo_path: String,
+    file_path: String,
+    hunk_header: String,
+    hunk_hash: String,
+) -> Result<(), String> {
+    git_service::unstage_hunk(&repo_path, &file_path, &hunk_header, &hunk_hash)
+}
+
+#[tauri::command]
+pub fn stage_lines(
+    repo_path: String,
+    file_path: String,
+    lines: Vec<git_service::DiffLineSelection>,
+) -> Result<(), String> {
+    git_service::stage_lines(&repo_path, &file_path, &lines)
+}
+
+#[tauri::command]
+pub fn unstage_lines(
+    repo_path: String,
+    file_path: String,
+    lines: Vec<git_service::DiffLineSelection>,
+) -> Result<(), String> {
+    git_service::unstage_lines(&repo_path, &file_path, &lines)
+}
+
+#[tauri::command]
+pub fn discard_hunk(
+    repo_path: String,
+    file_path: String,
+    hunk_header: String,
+    hunk_hash: String,
+    use_trash: Option<bool>,
+) -> Result<(), String> {
+    git_service::discard_hunk(
+        &repo_path,
+        &file_path,
+        &hunk_header,
+        &hunk_hash,
+        use_trash.unwrap_or(false),
+    )
+}
+
+#[tauri::command]
+pub fn discard_file(
+    repo_path: String,
+    file_path: String,
+    use_trash: Option<bool>,
+) -> Result<(), String> {
+    git_service::discard_file(&repo_path, &file_path, use_trash.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn discard_paths(
+    repo_path: String,
+    pathspecs: Vec<String>,
+    use_trash: Option<bool>,
+) -> Result<(), String> {
+    git_service::discard_paths(&repo_path, &pathspecs, use_trash.unwrap_or(false))
+}
+
+#[tauri::command]
+pub fn list_undo_entries(repo_path: String) -> Result<Vec<git_service::UndoEntry>, String> {
+    git_service::list_undo_entries(&repo_path)
+}
+
+#[tauri::command]
+pub fn undo(repo_path: String, id: String) -> Result<git_service::UndoEntry, String> {
+    git_service::undo(&repo_path, &id)
+}
+
+#[tauri::command]
+pub fn create_commit(
+    repo_path: String,
+    message: String,
+    amend: bool,
+) -> Result<git_service::CommitResult, String> {
+    git_service::create_commit(&repo_path, &message, amend)
+}
+
+#[tauri::command]
+pub fn get_last_commit_message(repo_path: String) -> Result<String, String> {
+    git_service::get_last_commit_message(&repo_path)
+}
+
+#[tauri::command]
+pub fn revert_commit(
+    repo_path: String,
+    commit_ids: Vec<String>,
+    auto_commit: bool,
+) -> Result<git_service::ApplyCommitsResult, String> {
+    git_service::revert_commit(&repo_path, &commit_ids, auto_commit)
+}
+
+#[tauri::command]
+pub fn cherry_pick_commit(
+    repo_path: String,
+    commit_ids: Vec<String>,
+    auto_commit: bool,
+) -> Result<git_service::ApplyCommitsResult, String> {
+    git_service::cherry_pick_commit(&repo_path, &commit_ids, auto_commit)
+}
+
+#[tauri::command]
+pub fn reset_to_commit(
+    repo_path: String,
+    commit: String,
+    mode: git_service::ResetMode,
+) -> Result<git_service::ResetResult, String> {
+    git_service::reset_to_commit(&repo_path, &commit, mode)
+}
+
+#[tauri::command]
+pub fn undo_reset(
+    repo_path: String,
+    undo_token: String,
+) -> Result<git_service::ResetResult, String> {
+    git_service::undo_reset(&repo_path, &undo_token)
+}
+
+#[tauri::command]
+pub fn get_repo_state(repo_path: String) -> Result<git_service::RepoStateInfo, String> {
+    git_service::get_repo_state(&repo_path)
+}
+
+#[tauri::command]
+pub fn get_conflicts(repo_path: String) -> Result<Vec<git_service::ConflictFile>, String> {
+    git_service::get_conflicts(&repo_path)
+}
+
+#[tauri::command]
+pub fn resolve_conflict(
+    repo_path: String,
+    file_path: String,
+    resolution: git_service::ConflictResolution,
+) -> Result<(), String> {
+    git_service::resolve_conflict(&repo_path, &file_path, resolution)
+}
+
+#[tauri::command]
+pub fn mark_resolved(
+    repo_path: String,
+    file_path: String,
+    content: Option<String>,
+) -> Result<(), String> {
+    git_service::mark_resolved(&repo_path, &file_path, content.as_deref())
+}
+
+#[tauri::command]
+pub fn continue_operation(
+    repo_path: String,
+    message: Option<String>,
+) -> Result<git_service::CommitResult, String> {
+    git_service::continue_operation(&repo_path, message.as_deref())
+}
+
+#[tauri::command]
+pub fn abort_operation(repo_path: String) -> Result<(), String> {
+    git_service::abort_operation(&repo_path)
+}
+
+#[tauri::command]
+pub fn start_rebase(
+    repo_path: String,
+    onto: String,
+    todo: Vec<git_service::RebaseTodoItem>,
+) -> Result<git_service::RebaseStatus, String> {
+    git_service::start_rebase(&repo_path, &onto, todo)
+}
+
+#[tauri::command]
+pub fn continue_rebase(repo_path: String) -> Result<git_service::RebaseStatus, String> {
+    git_service::continue_rebase(&repo_path)
+}
+
+#[tauri::command]
+pub fn abort_rebase(repo_path: String) -> Result<(), String> {
+    git_service::abort_rebase(&repo_path)
+}
+
+#[tauri::command]
+pub fn get_rebase_status(repo_path: String) -> Result<Option<git_service::RebaseStatus>, String> {
+    git_service::get_rebase_status(&repo_path)
+}
+
+#[tauri::command]
+pub fn create_fixup_commit(
+    repo_path: String,
+    commit: String,
+) -> Result<git_service::CommitResult, String> {
+    git_service::create_fixup_commit(&repo_path, &commit)
+}
+
+#[tauri::command]
+pub fn autosquash(
+    repo_path: String,
+    onto: Option<String>,
+) -> Result<git_service::RebaseStatus, String> {
+    git_service::autosquash(&repo_path, onto.as_deref())
+}
+
+#[tauri::command]
+pub fn list_stashes(repo_path: String) -> Result<Vec<git_service::Stash>, String> {
+    git_service::list_stashes(&repo_path)
+}
+
+#[tauri::command]
+pub fn create_stash(
+    repo_path: String,
+    message: Option<String>,
+    include_untracked: bool,
+    staged_only: bool,
+) -> Result<git_service::Stash, String> {
+    git_service::create_stash(
+        &repo_path,
+        message.as_deref(),
+        include_untracked,
+        staged_only,
+    )
+}
+
+#[tauri::command]
+pub fn apply_stash(
+    repo_path: String,
+    index: usize,
+) -> Result<git_service::StashApplyResult, String> {
+    git_service::apply_stash(&repo_path, index)
+}
+
+#[tauri::command]
+pub fn pop_stash(repo_path: String, index: usize) -> Result<git_service::StashApplyResult, String> {
+    git_service::pop_stash(&repo_path, index)
+}
+
+#[tauri::command]
+pub fn drop_stash(repo_path: String, index: usize) -> Result<(), String> {
+    git_service::drop_stash(&repo_path, index)
+}
+
+#[tauri::command]
+pub fn get_stash_files(
+    repo_path: String,
+    index: usize,
+) -> Result<Vec<git_service::ChangedFile>, String> {
+    git_service::get_stash_files(&repo_path, index)
+}
+
+#[tauri::command]
+pub fn get_stash_file_diff(
+    repo_path: String,
+    index: usize,
+    file_path: String,
+) -> Result<git_service::FileDiff, String> {
+    git_service::get_stash_file_diff(&repo_path, index, &file_path)
+}
+
+#[tauri::command]
+pub fn list_remotes(repo_path: String) -> Result<Vec<git_service::Remote>, String> {
+    git_service::list_remotes(&repo_path)
+}
+
+// Network operations run off the main thread so the window stays responsive
+
+#[tauri::command(async)]
+pub fn fetch(
+    app: AppHandle,
+    repo_path: String,
+    remote_name: Option<String>,
+    prune: bool,
+) -> Result<(), String> {
+    git_service::fetch(&repo_path, remote_name.as_deref(), prune, &|progress| {
+        let _ = app.emit(REMOTE_PROGRESS_EVENT, progress);
+    })
+}
+
+#[tauri::command(async)]
+pub fn pull(
+    app: AppHandle,
+    repo_path: String,
+    mode: git_service::PullMode,
+) -> Result<git_service::PullResult, String> {
+    git_service::pull(&repo_path, mode, &|progress| {
+        let _ = app.emit(REMOTE_PROGRESS_EVENT, progress);
+    })
+}
+
+#[tauri::command(async)]
+pub fn push(
+    app: AppHandle,
+    repo_path: String,
+    remote_name: Option<String>,
+    set_upstream: bool,
+) -> Result<(), String> {
+    git_service::push(
+        &repo_path,
+        remote_name.as_deref(),
+        set_upstream,
+        &|progress| {
+            let _ = app.emit(REMOTE_PROGRESS_EVENT, progress);
+        },
+    )
 }
@@ -1,5 +1,8 @@
-use git2::{build::CheckoutBuilder, BranchType, Delta, DiffOptions, Repository};
-use serde::Serialize;
+use git2::{
+    build::CheckoutBuilder, ApplyLocation, ApplyOptions, BranchType, Delta, DiffOptions,
+    IndexAddOption, Repository,
+};
+use serde::{Deserialize, Serialize};
 
 /// Status of a file in a commit or working directory
 #[derive(Debug, Clone, Serialize, PartialEq)]
@@ -11,6 +14,7 @@ pub enum FileStatus {
     Copied,
     Unmodified,
     Untracked,
+    Conflicted,
 }
 
 impl From<Delta> for FileStatus {
@@ -65,7 +69,7 @@ pub struct WorkingFile {
 }
 
 /// Type of diff line
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum LineType {
     Context,
     Addition,
@@ -85,9 +89,25 @@ pub struct DiffLine {
     pub new_line_no: Option<u32>,
 }
 
+/// Identifies an addition or deletion line of a diff for partial staging.
+/// Additions are matched by `new_line_no`, deletions by `old_line_no`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DiffLineSelection {
+    /// The type of line (Addition or Deletion)
+    pub line_type: LineType,
+    /// Line number in the old file (required for deletions)
+    pub old_line_no: Option<u32>,
+    /// Line number in the new file (required for additions)
+    pub new_line_no: Option<u32>,
+}
+
 /// A hunk in a diff
 #[derive(Debug, Clone, Serialize)]
 pub struct DiffHunk {
+    /// The hunk header line (e.g. "@@ -1,3 +1,4 @@ fn main")
+    pub header: String,
+    /// Hash of the hunk's lines, used to detect stale hunk selections
+    pub hash: String,
     /// Starting line in old file
     pub old_start: u32,
     /// Number of lines in old file
@@ -133,6 +153,35 @@ pub struct RepoInfo {
     pub name: String,
     /// Current branch name
     pub branch: String,
+    /// Whether this is a linked worktree sharing its history with another repository
+    pub is_worktree: bool,
+    /// Path to the main working directory, for linked worktrees
+    pub main_repo_path: Option<String>,
+    /// Whether the repository is bare, so only its history can be browsed
+    pub is_bare: bool,
+}
+
+/// A working directory attached to a repository, as listed by `git worktree list`
+#[derive(Debug, Clone, Serialize)]
+pub struct Worktree {
+    /// Worktree name (the directory name for the main worktree)
+    pub name: String,
+    /// Path to the working directory
+    pub path: String,
+    /// Checked out branch (None if HEAD is detached)
+    pub branch: Option<String>,
+    /// Commit SHA checked out (None for an unborn branch)
+    pub head: Option<String>,
+    /// Whether this is the main working directory
+    pub is_main: bool,
+    /// Whether this is the worktree the repository was opened from
+    pub is_current: bool,
+    /// Whether the worktree is locked against pruning
+    pub is_locked: bool,
+    /// Reason given when the worktree was locked
+    pub lock_reason: Option<String>,
+    /// Whether the working directory is gone and the worktree can be pruned
+    pub is_prunable: bool,
 }
 
 /// Represents a git branch
@@ -146,6 +195,46 @@ pub struct Branch {
     pub is_remote: bool,
     /// SHA of the tip commit
     pub commit_id: String,
+    /// Summary line of the tip commit
+    pub summary: String,
+    /// Unix timestamp of when the tip commit was made
+    pub timestamp: i64,
+    /// Name of the upstream branch (e.g. "origin/main"), if any
+    pub upstream: Option<String>,
+    /// Number of commits on this branch that aren't on its upstream
+    pub ahead: u32,
+    /// Number of commits on the upstream that aren't on this branch
+    pub behind: u32,
+    /// Whether the tip is reachable from the default branch
+    pub is_merged: bool,
+}
+
+/// Verification status of a commit signature
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum SignatureStatus {
+    /// The commit has no signature
+    Unsigned,
+    /// The commit is signed, but the signature hasn't been verified
+    Signed,
+    /// The signature is valid and made by a known key
+    Good,
+    /// The signature is valid, but it or its key has expired
+    Expired,
+    /// The signature doesn't match the commit, or its key is revoked
+    Bad,
+    /// The signature can't be checked against a known key
+    UnknownKey,
+}
+
+/// Result of verifying the signature of one commit
+#[derive(Debug, Clone, Serialize)]
+pub struct SignatureVerification {
+    /// The SHA hash of the commit
+    pub commit_id: String,
+    /// Verification status of the commit signature
+    pub status: SignatureStatus,
+    /// Identity of the signer (key user ID or SSH principal), if known
+    pub signer: Option<String>,
 }
 
 /// Represents a git commit with essential metadata
@@ -161,6 +250,11 @@ pub struct Commit {
     pub email: String,
     /// Unix timestamp of when the commit was authored
     pub timestamp: i64,
+    /// Whether the commit is signed. Only commits returned by create_commit are
+    /// verified; use verify_commit_signatures for the others.
+    pub signature_status: SignatureStatus,
+    /// Identity of the signer (key user ID or SSH principal), if verified
+    pub signer: Option<String>,
 }
 
 /// Lists commits from a git repository
@@ -179,6 +273,47 @@ pub fn list_commits(repo_path: &str, limit: Option<usize>) -> Result<Vec<Commit>
     // Start from HEAD
     revwalk.push_head().map_err(|e| format!("Failed to push HEAD: {}", e))?;
 
+    walk_commits(&repo, revwalk, limit)
+}
+
+/// Lists commits reachable from a branch, tag or any other revision
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `revision` - Branch, tag, commit SHA or other revision to start from
+/// * `limit` - Maximum number of commits to return (defaults to 100)
+///
+/// # Returns
+/// A vector of Commit structs or an error message
+pub fn list_commits_from(
+    repo_path: &str,
+    revision: &str,
+    limit: Option<usize>,
+) -> Result<Vec<Commit>, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    let start = repo
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| format!("Revision '{}' not found: {}", revision, e))?;
+
+    let mut revwalk = repo
+        .revwalk()
+        .map_err(|e| format!("Failed to create revwalk: {}", e))?;
+    revwalk
+        .push(start.id())
+        .map_err(|e| format!("Failed to push revision: {}", e))?;
+
+    walk_commits(&repo, revwalk, limit)
+}
+
+/// Collects up to `limit` commits (defaults to 100) from a prepared revwalk
+fn walk_commits(
+    repo: &Repository,
+    revwalk: git2::Revwalk,
+    limit: Option<usize>,
+) -> Result<Vec<Commit>, String> {
     let limit = limit.unwrap_or(100);
     let mut commits = Vec::new();
 
@@ -192,27 +327,277 @@ pub fn list_commits(repo_path: &str, limit: Option<usize>) -> Result<Vec<Commit>
             .find_commit(oid)
             .map_err(|e| format!("Failed to find commit: {}", e))?;
 
-        let author = commit.author();
-        let message = commit
-            .message()
-            .unwrap_or("")
-            .lines()
-            .next()
-            .unwrap_or("")
-            .to_string();
-
-        commits.push(Commit {
-            id: oid.to_string(),
-            message,
-            author: author.name().unwrap_or("Unknown").to_string(),
-            email: author.email().unwrap_or("").to_string(),
-            timestamp: author.when().seconds(),
-        });
+        commits.push(commit_summary(repo, &commit));
     }
 
     Ok(commits)
 }
 
+/// Builds the Commit model for a git2 commit. Verifying a signature runs an
+/// external program, so this only reports whether the commit is signed.
+fn commit_summary(repo: &Repository, commit: &git2::Commit) -> Commit {
+    let author = commit.author();
+    let message = commit
+        .message()
+        .unwrap_or("")
+        .lines()
+        .next()
+        .unwrap_or("")
+        .to_string();
+    let signature_status = if repo.extract_signature(&commit.id(), None).is_ok() {
+        SignatureStatus::Signed
+    } else {
+        SignatureStatus::Unsigned
+    };
+
+    Commit {
+        id: commit.id().to_string(),
+        message,
+        author: author.name().unwrap_or("Unknown").to_string(),
+        email: author.email().unwrap_or("").to_string(),
+        timestamp: author.when().seconds(),
+        signature_status,
+        signer: None,
+    }
+}
+
+/// Runs a command with `input` written to its stdin and captures its output
+fn run_with_input(
+    command: &mut std::process::Command,
+    input: &[u8],
+) -> std::io::Result<std::process::Output> {
+    use std::io::Write;
+
+    let mut child = command
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+
+    // Write from another thread so a child that answers early can't block on a full pipe
+    let mut stdin = child.stdin.take();
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || {
+        if let Some(stdin) = stdin.as_mut() {
+            let _ = stdin.write_all(&input);
+        }
+    });
+
+    let output = child.wait_with_output();
+    let _ = writer.join();
+    output
+}
+
+/// Verifies an OpenPGP or X.509 signature through gpg/gpgsm status output.
+/// Mirrors the statuses git reports for `%G?`.
+fn verify_gpg_signature(
+    program: &str,
+    signature: &[u8],
+    data: &[u8],
+) -> (SignatureStatus, Option<String>) {
+    let Ok(temp_dir) = private_temp_dir("recap-verify") else {
+        return (SignatureStatus::UnknownKey, None);
+    };
+    let signature_path = temp_dir.path().join("signature");
+    if write_private_file(&signature_path, signature).is_err() {
+        return (SignatureStatus::UnknownKey, None);
+    }
+
+    let output = run_with_input(
+        std::process::Command::new(program)
+            .args(["--status-fd=1", "--keyid-format=long", "--verify"])
+            .arg(&signature_path)
+            .arg("-"),
+        data,
+    );
+
+    let output = match output {
+        Ok(output) => output,
+        Err(_) => return (SignatureStatus::UnknownKey, None),
+    };
+
+    parse_gpg_status(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reads the verification result from gpg `--status-fd` output. Expired
+/// signatures and keys are reported like git's `X` and `Y` statuses.
+fn parse_gpg_status(status_output: &str) -> (SignatureStatus, Option<String>) {
+    for line in status_output.lines() {
+        let Some(status) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let mut fields = status.splitn(3, ' ');
+        let keyword = fields.next().unwrap_or("");
+        let key_id = fields.next().map(|s| s.to_string());
+        let user_id = fields.next().map(|s| s.to_string());
+
+        match keyword {
+            "GOODSIG" => return (SignatureStatus::Good, user_id.or(key_id)),
+            "EXPSIG" | "EXPKEYSIG" => return (SignatureStatus::Expired, user_id.or(key_id)),
+            "BADSIG" | "REVKEYSIG" => return (SignatureStatus::Bad, user_id.or(key_id)),
+            "ERRSIG" => return (SignatureStatus::UnknownKey, key_id),
+            _ => {}
+        }
+    }
+
+    (SignatureStatus::UnknownKey, None)
+}
+
+/// Verifies an SSH signature with ssh-keygen against gpg.ssh.allowedSignersFile.
+/// Without an allowed signer for the key, a valid signature is reported as UnknownKey.
+fn verify_ssh_signature(
+    config: &git2::Config,
+    signature: &[u8],
+    data: &[u8],
+) -> (SignatureStatus, Option<String>) {
+    let program = config
+        .get_string("gpg.ssh.program")
+        .unwrap_or_else(|_| "ssh-keygen".to_string());
+    let allowed_signers = config.get_path("gpg.ssh.allowedSignersFile").ok();
+
+    let Ok(temp_dir) = private_temp_dir("recap-verify") else {
+        return (SignatureStatus::UnknownKey, None);
+    };
+    let signature_path = temp_dir.path().join("signature");
+    if write_private_file(&signature_path, signature).is_err() {
+        return (SignatureStatus::UnknownKey, None);
+    }
+
+    let result = (|| {
+        if let Some(allowed_signers) = &allowed_signers {
+            let principals = std::process::Command::new(&program)
+                .args(["-Y", "find-principals", "-f"])
+                .arg(allowed_signers)
+                .arg("-s")
+                .arg(&signature_path)
+                .stdin(std::process::Stdio::null())
+                .output();
+
+            let principal = principals
+                .ok()
+                .filter(|o| o.status.success())
+                .and_then(|o| {
+                    String::from_utf8_lossy(&o.stdout)
+                        .lines()
+                        .next()
+                        .map(|line| line.trim().to_string())
+                });
+
+            if let Some(principal) = principal {
+                let verified = run_with_input(
+                    std::process::Command::new(&program)
+                        .args(["-Y", "verify", "-n", "git", "-f"])
+                        .arg(allowed_signers)
+                        .args(["-I", &principal, "-s"])
+                        .arg(&signature_path),
+                    data,
+                );
+
+                return match verified {
+                    Ok(output) if output.status.success() => {
+                        (SignatureStatus::Good, Some(principal))
+                    }
+                    Ok(_) => (SignatureStatus::Bad, Some(principal)),
+                    Err(_) => (SignatureStatus::UnknownKey, None),
+                };
+            }
+        }
+
+        // No trusted principal - only check that the signature matches its own key
+        let checked = run_with_input(
+            std::process::Command::new(&program)
+                .args(["-Y", "check-novalidate", "-n", "git", "-s"])
+                .arg(&signature_path),
+            data,
+        );
+
+        match checked {
+            Ok(output) if output.status.success() => {
+                // "Good "git" signature with ED25519 key SHA256:..."
+                let fingerprint = String::from_utf8_lossy(&output.stdout)
+                    .split_whitespace()
+                    .find(|word| word.starts_with("SHA256:"))
+                    .map(|word| word.to_string());
+                (SignatureStatus::UnknownKey, fingerprint)
+            }
+            Ok(_) => (SignatureStatus::Bad, None),
+            Err(_) => (SignatureStatus::UnknownKey, None),
+        }
+    })();
+
+    result
+}
+
+/// Verifies the signature of a commit using the local gpg/ssh configuration.
+///
+/// # Returns
+/// The signature status and the signer identity, if known
+fn verify_commit_signature(
+    repo: &Repository,
+    commit_id: git2::Oid,
+) -> (SignatureStatus, Option<String>) {
+    let (signature, signed_data) = match repo.extract_signature(&commit_id, None) {
+        Ok(extracted) => extracted,
+        Err(_) => return (SignatureStatus::Unsigned, None),
+    };
+
+    let config = match repo.config() {
+        Ok(config) => config,
+        Err(_) => return (SignatureStatus::UnknownKey, None),
+    };
+
+    let signature_text = String::from_utf8_lossy(&signature);
+
+    if signature_text.starts_with("-----BEGIN SSH SIGNATURE-----") {
+        verify_ssh_signature(&config, &signature, &signed_data)
+    } else if signature_text.starts_with("-----BEGIN SIGNED MESSAGE-----") {
+        let program = config
+            .get_string("gpg.x509.program")
+            .unwrap_or_else(|_| "gpgsm".to_string());
+        verify_gpg_signature(&program, &signature, &signed_data)
+    } else {
+        let program = config
+            .get_string("gpg.openpgp.program")
+            .or_else(|_| config.get_string("gpg.program"))
+            .unwrap_or_else(|_| "gpg".to_string());
+        verify_gpg_signature(&program, &signature, &signed_data)
+    }
+}
+
+/// Verifies the signatures of commits using the local gpg/ssh configuration.
+/// Each signed commit runs gpg, gpgsm or ssh-keygen, so this is meant for the
+/// commits being looked at rather than whole history listings.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `commit_ids` - SHAs of the commits to verify
+///
+/// # Returns
+/// The verification result of each commit, in the order given
+pub fn verify_commit_signatures(
+    repo_path: &str,
+    commit_ids: &[String],
+) -> Result<Vec<SignatureVerification>, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    commit_ids
+        .iter()
+        .map(|commit_id| {
+            let oid = git2::Oid::from_str(commit_id)
+                .map_err(|e| format!("Invalid commit ID '{}': {}", commit_id, e))?;
+            repo.find_commit(oid)
+                .map_err(|e| format!("Failed to find commit: {}", e))?;
+            let (status, signer) = verify_commit_signature(&repo, oid);
+            Ok(SignatureVerification {
+                commit_id: oid.to_string(),
+                status,
+                signer,
+            })
+        })
+        .collect()
+}
+
 fn resolve_commit_selection_range(
     repo: &Repository,
     commit_ids: &[String],
@@ -323,55 +708,7 @@ pub fn get_commit_files(repo_path: &str, commit_id: &str) -> Result<Vec<ChangedF
         .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut diff_opts))
         .map_err(|e| format!("Failed to create diff: {}", e))?;
 
-    let mut files: Vec<ChangedFile> = Vec::new();
-
-    // Collect file stats
-    let stats = diff
-        .stats()
-        .map_err(|e| format!("Failed to get diff stats: {}", e))?;
-    let _ = stats; // We'll get per-file stats differently
-
-    for delta_idx in 0..diff.deltas().len() {
-        let delta = diff.get_delta(delta_idx).expect("Delta should exist");
-
-        let new_file = delta.new_file();
-        let old_file = delta.old_file();
-
-        let path = new_file
-            .path()
-            .or_else(|| old_file.path())
-            .map(|p| p.to_string_lossy().to_string())
-            .unwrap_or_default();
-
-        let old_path = if delta.status() == Delta::Renamed {
-            old_file.path().map(|p| p.to_string_lossy().to_string())
-        } else {
-            None
-        };
-
-        // Get line stats for this file
-        let mut additions = 0u32;
-        let mut deletions = 0u32;
-
-        // Use a patch to get accurate line counts
-        if let Ok(patch) = git2::Patch::from_diff(&diff, delta_idx) {
-            if let Some(patch) = patch {
-                let (_, adds, dels) = patch.line_stats().unwrap_or((0, 0, 0));
-                additions = adds as u32;
-                deletions = dels as u32;
-            }
-        }
-
-        files.push(ChangedFile {
-            path,
-            status: delta.status().into(),
-            additions,
-            deletions,
-            old_path,
-        });
-    }
-
-    Ok(files)
+    collect_changed_files(&diff)
 }
 
 /// Gets the list of files changed across a selected commit range.
@@ -416,6 +753,11 @@ pub fn get_commit_range_files(repo_path: &str, commit_ids: &[String]) -> Result<
         )
         .map_err(|e| format!("Failed to create diff: {}", e))?;
 
+    collect_changed_files(&diff)
+}
+
+/// Builds the ChangedFile list for a tree diff
+fn collect_changed_files(diff: &git2::Diff) -> Result<Vec<ChangedFile>, String> {
     let mut files: Vec<ChangedFile> = Vec::new();
 
     for delta_idx in 0..diff.deltas().len() {
@@ -436,15 +778,14 @@ pub fn get_commit_range_files(repo_path: &str, commit_ids: &[String]) -> Result<
             None
         };
 
+        // Use a patch to get accurate line counts
         let mut additions = 0u32;
         let mut deletions = 0u32;
 
-        if let Ok(patch) = git2::Patch::from_diff(&diff, delta_idx) {
-            if let Some(patch) = patch {
-                let (_, adds, dels) = patch.line_stats().unwrap_or((0, 0, 0));
-                additions = adds as u32;
-                deletions = dels as u32;
-            }
+        if let Ok(Some(patch)) = git2::Patch::from_diff(diff, delta_idx) {
+            let (_, adds, dels) = patch.line_stats().unwrap_or((0, 0, 0));
+            additions = adds as u32;
+            deletions = dels as u32;
         }
 
         files.push(ChangedFile {
@@ -459,6 +800,73 @@ pub fn get_commit_range_files(repo_path: &str, commit_ids: &[String]) -> Result<
     Ok(files)
 }
 
+/// Computes the identifying hash for a hunk from its lines.
+fn hunk_hash(lines: &[DiffLine]) -> Result<String, String> {
+    let mut data = Vec::new();
+
+    for line in lines {
+        let origin = match line.line_type {
+            LineType::Addition => b'+',
+            LineType::Deletion => b'-',
+            LineType::Context => b' ',
+        };
+        data.push(origin);
+        data.extend_from_slice(line.content.as_bytes());
+    }
+
+    git2::Oid::hash_object(git2::ObjectType::Blob, &data)
+        .map(|oid| oid.to_string())
+        .map_err(|e| format!("Failed to hash hunk: {}", e))
+}
+
+/// Converts the hunks of a patch into DiffHunk structs
+fn collect_hunks(patch: &git2::Patch) -> Result<Vec<DiffHunk>, String> {
+    let mut hunks = Vec::new();
+
+    for hunk_idx in 0..patch.num_hunks() {
+        let (hunk, _) = patch
+            .hunk(hunk_idx)
+            .map_err(|e| format!("Failed to get hunk: {}", e))?;
+
+        let mut lines = Vec::new();
+
+        for line_idx in 0..patch.num_lines_in_hunk(hunk_idx).unwrap_or(0) {
+            let line = patch
+                .line_in_hunk(hunk_idx, line_idx)
+                .map_err(|e| format!("Failed to get line: {}", e))?;
+
+            let line_type = match line.origin() {
+                '+' => LineType::Addition,
+                '-' => LineType::Deletion,
+                _ => LineType::Context,
+            };
+
+            let content = String::from_utf8_lossy(line.content()).to_string();
+
+            lines.push(DiffLine {
+                content,
+                line_type,
+                old_line_no: line.old_lineno(),
+                new_line_no: line.new_lineno(),
+            });
+        }
+
+        hunks.push(DiffHunk {
+            header: String::from_utf8_lossy(hunk.header())
+                .trim_end()
+                .to_string(),
+            hash: hunk_hash(&lines)?,
+            old_start: hunk.old_start(),
+            old_lines: hunk.old_lines(),
+            new_start: hunk.new_start(),
+            new_lines: hunk.new_lines(),
+            lines,
+        });
+    }
+
+    Ok(hunks)
+}
+
 /// Gets the diff for a specific file in a commit
 ///
 /// # Arguments
@@ -506,10 +914,18 @@ pub fn get_file_diff(
         .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut diff_opts))
         .map_err(|e| format!("Failed to create diff: {}", e))?;
 
-    // Find the delta for our file
-    let delta = diff
-        .get_delta(0)
-        .ok_or_else(|| format!("File '{}' not found in commit", file_path))?;
+    first_file_diff(&diff)?.ok_or_else(|| format!("File '{}' not found in commit", file_path))
+}
+
+/// Builds the FileDiff for the first delta of a tree diff limited to one file.
+///
+/// # Returns
+/// None when the diff has no delta
+fn first_file_diff(diff: &git2::Diff) -> Result<Option<FileDiff>, String> {
+    let delta = match diff.get_delta(0) {
+        Some(delta) => delta,
+        None => return Ok(None),
+    };
 
     let new_file = delta.new_file();
     let old_file = delta.old_file();
@@ -530,63 +946,101 @@ pub fn get_file_diff(
     let is_binary = new_file.is_binary() || old_file.is_binary();
 
     if is_binary {
-        return Ok(FileDiff {
+        return Ok(Some(FileDiff {
             old_path,
             new_path,
             hunks: Vec::new(),
             is_binary: true,
-        });
+        }));
     }
 
     // Get patch for detailed diff
-    let patch = git2::Patch::from_diff(&diff, 0)
+    let patch = git2::Patch::from_diff(diff, 0)
         .map_err(|e| format!("Failed to create patch: {}", e))?
         .ok_or_else(|| "Failed to create patch for file".to_string())?;
 
-    let mut hunks = Vec::new();
+    let hunks = collect_hunks(&patch)?;
 
-    for hunk_idx in 0..patch.num_hunks() {
-        let (hunk, _) = patch
-            .hunk(hunk_idx)
-            .map_err(|e| format!("Failed to get hunk: {}", e))?;
+    Ok(Some(FileDiff {
+        old_path,
+        new_path,
+        hunks,
+        is_binary: false,
+    }))
+}
 
-        let mut lines = Vec::new();
+/// Diffs the trees of two revisions
+fn diff_revisions<'r>(
+    repo: &'r Repository,
+    base: &str,
+    head: &str,
+    file_path: Option<&str>,
+) -> Result<git2::Diff<'r>, String> {
+    let tree_of = |revision: &str| {
+        repo.revparse_single(revision)
+            .and_then(|object| object.peel_to_tree())
+            .map_err(|e| format!("Revision '{}' not found: {}", revision, e))
+    };
+    let base_tree = tree_of(base)?;
+    let head_tree = tree_of(head)?;
 
-        for line_idx in 0..patch.num_lines_in_hunk(hunk_idx).unwrap_or(0) {
-            let line = patch
-                .line_in_hunk(hunk_idx, line_idx)
-                .map_err(|e| format!("Failed to get line: {}", e))?;
+    let mut diff_opts = DiffOptions::new();
+    if let Some(file_path) = file_path {
+        diff_opts.pathspec(file_path);
+    }
 
-            let line_type = match line.origin() {
-                '+' => LineType::Addition,
-                '-' => LineType::Deletion,
-                _ => LineType::Context,
-            };
+    repo.diff_tree_to_tree(Some(&base_tree), Some(&head_tree), Some(&mut diff_opts))
+        .map_err(|e| format!("Failed to create diff: {}", e))
+}
 
-            let content = String::from_utf8_lossy(line.content()).to_string();
+/// Gets the list of files that differ between two revisions, e.g. two tags
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `base` - Branch, tag, commit SHA or other revision to compare from
+/// * `head` - Branch, tag, commit SHA or other revision to compare to
+///
+/// # Returns
+/// A vector of ChangedFile structs or an error message
+pub fn compare_revisions(
+    repo_path: &str,
+    base: &str,
+    head: &str,
+) -> Result<Vec<ChangedFile>, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-            lines.push(DiffLine {
-                content,
-                line_type,
-                old_line_no: line.old_lineno(),
-                new_line_no: line.new_lineno(),
-            });
-        }
+    let diff = diff_revisions(&repo, base, head, None)?;
 
-        hunks.push(DiffHunk {
-            old_start: hunk.old_start(),
-            old_lines: hunk.old_lines(),
-            new_start: hunk.new_start(),
-            new_lines: hunk.new_lines(),
-            lines,
-        });
-    }
+    collect_changed_files(&diff)
+}
 
-    Ok(FileDiff {
-        old_path,
-        new_path,
-        hunks,
-        is_binary: false,
+/// Gets the diff of a file between two revisions
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `base` - Branch, tag, commit SHA or other revision to compare from
+/// * `head` - Branch, tag, commit SHA or other revision to compare to
+/// * `file_path` - Path to the file to get diff for
+///
+/// # Returns
+/// A FileDiff struct or an error message
+pub fn get_compare_file_diff(
+    repo_path: &str,
+    base: &str,
+    head: &str,
+    file_path: &str,
+) -> Result<FileDiff, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    let diff = diff_revisions(&repo, base, head, Some(file_path))?;
+
+    first_file_diff(&diff)?.ok_or_else(|| {
+        format!(
+            "File '{}' does not differ between '{}' and '{}'",
+            file_path, base, head
+        )
     })
 }
 
@@ -833,9 +1287,7 @@ pub fn list_branches(repo_path: &str) -> Result<Vec<Branch>, String> {
     let repo =
         Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-    // Get current branch name for comparison
-    let current_branch = get_current_branch(repo_path).ok();
-
+    let default_tip = default_branch_tip(&repo);
     let mut branches = Vec::new();
 
     // Get local branches
@@ -846,28 +1298,7 @@ pub fn list_branches(repo_path: &str) -> Result<Vec<Branch>, String> {
     for branch_result in local_branches {
         let (branch, _branch_type) =
             branch_result.map_err(|e| format!("Failed to get branch: {}", e))?;
-
-        let name = branch
-            .name()
-            .map_err(|e| format!("Failed to get branch name: {}", e))?
-            .ok_or_else(|| "Branch name is not valid UTF-8".to_string())?
-            .to_string();
-
-        let is_current = current_branch.as_ref() == Some(&name);
-
-        // Get the tip commit SHA
-        let commit_id = branch
-            .get()
-            .peel_to_commit()
-            .map(|c| c.id().to_string())
-            .unwrap_or_default();
-
-        branches.push(Branch {
-            name,
-            is_current,
-            is_remote: false,
-            commit_id,
-        });
+        branches.push(branch_info(&repo, &branch, false, default_tip)?);
     }
 
     // Get remote branches
@@ -878,26 +1309,7 @@ pub fn list_branches(repo_path: &str) -> Result<Vec<Branch>, String> {
     for branch_result in remote_branches {
         let (branch, _branch_type) =
             branch_result.map_err(|e| format!("Failed to get branch: {}", e))?;
-
-        let name = branch
-            .name()
-            .map_err(|e| format!("Failed to get branch name: {}", e))?
-            .ok_or_else(|| "Branch name is not valid UTF-8".to_string())?
-            .to_string();
-
-        // Get the tip commit SHA
-        let commit_id = branch
-            .get()
-            .peel_to_commit()
-            .map(|c| c.id().to_string())
-            .unwrap_or_default();
-
-        branches.push(Branch {
-            name,
-            is_current: false, // Remote branches can't be current
-            is_remote: true,
-            commit_id,
-        });
+        branches.push(branch_info(&repo, &branch, true, default_tip)?);
     }
 
     // Sort: current branch first, then local branches, then remote
@@ -914,2606 +1326,12575 @@ pub fn list_branches(repo_path: &str) -> Result<Vec<Branch>, String> {
     Ok(branches)
 }
 
-/// Checks out a branch
-///
-/// # Arguments
-/// * `repo_path` - Path to the git repository
-/// * `branch_name` - Name of the branch to checkout
-///
-/// # Returns
-/// Ok(()) on success, or an error message
-pub fn checkout_branch(repo_path: &str, branch_name: &str) -> Result<(), String> {
-    let repo =
-        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
-
-    // Check for uncommitted changes that would be overwritten
-    let statuses = repo
-        .statuses(None)
-        .map_err(|e| format!("Failed to get status: {}", e))?;
+/// How a checkout treats uncommitted local changes
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum CheckoutMode {
+    /// Refuse to switch while tracked files have uncommitted changes
+    RequireClean,
+    /// Carry local changes over, failing only when they touch files that
+    /// differ between the two commits (like `git switch`)
+    Safe,
+    /// Stash local changes, switch, then re-apply the stash
+    AutoStash,
+}
 
-    let has_changes = statuses.iter().any(|entry| {
-        let status = entry.status();
-        // Check for modifications in index or workdir that could conflict
-        status.is_wt_modified()
-            || status.is_wt_deleted()
-            || status.is_index_modified()
-            || status.is_index_deleted()
-            || status.is_index_new()
-    });
+/// Outcome of a checkout
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct CheckoutResult {
+    /// Files left with conflicts after re-applying auto-stashed changes
+    pub conflicts: Vec<String>,
+    /// The auto-stash, kept in the stash list because re-applying it conflicted
+    pub stash: Option<Stash>,
+}
 
-    if has_changes {
-        return Err(
-            "Cannot switch branches: you have uncommitted changes that would be overwritten"
-                .to_string(),
-        );
+/// Finds the tip of the default branch: the branch origin/HEAD points to, or else
+/// the local branch named by init.defaultBranch, "main" or "master"
+fn default_branch_tip(repo: &Repository) -> Option<git2::Oid> {
+    if let Ok(origin_head) = repo.find_reference("refs/remotes/origin/HEAD") {
+        if let Ok(commit) = origin_head.peel_to_commit() {
+            return Some(commit.id());
+        }
     }
 
-    // Find the branch
-    let branch = repo
-        .find_branch(branch_name, BranchType::Local)
-        .map_err(|e| format!("Branch '{}' not found: {}", branch_name, e))?;
+    let configured = repo
+        .config()
+        .and_then(|config| config.get_string("init.defaultBranch"))
+        .ok();
 
-    let reference = branch.into_reference();
-    let refname = reference
-        .name()
-        .ok_or_else(|| "Branch reference name is not valid UTF-8".to_string())?;
+    let tip = configured
+        .iter()
+        .map(String::as_str)
+        .chain(["main", "master"])
+        .find_map(|name| {
+            repo.find_branch(name, BranchType::Local)
+                .and_then(|branch| branch.get().peel_to_commit())
+                .map(|commit| commit.id())
+                .ok()
+        });
 
-    // Set HEAD to point to the branch
-    repo.set_head(refname)
-        .map_err(|e| format!("Failed to set HEAD: {}", e))?;
+    tip
+}
 
-    // Checkout the tree (update working directory)
-    let tree = reference
-        .peel_to_tree()
-        .map_err(|e| format!("Failed to get tree: {}", e))?;
+/// Builds the Branch model for a local or remote git2 branch.
+/// `default_tip` is the tip of the default branch, used to tell merged branches apart.
+fn branch_info(
+    repo: &Repository,
+    branch: &git2::Branch,
+    is_remote: bool,
+    default_tip: Option<git2::Oid>,
+) -> Result<Branch, String> {
+    let name = branch
+        .name()
+        .map_err(|e| format!("Failed to get branch name: {}", e))?
+        .ok_or_else(|| "Branch name is not valid UTF-8".to_string())?
+        .to_string();
 
-    let mut checkout_opts = CheckoutBuilder::new();
-    checkout_opts.force(); // Force checkout to update working directory
+    // A dangling or non-commit ref is still listed, just without tip details
+    let tip = branch.get().peel_to_commit().ok();
+
+    // Ahead/behind are only meaningful against an upstream
+    let mut upstream = None;
+    let mut ahead = 0;
+    let mut behind = 0;
+    if !is_remote {
+        if let Ok(upstream_branch) = branch.upstream() {
+            upstream = upstream_branch.name().ok().flatten().map(|n| n.to_string());
+            if let (Some(tip), Ok(upstream_tip)) = (&tip, upstream_branch.get().peel_to_commit()) {
+                let (a, b) = repo
+                    .graph_ahead_behind(tip.id(), upstream_tip.id())
+                    .map_err(|e| format!("Failed to compare with upstream: {}", e))?;
+                ahead = a as u32;
+                behind = b as u32;
+            }
+        }
+    }
 
-    repo.checkout_tree(tree.as_object(), Some(&mut checkout_opts))
-        .map_err(|e| format!("Failed to checkout: {}", e))?;
+    let is_merged = match (default_tip, &tip) {
+        (Some(default_tip), Some(tip)) => {
+            default_tip == tip.id()
+                || repo
+                    .graph_descendant_of(default_tip, tip.id())
+                    .map_err(|e| format!("Failed to compare with default branch: {}", e))?
+        }
+        _ => false,
+    };
 
-    Ok(())
+    Ok(Branch {
+        name,
+        // Remote branches can't be current
+        is_current: !is_remote && branch.is_head(),
+        is_remote,
+        commit_id: tip.as_ref().map(|t| t.id().to_string()).unwrap_or_default(),
+        summary: tip
+            .as_ref()
+            .and_then(|t| t.summary())
+            .unwrap_or("")
+            .to_string(),
+        timestamp: tip.as_ref().map(|t| t.time().seconds()).unwrap_or(0),
+        upstream,
+        ahead,
+        behind,
+        is_merged,
+    })
 }
 
-/// Validates that a path is a git repository and returns info about it
-///
-/// # Arguments
-/// * `path` - Path to validate
-///
-/// # Returns
-/// A RepoInfo struct or an error message
-pub fn validate_repo(path: &str) -> Result<RepoInfo, String> {
-    let repo = Repository::open(path).map_err(|e| format!("Not a valid git repository: {}", e))?;
+/// Checks out `target` and points HEAD at `refname`, or detaches HEAD at `target`
+/// when no ref is given. A safe checkout keeps local changes and fails listing the
+/// files they would be overwritten in; a forced checkout discards them.
+fn switch_to(
+    repo: &Repository,
+    target: git2::Oid,
+    refname: Option<&str>,
+    force: bool,
+) -> Result<(), String> {
+    let commit = repo
+        .find_commit(target)
+        .map_err(|e| format!("Failed to find commit: {}", e))?;
 
-    // Get repository root path
-    let repo_path = repo
-        .workdir()
-        .ok_or_else(|| "Repository has no working directory (bare repo)".to_string())?
-        .to_string_lossy()
-        .to_string();
+    let blocked = std::cell::RefCell::new(Vec::new());
+    let result = {
+        let mut checkout_opts = CheckoutBuilder::new();
+        if force {
+            checkout_opts.force();
+        } else {
+            checkout_opts
+                .safe()
+                .notify_on(git2::CheckoutNotificationType::CONFLICT)
+                .notify(|_, path, _, _, _| {
+                    if let Some(path) = path {
+                        blocked
+                            .borrow_mut()
+                            .push(path.to_string_lossy().to_string());
+                    }
+                    true
+                });
+        }
 
-    // Get directory name
-    let name = std::path::Path::new(&repo_path)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("unknown")
-        .to_string();
+        repo.checkout_tree(commit.as_object(), Some(&mut checkout_opts))
+    };
 
-    // Get current branch
-    let branch = get_current_branch(path)?;
+    if let Err(e) = result {
+        let blocked = blocked.into_inner();
+        return Err(if blocked.is_empty() {
+            format!("Failed to checkout: {}", e)
+        } else {
+            format!(
+                "Cannot switch: your local changes to the following files would be overwritten: {}",
+                blocked.join(", ")
+            )
+        });
+    }
 
-    Ok(RepoInfo {
-        path: repo_path,
-        name,
-        branch,
-    })
+    match refname {
+        Some(refname) => repo.set_head(refname),
+        None => repo.set_head_detached(target),
+    }
+    .map_err(|e| format!("Failed to set HEAD: {}", e))
 }
 
-/// Gets the list of files changed in the working directory (uncommitted changes)
+/// Checks out a branch
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
+/// * `branch_name` - Name of the branch to checkout
+/// * `mode` - How uncommitted local changes are handled
 ///
 /// # Returns
-/// A vector of ChangedFile structs representing working directory changes
-pub fn get_working_changes(repo_path: &str) -> Result<Vec<ChangedFile>, String> {
-    let repo =
-        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+/// A CheckoutResult with the conflicts left by re-applying auto-stashed changes,
+/// or an error message
+pub fn checkout_branch(
+    repo_path: &str,
+    branch_name: &str,
+    mode: CheckoutMode,
+) -> Result<CheckoutResult, String> {
+    let mut repo = open_workdir_repo(repo_path)?;
 
-    let statuses = repo
-        .statuses(None)
-        .map_err(|e| format!("Failed to get statuses: {}", e))?;
+    // Find the branch
+    let (refname, target) = {
+        let branch = repo
+            .find_branch(branch_name, BranchType::Local)
+            .map_err(|e| format!("Branch '{}' not found: {}", branch_name, e))?;
 
-    let mut files: Vec<ChangedFile> = Vec::new();
+        let reference = branch.into_reference();
+        let refname = reference
+            .name()
+            .ok_or_else(|| "Branch reference name is not valid UTF-8".to_string())?
+            .to_string();
+        let target = reference
+            .peel_to_commit()
+            .map_err(|e| format!("Failed to get branch commit: {}", e))?
+            .id();
 
-    for entry in statuses.iter() {
-        let status = entry.status();
+        (refname, target)
+    };
 
-        // Skip files that are unchanged
-        if status.is_empty() {
-            continue;
-        }
+    checkout_target(&mut repo, target, Some(&refname), mode)
+}
 
-        let path = entry
-            .path()
-            .map(|p| p.to_string())
-            .unwrap_or_default();
+/// Checks out `target`, pointing HEAD at `refname` or detaching it when no ref
+/// is given, and handles local changes according to `mode`
+fn checkout_target(
+    repo: &mut Repository,
+    target: git2::Oid,
+    refname: Option<&str>,
+    mode: CheckoutMode,
+) -> Result<CheckoutResult, String> {
+    ensure_no_rebase(repo, "check out")?;
+
+    match mode {
+        CheckoutMode::RequireClean => {
+            // Check for uncommitted changes that would be overwritten
+            let statuses = repo
+                .statuses(None)
+                .map_err(|e| format!("Failed to get status: {}", e))?;
+
+            let has_changes = statuses.iter().any(|entry| {
+                let status = entry.status();
+                // Check for modifications in index or workdir that could conflict
+                status.is_wt_modified()
+                    || status.is_wt_deleted()
+                    || status.is_index_modified()
+                    || status.is_index_deleted()
+                    || status.is_index_new()
+            });
 
-        // Determine the file status
-        // We combine index (staged) and worktree (unstaged) status since we're showing all changes
-        let file_status = if status.is_wt_new() || status.is_index_new() {
-            if status.is_wt_new() && !status.is_index_new() {
-                FileStatus::Untracked
-            } else {
-                FileStatus::Added
+            if has_changes {
+                return Err(
+                    "Cannot switch branches: you have uncommitted changes that would be overwritten"
+                        .to_string(),
+                );
             }
-        } else if status.is_wt_deleted() || status.is_index_deleted() {
-            FileStatus::Deleted
-        } else if status.is_wt_modified() || status.is_index_modified() {
-            FileStatus::Modified
-        } else if status.is_wt_renamed() || status.is_index_renamed() {
-            FileStatus::Renamed
-        } else {
-            continue; // Skip other statuses (ignored, etc.)
-        };
 
-        // Get line stats by creating a diff
-        let mut additions = 0u32;
-        let mut deletions = 0u32;
+            switch_to(repo, target, refname, true)?;
+            Ok(CheckoutResult::default())
+        }
+        CheckoutMode::Safe => {
+            switch_to(repo, target, refname, false)?;
+            Ok(CheckoutResult::default())
+        }
+        CheckoutMode::AutoStash => checkout_with_autostash(repo, target, refname),
+    }
+}
 
-        // Try to get line stats from diff to HEAD
-        if let Ok(head) = repo.head() {
-            if let Ok(head_commit) = head.peel_to_commit() {
-                if let Ok(head_tree) = head_commit.tree() {
-                    let mut diff_opts = DiffOptions::new();
-                    diff_opts.pathspec(&path);
-                    diff_opts.include_untracked(true);
+/// Stashes local changes, switches to `target` and re-applies the stash.
+/// The stash is dropped once re-applied cleanly, and kept when it conflicts.
+fn checkout_with_autostash(
+    repo: &mut Repository,
+    target: git2::Oid,
+    refname: Option<&str>,
+) -> Result<CheckoutResult, String> {
+    let stasher = repo
+        .signature()
+        .map_err(|e| format!("Failed to get signature: {}", e))?;
+
+    let stashed = match repo.stash_save2(&stasher, Some("autostash"), None) {
+        Ok(_) => true,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => false,
+        Err(e) => return Err(format!("Failed to stash local changes: {}", e)),
+    };
 
-                    if let Ok(diff) = repo.diff_tree_to_workdir_with_index(
-                        Some(&head_tree),
-                        Some(&mut diff_opts),
-                    ) {
-                        if let Ok(stats) = diff.stats() {
-                            additions = stats.insertions() as u32;
-                            deletions = stats.deletions() as u32;
-                        }
-                    }
-                }
-            }
+    if let Err(e) = switch_to(repo, target, refname, false) {
+        // Put the changes back where they were before giving up
+        if stashed && apply_stash_at(repo, 0).is_ok_and(|conflicts| conflicts.is_empty()) {
+            let _ = repo.stash_drop(0);
         }
+        return Err(e);
+    }
 
-        files.push(ChangedFile {
-            path,
-            status: file_status,
-            additions,
-            deletions,
-            old_path: None, // TODO: Handle renames if needed
-        });
+    if !stashed {
+        return Ok(CheckoutResult::default());
     }
 
-    Ok(files)
+    let conflicts = apply_stash_at(repo, 0)
+        .map_err(|e| format!("Switched, but your changes are still stashed: {}", e))?;
+
+    if conflicts.is_empty() {
+        repo.stash_drop(0)
+            .map_err(|e| format!("Failed to drop stash: {}", e))?;
+        return Ok(CheckoutResult::default());
+    }
+
+    Ok(CheckoutResult {
+        conflicts,
+        stash: read_stashes(repo)?.into_iter().next(),
+    })
 }
 
-/// Gets the list of files changed in the working directory, split by staged/unstaged
+/// Checks out a remote branch through a local branch of the same name that tracks it.
+/// The local branch is created with its upstream set, or fast-forwarded when it's
+/// behind the remote branch. A local branch that has diverged is left alone.
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
+/// * `remote_branch` - Name of the remote branch (e.g. "origin/feature-x")
+/// * `mode` - How uncommitted local changes are handled
 ///
 /// # Returns
-/// A vector of WorkingFile structs, where each file can appear up to twice:
-/// once for staged changes and once for unstaged changes
-pub fn get_working_changes_ex(repo_path: &str) -> Result<Vec<WorkingFile>, String> {
-    let repo =
-        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+/// A CheckoutResult like checkout_branch, or an error message
+pub fn checkout_remote_branch(
+    repo_path: &str,
+    remote_branch: &str,
+    mode: CheckoutMode,
+) -> Result<CheckoutResult, String> {
+    let mut repo = open_workdir_repo(repo_path)?;
+
+    let (remote_refname, target) = {
+        let branch = repo
+            .find_branch(remote_branch, BranchType::Remote)
+            .map_err(|e| format!("Remote branch '{}' not found: {}", remote_branch, e))?;
+        let reference = branch.into_reference();
+        let refname = reference
+            .name()
+            .ok_or_else(|| "Branch reference name is not valid UTF-8".to_string())?
+            .to_string();
+        let target = reference
+            .peel_to_commit()
+            .map_err(|e| format!("Failed to get branch commit: {}", e))?
+            .id();
 
-    let statuses = repo
-        .statuses(None)
-        .map_err(|e| format!("Failed to get statuses: {}", e))?;
+        (refname, target)
+    };
 
-    let mut files: Vec<WorkingFile> = Vec::new();
+    // "origin/feature-x" -> "feature-x", allowing for remote names with slashes
+    let remote_name = repo
+        .branch_remote_name(&remote_refname)
+        .map_err(|e| format!("Failed to find remote of '{}': {}", remote_branch, e))?;
+    let remote_name = remote_name
+        .as_str()
+        .ok_or_else(|| "Remote name is not valid UTF-8".to_string())?;
+    let local_name = remote_branch
+        .strip_prefix(remote_name)
+        .and_then(|name| name.strip_prefix('/'))
+        .ok_or_else(|| {
+            format!(
+                "'{}' is not a branch of remote '{}'",
+                remote_branch, remote_name
+            )
+        })?
+        .to_string();
+    let local_refname = format!("refs/heads/{}", local_name);
+
+    // What to restore if the checkout fails: None to delete a branch we created
+    let previous_target = match repo.find_branch(&local_name, BranchType::Local) {
+        Ok(mut local) => {
+            let local_target = local
+                .get()
+                .peel_to_commit()
+                .map_err(|e| format!("Failed to get branch commit: {}", e))?
+                .id();
+
+            if local.is_head() {
+                set_missing_upstream(&mut local, remote_branch)?;
+                // Already on the branch; moving it would need a pull, not a checkout
+                return Ok(CheckoutResult::default());
+            }
 
-    // Get HEAD tree for diff calculations
-    let head_tree = match repo.head() {
-        Ok(head) => head
-            .peel_to_commit()
-            .ok()
-            .and_then(|c| c.tree().ok()),
-        Err(_) => None,
+            let behind = local_target != target
+                && repo
+                    .graph_descendant_of(target, local_target)
+                    .map_err(|e| format!("Failed to compare branches: {}", e))?;
+            let ahead_or_equal = local_target == target
+                || repo
+                    .graph_descendant_of(local_target, target)
+                    .map_err(|e| format!("Failed to compare branches: {}", e))?;
+
+            if !behind && !ahead_or_equal {
+                return Err(format!(
+                    "Local branch '{}' has diverged from '{}'",
+                    local_name, remote_branch
+                ));
+            }
+            if behind {
+                local
+                    .get_mut()
+                    .set_target(
+                        target,
+                        &format!("branch: Fast-forward to {}", remote_branch),
+                    )
+                    .map_err(|e| format!("Failed to fast-forward branch: {}", e))?;
+            }
+
+            Some(local_target)
+        }
+        Err(_) => {
+            let commit = repo
+                .find_commit(target)
+                .map_err(|e| format!("Failed to find commit: {}", e))?;
+            let mut local = repo
+                .branch(&local_name, &commit, false)
+                .map_err(|e| format!("Failed to create branch: {}", e))?;
+            local
+                .set_upstream(Some(remote_branch))
+                .map_err(|e| format!("Failed to set upstream: {}", e))?;
+
+            None
+        }
     };
 
-    for entry in statuses.iter() {
-        let status = entry.status();
+    let branch_target = repo
+        .refname_to_id(&local_refname)
+        .map_err(|e| format!("Failed to resolve branch: {}", e))?;
+    let result = checkout_target(&mut repo, branch_target, Some(&local_refname), mode);
 
-        // Skip files that are unchanged
-        if status.is_empty() {
-            continue;
+    if result.is_err() {
+        // Leave the branches as they were before the failed checkout
+        match previous_target {
+            Some(previous) if previous != branch_target => {
+                let _ = repo.reference(&local_refname, previous, true, "branch: Undo fast-forward");
+            }
+            Some(_) => {}
+            None => {
+                if let Ok(mut local) = repo.find_branch(&local_name, BranchType::Local) {
+                    let _ = local.delete();
+                }
+            }
         }
+    } else if previous_target.is_some() {
+        // Only track the remote branch once the checkout went through
+        let mut local = repo
+            .find_branch(&local_name, BranchType::Local)
+            .map_err(|e| format!("Failed to find branch: {}", e))?;
+        set_missing_upstream(&mut local, remote_branch)?;
+    }
 
-        let path = entry
-            .path()
-            .map(|p| p.to_string())
-            .unwrap_or_default();
+    result
+}
 
-        // Determine staged (index) status
-        let staged_status = if status.is_index_new() {
-            Some(FileStatus::Added)
-        } else if status.is_index_deleted() {
-            Some(FileStatus::Deleted)
-        } else if status.is_index_modified() {
-            Some(FileStatus::Modified)
-        } else if status.is_index_renamed() {
-            Some(FileStatus::Renamed)
-        } else {
-            None
-        };
-
-        // Determine unstaged (workdir) status
-        let unstaged_status = if status.is_wt_new() {
-            Some(FileStatus::Untracked)
-        } else if status.is_wt_deleted() {
-            Some(FileStatus::Deleted)
-        } else if status.is_wt_modified() {
-            Some(FileStatus::Modified)
-        } else if status.is_wt_renamed() {
-            Some(FileStatus::Renamed)
-        } else {
-            None
-        };
+/// Sets a branch's upstream unless it already tracks one
+fn set_missing_upstream(local: &mut git2::Branch, remote_branch: &str) -> Result<(), String> {
+    if local.upstream().is_err() {
+        local
+            .set_upstream(Some(remote_branch))
+            .map_err(|e| format!("Failed to set upstream: {}", e))?;
+    }
+    Ok(())
+}
 
-        // Calculate line stats for staged changes (HEAD -> index)
-        let (staged_additions, staged_deletions) = if staged_status.is_some() {
-            if let Some(ref head) = head_tree {
-                let mut diff_opts = DiffOptions::new();
-                diff_opts.pathspec(&path);
+/// Checks out a commit or tag with a detached HEAD
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `revision` - Commit SHA, tag or any revision git understands
+/// * `mode` - How uncommitted local changes are handled
+///
+/// # Returns
+/// A CheckoutResult like checkout_branch, or an error message
+pub fn checkout_detached(
+    repo_path: &str,
+    revision: &str,
+    mode: CheckoutMode,
+) -> Result<CheckoutResult, String> {
+    let mut repo = open_workdir_repo(repo_path)?;
+
+    let target = repo
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| format!("Revision '{}' not found: {}", revision, e))?
+        .id();
+
+    checkout_target(&mut repo, target, None, mode)
+}
 
-                if let Ok(diff) = repo.diff_tree_to_index(Some(head), None, Some(&mut diff_opts)) {
-                    if let Ok(stats) = diff.stats() {
-                        (stats.insertions() as u32, stats.deletions() as u32)
-                    } else {
-                        (0, 0)
-                    }
-                } else {
-                    (0, 0)
-                }
-            } else {
-                (0, 0)
-            }
-        } else {
-            (0, 0)
-        };
+/// Creates a local branch
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branch_name` - Name of the new branch
+/// * `start_point` - Commit SHA, branch, tag or other revision to start from (HEAD when None)
+/// * `checkout` - Whether to switch to the new branch, carrying local changes over
+///
+/// # Returns
+/// The new Branch or an error message
+pub fn create_branch(
+    repo_path: &str,
+    branch_name: &str,
+    start_point: Option<&str>,
+    checkout: bool,
+) -> Result<Branch, String> {
+    // Creating a branch only needs history; checking it out needs a working directory
+    let mut repo = if checkout {
+        open_workdir_repo(repo_path)?
+    } else {
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?
+    };
 
-        // Calculate line stats for unstaged changes (index -> workdir)
-        let (unstaged_additions, unstaged_deletions) = if unstaged_status.is_some() {
-            let mut diff_opts = DiffOptions::new();
-            diff_opts.pathspec(&path);
-            diff_opts.include_untracked(true);
+    if !git2::Branch::name_is_valid(branch_name).unwrap_or(false) {
+        return Err(format!("'{}' is not a valid branch name", branch_name));
+    }
+    if repo.find_branch(branch_name, BranchType::Local).is_ok() {
+        return Err(format!("Branch '{}' already exists", branch_name));
+    }
 
-            if let Ok(diff) = repo.diff_index_to_workdir(None, Some(&mut diff_opts)) {
-                if let Ok(stats) = diff.stats() {
-                    (stats.insertions() as u32, stats.deletions() as u32)
-                } else {
-                    (0, 0)
-                }
-            } else {
-                (0, 0)
-            }
-        } else {
-            (0, 0)
-        };
+    let start_point = start_point.unwrap_or("HEAD");
+    let (refname, target) = {
+        let commit = repo
+            .revparse_single(start_point)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| format!("Revision '{}' not found: {}", start_point, e))?;
+
+        let refname = repo
+            .branch(branch_name, &commit, false)
+            .map_err(|e| format!("Failed to create branch: {}", e))?
+            .into_reference()
+            .name()
+            .ok_or_else(|| "Branch reference name is not valid UTF-8".to_string())?
+            .to_string();
 
-        // Create entry for staged changes if present
-        if staged_status.is_some() {
-            files.push(WorkingFile {
-                path: path.clone(),
-                staged_status,
-                unstaged_status: None,
-                staged_additions,
-                staged_deletions,
-                unstaged_additions: 0,
-                unstaged_deletions: 0,
-                old_path: None,
-                section: "staged".to_string(),
-            });
-        }
+        (refname, commit.id())
+    };
 
-        // Create entry for unstaged changes if present
-        if unstaged_status.is_some() {
-            files.push(WorkingFile {
-                path,
-                staged_status: None,
-                unstaged_status,
-                staged_additions: 0,
-                staged_deletions: 0,
-                unstaged_additions,
-                unstaged_deletions,
-                old_path: None,
-                section: "unstaged".to_string(),
-            });
+    if checkout {
+        if let Err(e) = checkout_target(&mut repo, target, Some(&refname), CheckoutMode::Safe) {
+            // Don't leave a half-done branch behind
+            if let Ok(mut branch) = repo.find_branch(branch_name, BranchType::Local) {
+                let _ = branch.delete();
+            }
+            return Err(e);
         }
     }
 
-    Ok(files)
+    let branch = repo
+        .find_branch(branch_name, BranchType::Local)
+        .map_err(|e| format!("Branch '{}' not found: {}", branch_name, e))?;
+    branch_info(&repo, &branch, false, default_branch_tip(&repo))
 }
 
-/// Gets the diff for a specific file in the working directory (vs HEAD)
+/// Renames a local branch. Renaming the current branch keeps it checked out.
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
-/// * `file_path` - Path to the file to get diff for
+/// * `branch_name` - Current name of the branch
+/// * `new_name` - New name of the branch
 ///
 /// # Returns
-/// A FileDiff struct or an error message
-pub fn get_working_file_diff(repo_path: &str, file_path: &str) -> Result<FileDiff, String> {
+/// The renamed Branch or an error message
+pub fn rename_branch(repo_path: &str, branch_name: &str, new_name: &str) -> Result<Branch, String> {
     let repo =
         Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-    // Get HEAD tree (if it exists)
-    let head_tree = match repo.head() {
-        Ok(head) => Some(
-            head.peel_to_tree()
-                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?,
-        ),
-        Err(_) => None, // No commits yet
-    };
-
-    let mut diff_opts = DiffOptions::new();
-    diff_opts.pathspec(file_path);
-    diff_opts.include_untracked(true);
-
-    let diff = repo
-        .diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut diff_opts))
-        .map_err(|e| format!("Failed to create diff: {}", e))?;
-
-    // Check if file exists in diff
-    if diff.deltas().len() == 0 {
-        return Err(format!("File '{}' has no changes", file_path));
+    if !git2::Branch::name_is_valid(new_name).unwrap_or(false) {
+        return Err(format!("'{}' is not a valid branch name", new_name));
+    }
+    if repo.find_branch(new_name, BranchType::Local).is_ok() {
+        return Err(format!("Branch '{}' already exists", new_name));
     }
 
-    let delta = diff.get_delta(0).expect("Delta should exist");
+    let mut branch = repo
+        .find_branch(branch_name, BranchType::Local)
+        .map_err(|e| format!("Branch '{}' not found: {}", branch_name, e))?;
 
-    let new_file = delta.new_file();
-    let old_file = delta.old_file();
+    let renamed = branch
+        .rename(new_name, false)
+        .map_err(|e| format!("Failed to rename branch: {}", e))?;
 
-    let new_path = new_file
-        .path()
-        .or_else(|| old_file.path())
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_default();
+    branch_info(&repo, &renamed, false, default_branch_tip(&repo))
+}
 
-    let old_path = if delta.status() == Delta::Renamed || delta.status() == Delta::Copied {
-        old_file.path().map(|p| p.to_string_lossy().to_string())
-    } else {
-        None
-    };
+/// Deletes a local branch. Unless forced, a branch whose commits aren't merged into
+/// HEAD or its upstream is kept, like `git branch -d`.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `branch_name` - Name of the branch to delete
+/// * `force` - Whether to delete the branch even if it has unmerged commits
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn delete_branch(repo_path: &str, branch_name: &str, force: bool) -> Result<(), String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-    // Check if binary
-    let is_binary = new_file.is_binary() || old_file.is_binary();
+    let mut branch = repo
+        .find_branch(branch_name, BranchType::Local)
+        .map_err(|e| format!("Branch '{}' not found: {}", branch_name, e))?;
 
-    if is_binary {
-        return Ok(FileDiff {
-            old_path,
-            new_path,
-            hunks: Vec::new(),
-            is_binary: true,
-        });
+    if branch.is_head() {
+        return Err(format!(
+            "Cannot delete branch '{}': it is currently checked out",
+            branch_name
+        ));
     }
 
-    // Get patch for detailed diff
-    let patch = git2::Patch::from_diff(&diff, 0)
-        .map_err(|e| format!("Failed to create patch: {}", e))?
-        .ok_or_else(|| "Failed to create patch for file".to_string())?;
-
-    let mut hunks = Vec::new();
-
-    for hunk_idx in 0..patch.num_hunks() {
-        let (hunk, _) = patch
-            .hunk(hunk_idx)
-            .map_err(|e| format!("Failed to get hunk: {}", e))?;
-
-        let mut lines = Vec::new();
-
-        for line_idx in 0..patch.num_lines_in_hunk(hunk_idx).unwrap_or(0) {
-            let line = patch
-                .line_in_hunk(hunk_idx, line_idx)
-                .map_err(|e| format!("Failed to get line: {}", e))?;
+    if !force {
+        let tip = branch
+            .get()
+            .peel_to_commit()
+            .map_err(|e| format!("Failed to get branch commit: {}", e))?
+            .id();
 
-            let line_type = match line.origin() {
-                '+' => LineType::Addition,
-                '-' => LineType::Deletion,
-                _ => LineType::Context,
-            };
+        // Merged when reachable from its upstream, or from HEAD without one
+        let base = match branch.upstream() {
+            Ok(upstream) => upstream.get().peel_to_commit().ok(),
+            Err(_) => repo.head().and_then(|head| head.peel_to_commit()).ok(),
+        };
 
-            let content = String::from_utf8_lossy(line.content()).to_string();
+        let merged = match base {
+            Some(base) => {
+                base.id() == tip
+                    || repo
+                        .graph_descendant_of(base.id(), tip)
+                        .map_err(|e| format!("Failed to compare branches: {}", e))?
+            }
+            None => false,
+        };
 
-            lines.push(DiffLine {
-                content,
-                line_type,
-                old_line_no: line.old_lineno(),
-                new_line_no: line.new_lineno(),
-            });
+        if !merged {
+            return Err(format!(
+                "Branch '{}' is not fully merged, force the deletion to remove it anyway",
+                branch_name
+            ));
         }
-
-        hunks.push(DiffHunk {
-            old_start: hunk.old_start(),
-            old_lines: hunk.old_lines(),
-            new_start: hunk.new_start(),
-            new_lines: hunk.new_lines(),
-            lines,
-        });
     }
 
-    Ok(FileDiff {
-        old_path,
-        new_path,
-        hunks,
-        is_binary: false,
-    })
+    branch
+        .delete()
+        .map_err(|e| format!("Failed to delete branch: {}", e))
 }
 
-/// Gets the diff for staged changes of a file (index vs HEAD)
+/// Sets or unsets the upstream of a local branch
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
-/// * `file_path` - Path to the file to get diff for
+/// * `branch_name` - Name of the local branch
+/// * `upstream` - Remote branch (e.g. "origin/main") or local branch to track, None to unset
 ///
 /// # Returns
-/// A FileDiff struct showing staged changes, or an error if file has no staged changes
-pub fn get_staged_file_diff(repo_path: &str, file_path: &str) -> Result<FileDiff, String> {
+/// Ok(()) on success, or an error message
+pub fn set_branch_upstream(
+    repo_path: &str,
+    branch_name: &str,
+    upstream: Option<&str>,
+) -> Result<(), String> {
     let repo =
         Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-    // Get HEAD tree (if it exists)
-    let head_tree = match repo.head() {
-        Ok(head) => Some(
-            head.peel_to_tree()
-                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?,
-        ),
-        Err(_) => None, // No commits yet
-    };
+    let mut branch = repo
+        .find_branch(branch_name, BranchType::Local)
+        .map_err(|e| format!("Branch '{}' not found: {}", branch_name, e))?;
 
-    let mut diff_opts = DiffOptions::new();
-    diff_opts.pathspec(file_path);
+    branch
+        .set_upstream(upstream)
+        .map_err(|e| format!("Failed to set upstream: {}", e))
+}
 
-    // Diff HEAD -> index (staged changes only)
-    let diff = repo
-        .diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_opts))
-        .map_err(|e| format!("Failed to create diff: {}", e))?;
+/// Represents a git tag
+#[derive(Debug, Clone, Serialize)]
+pub struct Tag {
+    /// Name of the tag
+    pub name: String,
+    /// SHA of the commit the tag points to
+    pub commit_id: String,
+    /// Whether this is an annotated tag (as opposed to a lightweight one)
+    pub is_annotated: bool,
+    /// Name of the tagger, for annotated tags
+    pub tagger: Option<String>,
+    /// Email of the tagger, for annotated tags
+    pub email: Option<String>,
+    /// Tag message, for annotated tags
+    pub message: Option<String>,
+    /// Unix timestamp of the tag for annotated tags, or of the commit for lightweight ones
+    pub timestamp: i64,
+}
 
-    // Check if file exists in diff
-    if diff.deltas().len() == 0 {
-        return Err(format!("File '{}' has no staged changes", file_path));
-    }
-
-    let delta = diff.get_delta(0).expect("Delta should exist");
-
-    // Check if file actually has changes (not just in index with no diff)
-    if delta.status() == Delta::Unmodified {
-        return Err(format!("File '{}' has no staged changes", file_path));
-    }
+/// Lists the tags pointing to commits, most recent first
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A vector of Tag structs or an error message
+pub fn list_tags(repo_path: &str) -> Result<Vec<Tag>, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-    let new_file = delta.new_file();
-    let old_file = delta.old_file();
+    let references = repo
+        .references_glob("refs/tags/*")
+        .map_err(|e| format!("Failed to list tags: {}", e))?;
 
-    let new_path = new_file
-        .path()
-        .or_else(|| old_file.path())
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_default();
+    let mut tags = Vec::new();
+    for reference in references {
+        let reference = reference.map_err(|e| format!("Failed to get tag: {}", e))?;
 
-    let old_path = if delta.status() == Delta::Renamed || delta.status() == Delta::Copied {
-        old_file.path().map(|p| p.to_string_lossy().to_string())
-    } else {
-        None
-    };
+        let Some(name) = reference.shorthand().map(|n| n.to_string()) else {
+            continue;
+        };
+        // Tags of trees or blobs have no place in the history
+        let Ok(commit) = reference.peel_to_commit() else {
+            continue;
+        };
 
-    // Check if binary
-    let is_binary = new_file.is_binary() || old_file.is_binary();
+        let annotation = reference.peel_to_tag().ok();
+        let tagger = annotation.as_ref().and_then(|tag| tag.tagger());
 
-    if is_binary {
-        return Ok(FileDiff {
-            old_path,
-            new_path,
-            hunks: Vec::new(),
-            is_binary: true,
+        tags.push(Tag {
+            name,
+            commit_id: commit.id().to_string(),
+            is_annotated: annotation.is_some(),
+            tagger: tagger.as_ref().map(|t| t.name().unwrap_or("").to_string()),
+            email: tagger.as_ref().map(|t| t.email().unwrap_or("").to_string()),
+            message: annotation
+                .as_ref()
+                .and_then(|tag| tag.message())
+                .map(|m| m.trim_end().to_string()),
+            timestamp: tagger
+                .as_ref()
+                .map(|t| t.when().seconds())
+                .unwrap_or_else(|| commit.time().seconds()),
         });
     }
 
-    // Get patch for detailed diff
-    let patch = git2::Patch::from_diff(&diff, 0)
-        .map_err(|e| format!("Failed to create patch: {}", e))?
-        .ok_or_else(|| "Failed to create patch for file".to_string())?;
-
-    let mut hunks = Vec::new();
-
-    for hunk_idx in 0..patch.num_hunks() {
-        let (hunk, _) = patch
-            .hunk(hunk_idx)
-            .map_err(|e| format!("Failed to get hunk: {}", e))?;
-
-        let mut lines = Vec::new();
+    tags.sort_by(|a, b| {
+        b.timestamp
+            .cmp(&a.timestamp)
+            .then_with(|| a.name.cmp(&b.name))
+    });
 
-        for line_idx in 0..patch.num_lines_in_hunk(hunk_idx).unwrap_or(0) {
-            let line = patch
-                .line_in_hunk(hunk_idx, line_idx)
-                .map_err(|e| format!("Failed to get line: {}", e))?;
+    Ok(tags)
+}
 
-            let line_type = match line.origin() {
-                '+' => LineType::Addition,
-                '-' => LineType::Deletion,
-                _ => LineType::Context,
-            };
+/// Creates a tag on a commit. The tag is annotated when a message is given,
+/// and lightweight otherwise.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `tag_name` - Name of the new tag
+/// * `target` - Commit SHA, branch or other revision to tag (HEAD when None)
+/// * `message` - Message of an annotated tag, None for a lightweight tag
+///
+/// # Returns
+/// The new Tag or an error message
+pub fn create_tag(
+    repo_path: &str,
+    tag_name: &str,
+    target: Option<&str>,
+    message: Option<&str>,
+) -> Result<Tag, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-            let content = String::from_utf8_lossy(line.content()).to_string();
+    if !git2::Reference::is_valid_name(&format!("refs/tags/{}", tag_name)) {
+        return Err(format!("'{}' is not a valid tag name", tag_name));
+    }
+    if repo
+        .find_reference(&format!("refs/tags/{}", tag_name))
+        .is_ok()
+    {
+        return Err(format!("Tag '{}' already exists", tag_name));
+    }
 
-            lines.push(DiffLine {
-                content,
-                line_type,
-                old_line_no: line.old_lineno(),
-                new_line_no: line.new_lineno(),
-            });
+    let target = target.unwrap_or("HEAD");
+    let commit = repo
+        .revparse_single(target)
+        .and_then(|object| object.peel(git2::ObjectType::Commit))
+        .map_err(|e| format!("Revision '{}' not found: {}", target, e))?;
+
+    let message = message.map(str::trim).filter(|m| !m.is_empty());
+    match message {
+        Some(message) => {
+            let tagger = repo
+                .signature()
+                .map_err(|e| format!("Failed to get signature: {}", e))?;
+            let message = git2::message_prettify(message, None)
+                .map_err(|e| format!("Failed to format tag message: {}", e))?;
+            repo.tag(tag_name, &commit, &tagger, &message, false)
         }
-
-        hunks.push(DiffHunk {
-            old_start: hunk.old_start(),
-            old_lines: hunk.old_lines(),
-            new_start: hunk.new_start(),
-            new_lines: hunk.new_lines(),
-            lines,
-        });
+        None => repo.tag_lightweight(tag_name, &commit, false),
     }
+    .map_err(|e| format!("Failed to create tag: {}", e))?;
 
-    Ok(FileDiff {
-        old_path,
-        new_path,
-        hunks,
-        is_binary: false,
-    })
+    list_tags(repo_path)?
+        .into_iter()
+        .find(|tag| tag.name == tag_name)
+        .ok_or_else(|| format!("Tag '{}' not found", tag_name))
 }
 
-/// Gets the diff for unstaged changes of a file (workdir vs index)
-/// Falls back to workdir vs HEAD if file has no staged changes
+/// Deletes a tag
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
-/// * `file_path` - Path to the file to get diff for
+/// * `tag_name` - Name of the tag to delete
 ///
 /// # Returns
-/// A FileDiff struct showing unstaged changes
-pub fn get_unstaged_file_diff(repo_path: &str, file_path: &str) -> Result<FileDiff, String> {
+/// Ok(()) on success, or an error message
+pub fn delete_tag(repo_path: &str, tag_name: &str) -> Result<(), String> {
     let repo =
         Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-    let mut diff_opts = DiffOptions::new();
-    diff_opts.pathspec(file_path);
-    diff_opts.include_untracked(true);
+    if repo
+        .find_reference(&format!("refs/tags/{}", tag_name))
+        .is_err()
+    {
+        return Err(format!("Tag '{}' not found", tag_name));
+    }
 
-    // Diff index -> workdir (unstaged changes)
-    let diff = repo
-        .diff_index_to_workdir(None, Some(&mut diff_opts))
-        .map_err(|e| format!("Failed to create diff: {}", e))?;
+    repo.tag_delete(tag_name)
+        .map_err(|e| format!("Failed to delete tag: {}", e))
+}
 
-    // Check if file exists in diff
-    if diff.deltas().len() == 0 {
-        return Err(format!("File '{}' has no unstaged changes", file_path));
-    }
+/// Validates that a path is a git repository and returns info about it.
+/// Bare repositories and mirrors are accepted in a history-only mode: commits,
+/// branches, tags and comparisons work, but the Changes view is not available.
+///
+/// # Arguments
+/// * `path` - Path to validate
+///
+/// # Returns
+/// A RepoInfo struct or an error message
+pub fn validate_repo(path: &str) -> Result<RepoInfo, String> {
+    let repo = Repository::open(path).map_err(|e| format!("Not a valid git repository: {}", e))?;
+    let is_bare = repo.is_bare();
 
-    let delta = diff.get_delta(0).expect("Delta should exist");
+    // Get repository root path (the git directory itself for bare repositories)
+    let root = repo.workdir().unwrap_or_else(|| repo.path());
+    let repo_path = root.to_string_lossy().to_string();
 
-    let new_file = delta.new_file();
-    let old_file = delta.old_file();
+    // Get directory name, without the ".git" suffix bare repositories usually have
+    let dir_name = root
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown");
+    let name = match dir_name.strip_suffix(".git") {
+        Some(stripped) if is_bare && !stripped.is_empty() => stripped,
+        // A bare repository named ".git" is named after its parent
+        Some(_) if is_bare => root
+            .parent()
+            .and_then(|p| p.file_name())
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown"),
+        _ => dir_name,
+    }
+    .to_string();
 
-    let new_path = new_file
-        .path()
-        .or_else(|| old_file.path())
-        .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_default();
+    // Get current branch
+    let branch = get_current_branch(path)?;
 
-    let old_path = if delta.status() == Delta::Renamed || delta.status() == Delta::Copied {
-        old_file.path().map(|p| p.to_string_lossy().to_string())
+    // Linked worktrees are their own entry, but share history with the main one
+    let is_worktree = repo.is_worktree();
+    let main_repo_path = if is_worktree {
+        main_workdir(&repo).map(|dir| dir.to_string_lossy().to_string())
     } else {
         None
     };
 
-    // Check if binary
-    let is_binary = new_file.is_binary() || old_file.is_binary();
+    Ok(RepoInfo {
+        path: repo_path,
+        name,
+        branch,
+        is_worktree,
+        main_repo_path,
+        is_bare,
+    })
+}
 
-    if is_binary {
-        return Ok(FileDiff {
-            old_path,
-            new_path,
-            hunks: Vec::new(),
-            is_binary: true,
-        });
-    }
+/// Opens a repository for an operation on its working directory or index.
+/// Bare repositories are refused with an error the Changes view can show.
+fn open_workdir_repo(repo_path: &str) -> Result<Repository, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
 
-    // Get patch for detailed diff
-    let patch = git2::Patch::from_diff(&diff, 0)
-        .map_err(|e| format!("Failed to create patch: {}", e))?
-        .ok_or_else(|| "Failed to create patch for file".to_string())?;
+    if repo.is_bare() {
+        return Err("Not available in a bare repository: it has no working directory".to_string());
+    }
 
-    let mut hunks = Vec::new();
+    Ok(repo)
+}
 
-    for hunk_idx in 0..patch.num_hunks() {
-        let (hunk, _) = patch
-            .hunk(hunk_idx)
-            .map_err(|e| format!("Failed to get hunk: {}", e))?;
+/// Gets the working directory of the main worktree (None if the main repository is bare)
+fn main_workdir(repo: &Repository) -> Option<std::path::PathBuf> {
+    let main = Repository::open(common_dir(repo)).ok()?;
+    main.workdir().map(|dir| dir.to_path_buf())
+}
 
-        let mut lines = Vec::new();
+/// Reads the HEAD file of a worktree's git directory
+///
+/// # Returns
+/// The checked out branch name (None when detached) and the commit it points to
+fn worktree_head(repo: &Repository, git_dir: &std::path::Path) -> (Option<String>, Option<String>) {
+    let content = std::fs::read_to_string(git_dir.join("HEAD")).unwrap_or_default();
+    let content = content.trim();
+
+    match content.strip_prefix("ref:") {
+        Some(refname) => {
+            let refname = refname.trim();
+            let branch = refname
+                .strip_prefix("refs/heads/")
+                .unwrap_or(refname)
+                .to_string();
+            let head = repo.refname_to_id(refname).ok().map(|oid| oid.to_string());
+            (Some(branch), head)
+        }
+        None => (
+            None,
+            git2::Oid::from_str(content).ok().map(|oid| oid.to_string()),
+        ),
+    }
+}
 
-        for line_idx in 0..patch.num_lines_in_hunk(hunk_idx).unwrap_or(0) {
-            let line = patch
-                .line_in_hunk(hunk_idx, line_idx)
-                .map_err(|e| format!("Failed to get line: {}", e))?;
+/// Gets whether a worktree is locked, and the reason given when locking it
+fn worktree_lock(worktree: &git2::Worktree) -> (bool, Option<String>) {
+    match worktree.is_locked() {
+        Ok(git2::WorktreeLockStatus::Locked(reason)) => (
+            true,
+            reason
+                .map(|r| r.trim().to_string())
+                .filter(|r| !r.is_empty()),
+        ),
+        _ => (false, None),
+    }
+}
 
-            let line_type = match line.origin() {
-                '+' => LineType::Addition,
-                '-' => LineType::Deletion,
-                _ => LineType::Context,
-            };
+/// Checks whether two paths point to the same directory
+fn same_dir(a: &std::path::Path, b: &std::path::Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
 
-            let content = String::from_utf8_lossy(line.content()).to_string();
+/// Lists the main working directory and every linked worktree of a repository
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository (any of its worktrees)
+///
+/// # Returns
+/// The worktrees, main one first, or an error message
+pub fn list_worktrees(repo_path: &str) -> Result<Vec<Worktree>, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+    let common = common_dir(&repo);
+    let current = repo.workdir().map(|dir| dir.to_path_buf());
+    let is_current = |path: &std::path::Path| {
+        current
+            .as_deref()
+            .is_some_and(|current| same_dir(current, path))
+    };
 
-            lines.push(DiffLine {
-                content,
-                line_type,
-                old_line_no: line.old_lineno(),
-                new_line_no: line.new_lineno(),
-            });
-        }
+    let mut worktrees = Vec::new();
+
+    if let Some(workdir) = main_workdir(&repo) {
+        let (branch, head) = worktree_head(&repo, &common);
+        worktrees.push(Worktree {
+            name: workdir
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path: workdir.to_string_lossy().to_string(),
+            branch,
+            head,
+            is_main: true,
+            is_current: is_current(&workdir),
+            is_locked: false,
+            lock_reason: None,
+            is_prunable: false,
+        });
+    }
 
-        hunks.push(DiffHunk {
-            old_start: hunk.old_start(),
-            old_lines: hunk.old_lines(),
-            new_start: hunk.new_start(),
-            new_lines: hunk.new_lines(),
-            lines,
+    let names = repo
+        .worktrees()
+        .map_err(|e| format!("Failed to list worktrees: {}", e))?;
+    for name in names.iter().flatten() {
+        let worktree = repo
+            .find_worktree(name)
+            .map_err(|e| format!("Failed to open worktree '{}': {}", name, e))?;
+        let (is_locked, lock_reason) = worktree_lock(&worktree);
+        let (branch, head) = worktree_head(&repo, &common.join("worktrees").join(name));
+        // Trailing separator matches the workdir paths reported by validate_repo
+        let path = worktree.path().join("");
+
+        worktrees.push(Worktree {
+            name: name.to_string(),
+            path: path.to_string_lossy().to_string(),
+            branch,
+            head,
+            is_main: false,
+            is_current: is_current(&path),
+            is_locked,
+            lock_reason,
+            is_prunable: worktree.validate().is_err(),
         });
     }
 
-    Ok(FileDiff {
-        old_path,
-        new_path,
-        hunks,
-        is_binary: false,
-    })
+    Ok(worktrees)
 }
 
-/// Gets the full file contents for a working directory change (vs HEAD)
+/// Creates a linked worktree with a branch checked out. Like `git worktree add`,
+/// a branch can only be checked out in one worktree at a time.
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
-/// * `file_path` - Path to the file to get contents for
+/// * `path` - Directory to create the worktree in (must not exist or be empty)
+/// * `branch` - Local branch to check out
+/// * `create_branch` - Whether to create the branch from HEAD first
 ///
 /// # Returns
-/// A FileContents struct with old (HEAD) and new (working dir) content
-pub fn get_working_file_contents(repo_path: &str, file_path: &str) -> Result<FileContents, String> {
+/// Info about the new worktree, to add as its own repository entry
+pub fn add_worktree(
+    repo_path: &str,
+    path: &str,
+    branch: &str,
+    create_branch: bool,
+) -> Result<RepoInfo, String> {
     let repo =
         Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+    let target = std::path::Path::new(path);
 
-    // Get old content from HEAD (if it exists)
-    let old_content = match repo.head() {
-        Ok(head) => {
-            let tree = head
-                .peel_to_tree()
-                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+    let is_empty_dir = std::fs::read_dir(target)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(false);
+    if target.exists() && !is_empty_dir {
+        return Err(format!("'{}' already exists", path));
+    }
 
-            match tree.get_path(std::path::Path::new(file_path)) {
-                Ok(entry) => {
-                    let object = entry
-                        .to_object(&repo)
-                        .map_err(|e| format!("Failed to get object: {}", e))?;
+    if let Some(existing) = list_worktrees(repo_path)?
+        .into_iter()
+        .find(|wt| wt.branch.as_deref() == Some(branch))
+    {
+        return Err(format!(
+            "Branch '{}' is already checked out at {}",
+            branch, existing.path
+        ));
+    }
 
-                    if let Some(blob) = object.as_blob() {
-                        if blob.is_binary() {
-                            return Ok(FileContents {
-                                old_content: None,
-                                new_content: None,
-                                is_binary: true,
-                            });
-                        }
-                        match std::str::from_utf8(blob.content()) {
-                            Ok(s) => Some(s.to_string()),
-                            Err(_) => {
-                                return Err("File is not valid UTF-8".to_string());
-                            }
-                        }
-                    } else {
-                        return Err("Not a blob".to_string());
-                    }
-                }
-                Err(_) => None, // File doesn't exist in HEAD (new file)
-            }
-        }
-        Err(_) => None, // No HEAD (empty repo)
-    };
-
-    // Get new content from working directory
-    let workdir = repo
-        .workdir()
-        .ok_or_else(|| "Repository has no working directory".to_string())?;
-
-    let file_full_path = workdir.join(file_path);
-
-    let new_content = if file_full_path.exists() {
-        let content = std::fs::read(&file_full_path)
-            .map_err(|e| format!("Failed to read file: {}", e))?;
-
-        // Check if binary (contains null bytes in first 8000 bytes)
-        let check_len = std::cmp::min(content.len(), 8000);
-        if content[..check_len].contains(&0) {
-            return Ok(FileContents {
-                old_content: None,
-                new_content: None,
-                is_binary: true,
-            });
-        }
-
-        match String::from_utf8(content) {
-            Ok(s) => Some(s),
-            Err(_) => return Err("File is not valid UTF-8".to_string()),
-        }
+    // Name the worktree after its directory, avoiding names already in use
+    let base = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .filter(|n| !n.is_empty())
+        .ok_or_else(|| format!("Invalid worktree path '{}'", path))?;
+    let worktrees_dir = common_dir(&repo).join("worktrees");
+    let name = (1..)
+        .map(|i| {
+            if i == 1 {
+                base.clone()
+            } else {
+                format!("{}-{}", base, i)
+            }
+        })
+        .find(|name| !worktrees_dir.join(name).exists())
+        .unwrap_or(base);
+
+    let branch_ref = if create_branch {
+        let head = repo
+            .head()
+            .and_then(|h| h.peel_to_commit())
+            .map_err(|e| format!("Failed to get HEAD commit: {}", e))?;
+        repo.branch(branch, &head, false)
+            .map_err(|e| format!("Failed to create branch: {}", e))?
     } else {
-        None // File was deleted
+        repo.find_branch(branch, BranchType::Local)
+            .map_err(|_| format!("Branch '{}' not found", branch))?
     };
 
-    // Verify there's actually a change
-    if old_content.is_none() && new_content.is_none() {
-        return Err(format!("File '{}' not found", file_path));
+    let mut options = git2::WorktreeAddOptions::new();
+    options.reference(Some(branch_ref.get()));
+    if let Err(e) = repo.worktree(&name, target, Some(&options)) {
+        // Don't leave the branch we just created behind
+        if create_branch {
+            let mut branch_ref = branch_ref;
+            let _ = branch_ref.delete();
+        }
+        return Err(format!("Failed to add worktree: {}", e));
     }
 
-    Ok(FileContents {
-        old_content,
-        new_content,
-        is_binary: false,
-    })
+    validate_repo(path)
 }
 
-/// Gets the file contents for a staged file (HEAD vs index/staging area)
+/// Removes a linked worktree and deletes its working directory. Without `force`,
+/// locked worktrees and worktrees with uncommitted changes are refused.
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
-/// * `file_path` - Path to the file to get contents for
+/// * `name` - Name of the worktree, as reported by list_worktrees
+/// * `force` - Whether to remove it even if locked or dirty
 ///
 /// # Returns
-/// A FileContents struct with old (HEAD) and new (staged/index) content
-pub fn get_staged_file_contents(repo_path: &str, file_path: &str) -> Result<FileContents, String> {
+/// Ok(()) on success, or an error message
+pub fn remove_worktree(repo_path: &str, name: &str, force: bool) -> Result<(), String> {
     let repo =
         Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+    let worktree = repo
+        .find_worktree(name)
+        .map_err(|_| format!("Worktree '{}' not found", name))?;
 
-    // Get old content from HEAD (if it exists)
-    let old_content = match repo.head() {
-        Ok(head) => {
-            let tree = head
-                .peel_to_tree()
-                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
-
-            match tree.get_path(std::path::Path::new(file_path)) {
-                Ok(entry) => {
-                    let object = entry
-                        .to_object(&repo)
-                        .map_err(|e| format!("Failed to get object: {}", e))?;
+    if repo
+        .workdir()
+        .is_some_and(|dir| same_dir(dir, worktree.path()))
+    {
+        return Err("Cannot remove the worktree that is currently open".to_string());
+    }
 
-                    if let Some(blob) = object.as_blob() {
-                        if blob.is_binary() {
-                            return Ok(FileContents {
-                                old_content: None,
-                                new_content: None,
-                                is_binary: true,
-                            });
-                        }
-                        match std::str::from_utf8(blob.content()) {
-                            Ok(s) => Some(s.to_string()),
-                            Err(_) => {
-                                return Err("File is not valid UTF-8".to_string());
-                            }
-                        }
-                    } else {
-                        return Err("Not a blob".to_string());
-                    }
-                }
-                Err(_) => None, // File doesn't exist in HEAD (new file)
-            }
+    if !force {
+        if let (true, reason) = worktree_lock(&worktree) {
+            return Err(match reason {
+                Some(reason) => format!("Worktree '{}' is locked: {}", name, reason),
+                None => format!("Worktree '{}' is locked", name),
+            });
         }
-        Err(_) => None, // No HEAD (empty repo)
-    };
 
-    // Get new content from index (staged)
-    let new_content = match repo.index() {
-        Ok(index) => {
-            match index.get_path(std::path::Path::new(file_path), 0) {
-                Some(entry) => {
-                    match repo.find_blob(entry.id) {
-                        Ok(blob) => {
-                            if blob.is_binary() {
-                                return Ok(FileContents {
-                                    old_content: None,
-                                    new_content: None,
-                                    is_binary: true,
-                                });
-                            }
-                            match std::str::from_utf8(blob.content()) {
-                                Ok(s) => Some(s.to_string()),
-                                Err(_) => return Err("File is not valid UTF-8".to_string()),
-                            }
-                        }
-                        Err(_) => None,
-                    }
-                }
-                None => None, // File not in index
+        if worktree.validate().is_ok() {
+            let wt_repo = Repository::open_from_worktree(&worktree)
+                .map_err(|e| format!("Failed to open worktree: {}", e))?;
+            let mut opts = git2::StatusOptions::new();
+            opts.include_untracked(true);
+            let statuses = wt_repo
+                .statuses(Some(&mut opts))
+                .map_err(|e| format!("Failed to get statuses: {}", e))?;
+            if !statuses.is_empty() {
+                return Err(format!("Worktree '{}' has uncommitted changes", name));
             }
         }
-        Err(_) => None,
-    };
-
-    // Verify there's actually a change
-    if old_content.is_none() && new_content.is_none() {
-        return Err(format!("File '{}' not found in HEAD or index", file_path));
     }
 
-    Ok(FileContents {
-        old_content,
-        new_content,
-        is_binary: false,
-    })
+    let mut options = git2::WorktreePruneOptions::new();
+    options.valid(true).locked(force).working_tree(true);
+    worktree
+        .prune(Some(&mut options))
+        .map_err(|e| format!("Failed to remove worktree: {}", e))
 }
 
-/// Gets the file contents for an unstaged file (index vs working directory)
+/// Prunes worktrees whose working directories have been deleted, like
+/// `git worktree prune`. Locked worktrees are kept.
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
-/// * `file_path` - Path to the file to get contents for
 ///
 /// # Returns
-/// A FileContents struct with old (index/staged) and new (working dir) content
-pub fn get_unstaged_file_contents(repo_path: &str, file_path: &str) -> Result<FileContents, String> {
+/// The names of the pruned worktrees
+pub fn prune_worktrees(repo_path: &str) -> Result<Vec<String>, String> {
     let repo =
         Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
-
-    // Get old content from index (staged)
-    let old_content = match repo.index() {
-        Ok(index) => {
-            match index.get_path(std::path::Path::new(file_path), 0) {
-                Some(entry) => {
-                    match repo.find_blob(entry.id) {
-                        Ok(blob) => {
-                            if blob.is_binary() {
-                                return Ok(FileContents {
-                                    old_content: None,
-                                    new_content: None,
-                                    is_binary: true,
-                                });
-                            }
-                            match std::str::from_utf8(blob.content()) {
-                                Ok(s) => Some(s.to_string()),
-                                Err(_) => return Err("File is not valid UTF-8".to_string()),
-                            }
-                        }
-                        Err(_) => None,
-                    }
-                }
-                None => None, // File not in index (check HEAD as fallback)
-            }
-        }
-        Err(_) => None,
-    };
-
-    // If not in index, try HEAD (file not staged)
-    let old_content = if old_content.is_none() {
-        match repo.head() {
-            Ok(head) => {
-                let tree = head.peel_to_tree()
-                    .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
-                
-                match tree.get_path(std::path::Path::new(file_path)) {
-                    Ok(entry) => {
-                        let object = entry.to_object(&repo)
-                            .map_err(|e| format!("Failed to get object: {}", e))?;
-                        
-                        if let Some(blob) = object.as_blob() {
-                            if blob.is_binary() {
-                                return Ok(FileContents {
-                                    old_content: None,
-                                    new_content: None,
-                                    is_binary: true,
-                                });
-                            }
-                            match std::str::from_utf8(blob.content()) {
-                                Ok(s) => Some(s.to_string()),
-                                Err(_) => return Err("File is not valid UTF-8".to_string()),
-                            }
-                        } else {
-                            None
-                        }
-                    }
-                    Err(_) => None,
-                }
-            }
-            Err(_) => None,
-        }
-    } else {
-        old_content
-    };
-
-    // Get new content from working directory
-    let workdir = repo
-        .workdir()
-        .ok_or_else(|| "Repository has no working directory".to_string())?;
-
-    let file_full_path = workdir.join(file_path);
-
-    let new_content = if file_full_path.exists() {
-        let content = std::fs::read(&file_full_path)
-            .map_err(|e| format!("Failed to read file: {}", e))?;
-
-        // Check if binary (contains null bytes in first 8000 bytes)
-        let check_len = std::cmp::min(content.len(), 8000);
-        if content[..check_len].contains(&0) {
-            return Ok(FileContents {
-                old_content: None,
-                new_content: None,
-                is_binary: true,
-            });
-        }
-
-        match String::from_utf8(content) {
-            Ok(s) => Some(s),
-            Err(_) => return Err("File is not valid UTF-8".to_string()),
+    let names = repo
+        .worktrees()
+        .map_err(|e| format!("Failed to list worktrees: {}", e))?;
+
+    let mut pruned = Vec::new();
+    for name in names.iter().flatten() {
+        let worktree = repo
+            .find_worktree(name)
+            .map_err(|e| format!("Failed to open worktree '{}': {}", name, e))?;
+        if worktree.is_prunable(None).unwrap_or(false) {
+            worktree
+                .prune(None)
+                .map_err(|e| format!("Failed to prune worktree '{}': {}", name, e))?;
+            pruned.push(name.to_string());
         }
-    } else {
-        None // File was deleted
-    };
-
-    // Verify there's actually a change
-    if old_content.is_none() && new_content.is_none() {
-        return Err(format!("File '{}' not found", file_path));
     }
 
-    Ok(FileContents {
-        old_content,
-        new_content,
-        is_binary: false,
-    })
+    Ok(pruned)
 }
 
-/// Unstages a file by resetting its index entry to match HEAD.
+/// Gets the list of files changed in the working directory (uncommitted changes)
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
-/// * `file_path` - Relative path to the file within the repository
 ///
 /// # Returns
-/// Ok(()) on success, or an error message
-pub fn unstage_file(repo_path: &str, file_path: &str) -> Result<(), String> {
-    let repo =
-        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+/// A vector of ChangedFile structs representing working directory changes
+pub fn get_working_changes(repo_path: &str) -> Result<Vec<ChangedFile>, String> {
+    let repo = open_workdir_repo(repo_path)?;
 
-    let mut index = repo
-        .index()
-        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let statuses = repo
+        .statuses(None)
+        .map_err(|e| format!("Failed to get statuses: {}", e))?;
 
-    // Get HEAD tree to reset the file to
-    let head = repo.head().map_err(|e| format!("Failed to get HEAD: {}", e))?;
-    let head_commit = head
-        .peel_to_commit()
-        .map_err(|e| format!("Failed to get HEAD commit: {}", e))?;
-    let head_tree = head_commit
-        .tree()
-        .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+    let mut files: Vec<ChangedFile> = Vec::new();
 
-    // Check if file exists in HEAD
-    let path = std::path::Path::new(file_path);
-    match head_tree.get_path(path) {
-        Ok(entry) => {
-            // File exists in HEAD - restore index entry to HEAD version
-            let blob = repo
-                .find_blob(entry.id())
-                .map_err(|e| format!("Failed to find blob: {}", e))?;
-
-            let index_entry = git2::IndexEntry {
-                ctime: git2::IndexTime::new(0, 0),
-                mtime: git2::IndexTime::new(0, 0),
-                dev: 0,
-                ino: 0,
-                mode: entry.filemode() as u32,
-                uid: 0,
-                gid: 0,
-                file_size: blob.size() as u32,
-                id: entry.id(),
-                flags: 0,
-                flags_extended: 0,
-                path: file_path.as_bytes().to_vec(),
-            };
+    for entry in statuses.iter() {
+        let status = entry.status();
 
-            index
-                .add(&index_entry)
-                .map_err(|e| format!("Failed to reset index entry: {}", e))?;
+        // Skip files that are unchanged
+        if status.is_empty() {
+            continue;
         }
-        Err(_) => {
-            // File doesn't exist in HEAD - remove from index (was a new file)
-            index
-                .remove_path(path)
-                .map_err(|e| format!("Failed to remove from index: {}", e))?;
+
+        let path = entry
+            .path()
+            .map(|p| p.to_string())
+            .unwrap_or_default();
+
+        // Determine the file status
+        // We combine index (staged) and worktree (unstaged) status since we're showing all changes
+        let file_status = if status.is_wt_new() || status.is_index_new() {
+            if status.is_wt_new() && !status.is_index_new() {
+                FileStatus::Untracked
+            } else {
+                FileStatus::Added
+            }
+        } else if status.is_wt_deleted() || status.is_index_deleted() {
+            FileStatus::Deleted
+        } else if status.is_wt_modified() || status.is_index_modified() {
+            FileStatus::Modified
+        } else if status.is_wt_renamed() || status.is_index_renamed() {
+            FileStatus::Renamed
+        } else {
+            continue; // Skip other statuses (ignored, etc.)
+        };
+
+        // Get line stats by creating a diff
+        let mut additions = 0u32;
+        let mut deletions = 0u32;
+
+        // Try to get line stats from diff to HEAD
+        if let Ok(head) = repo.head() {
+            if let Ok(head_commit) = head.peel_to_commit() {
+                if let Ok(head_tree) = head_commit.tree() {
+                    let mut diff_opts = DiffOptions::new();
+                    diff_opts.pathspec(&path);
+                    diff_opts.include_untracked(true);
+
+                    if let Ok(diff) = repo.diff_tree_to_workdir_with_index(
+                        Some(&head_tree),
+                        Some(&mut diff_opts),
+                    ) {
+                        if let Ok(stats) = diff.stats() {
+                            additions = stats.insertions() as u32;
+                            deletions = stats.deletions() as u32;
+                        }
+                    }
+                }
+            }
         }
-    }
 
-    index
-        .write()
-        .map_err(|e| format!("Failed to write index: {}", e))?;
+        files.push(ChangedFile {
+            path,
+            status: file_status,
+            additions,
+            deletions,
+            old_path: None, // TODO: Handle renames if needed
+        });
+    }
 
-    Ok(())
+    Ok(files)
 }
 
-/// Discards changes to a file in the working directory.
-/// For tracked files: restores the file content from the index (or HEAD if not staged).
-/// For untracked files: deletes the file from the working directory.
+/// Gets the list of files changed in the working directory, split by staged/unstaged
 ///
 /// # Arguments
 /// * `repo_path` - Path to the git repository
-/// * `file_path` - Relative path to the file within the repository
 ///
 /// # Returns
-/// Ok(()) on success, or an error message
-pub fn discard_file(repo_path: &str, file_path: &str) -> Result<(), String> {
-    let repo =
-        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+/// A vector of WorkingFile structs, where each file can appear up to twice:
+/// once for staged changes and once for unstaged changes
+pub fn get_working_changes_ex(repo_path: &str) -> Result<Vec<WorkingFile>, String> {
+    let repo = open_workdir_repo(repo_path)?;
 
-    // Check the status of the file
     let statuses = repo
         .statuses(None)
         .map_err(|e| format!("Failed to get statuses: {}", e))?;
 
-    let file_status = statuses.iter().find(|entry| {
-        entry
-            .path()
-            .map(|p| p == file_path)
-            .unwrap_or(false)
-    });
+    let mut files: Vec<WorkingFile> = Vec::new();
+
+    // Get HEAD tree for diff calculations
+    let head_tree = match repo.head() {
+        Ok(head) => head
+            .peel_to_commit()
+            .ok()
+            .and_then(|c| c.tree().ok()),
+        Err(_) => None,
+    };
+
+    for entry in statuses.iter() {
+        let status = entry.status();
+
+        // Skip files that are unchanged
+        if status.is_empty() {
+            continue;
+        }
+
+        let path = entry
+            .path()
+            .map(|p| p.to_string())
+            .unwrap_or_default();
+
+        // Unresolved conflicts get a single entry; they can't be partly staged
+        if status.is_conflicted() {
+            files.push(WorkingFile {
+                path,
+                staged_status: None,
+                unstaged_status: Some(FileStatus::Conflicted),
+                staged_additions: 0,
+                staged_deletions: 0,
+                unstaged_additions: 0,
+                unstaged_deletions: 0,
+                old_path: None,
+                section: "unstaged".to_string(),
+            });
+            continue;
+        }
+
+        // Determine staged (index) status
+        let staged_status = if status.is_index_new() {
+            Some(FileStatus::Added)
+        } else if status.is_index_deleted() {
+            Some(FileStatus::Deleted)
+        } else if status.is_index_modified() {
+            Some(FileStatus::Modified)
+        } else if status.is_index_renamed() {
+            Some(FileStatus::Renamed)
+        } else {
+            None
+        };
+
+        // Determine unstaged (workdir) status
+        let unstaged_status = if status.is_wt_new() {
+            Some(FileStatus::Untracked)
+        } else if status.is_wt_deleted() {
+            Some(FileStatus::Deleted)
+        } else if status.is_wt_modified() {
+            Some(FileStatus::Modified)
+        } else if status.is_wt_renamed() {
+            Some(FileStatus::Renamed)
+        } else {
+            None
+        };
+
+        // Calculate line stats for staged changes (HEAD -> index)
+        let (staged_additions, staged_deletions) = if staged_status.is_some() {
+            if let Some(ref head) = head_tree {
+                let mut diff_opts = DiffOptions::new();
+                diff_opts.pathspec(&path);
+
+                if let Ok(diff) = repo.diff_tree_to_index(Some(head), None, Some(&mut diff_opts)) {
+                    if let Ok(stats) = diff.stats() {
+                        (stats.insertions() as u32, stats.deletions() as u32)
+                    } else {
+                        (0, 0)
+                    }
+                } else {
+                    (0, 0)
+                }
+            } else {
+                (0, 0)
+            }
+        } else {
+            (0, 0)
+        };
+
+        // Calculate line stats for unstaged changes (index -> workdir)
+        let (unstaged_additions, unstaged_deletions) = if unstaged_status.is_some() {
+            let mut diff_opts = DiffOptions::new();
+            diff_opts.pathspec(&path);
+            diff_opts.include_untracked(true);
+
+            if let Ok(diff) = repo.diff_index_to_workdir(None, Some(&mut diff_opts)) {
+                if let Ok(stats) = diff.stats() {
+                    (stats.insertions() as u32, stats.deletions() as u32)
+                } else {
+                    (0, 0)
+                }
+            } else {
+                (0, 0)
+            }
+        } else {
+            (0, 0)
+        };
+
+        // Create entry for staged changes if present
+        if staged_status.is_some() {
+            files.push(WorkingFile {
+                path: path.clone(),
+                staged_status,
+                unstaged_status: None,
+                staged_additions,
+                staged_deletions,
+                unstaged_additions: 0,
+                unstaged_deletions: 0,
+                old_path: None,
+                section: "staged".to_string(),
+            });
+        }
+
+        // Create entry for unstaged changes if present
+        if unstaged_status.is_some() {
+            files.push(WorkingFile {
+                path,
+                staged_status: None,
+                unstaged_status,
+                staged_additions: 0,
+                staged_deletions: 0,
+                unstaged_additions,
+                unstaged_deletions,
+                old_path: None,
+                section: "unstaged".to_string(),
+            });
+        }
+    }
+
+    Ok(files)
+}
+
+/// Gets the diff for a specific file in the working directory (vs HEAD)
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Path to the file to get diff for
+///
+/// # Returns
+/// A FileDiff struct or an error message
+pub fn get_working_file_diff(repo_path: &str, file_path: &str) -> Result<FileDiff, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    // Get HEAD tree (if it exists)
+    let head_tree = match repo.head() {
+        Ok(head) => Some(
+            head.peel_to_tree()
+                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?,
+        ),
+        Err(_) => None, // No commits yet
+    };
+
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.pathspec(file_path);
+    diff_opts.include_untracked(true);
+
+    let diff = repo
+        .diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut diff_opts))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    // Check if file exists in diff
+    if diff.deltas().len() == 0 {
+        return Err(format!("File '{}' has no changes", file_path));
+    }
+
+    let delta = diff.get_delta(0).expect("Delta should exist");
+
+    let new_file = delta.new_file();
+    let old_file = delta.old_file();
+
+    let new_path = new_file
+        .path()
+        .or_else(|| old_file.path())
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let old_path = if delta.status() == Delta::Renamed || delta.status() == Delta::Copied {
+        old_file.path().map(|p| p.to_string_lossy().to_string())
+    } else {
+        None
+    };
+
+    // Check if binary
+    let is_binary = new_file.is_binary() || old_file.is_binary();
+
+    if is_binary {
+        return Ok(FileDiff {
+            old_path,
+            new_path,
+            hunks: Vec::new(),
+            is_binary: true,
+        });
+    }
+
+    // Get patch for detailed diff
+    let patch = git2::Patch::from_diff(&diff, 0)
+        .map_err(|e| format!("Failed to create patch: {}", e))?
+        .ok_or_else(|| "Failed to create patch for file".to_string())?;
+
+    let hunks = collect_hunks(&patch)?;
+
+    Ok(FileDiff {
+        old_path,
+        new_path,
+        hunks,
+        is_binary: false,
+    })
+}
+
+/// Gets the diff for staged changes of a file (index vs HEAD)
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Path to the file to get diff for
+///
+/// # Returns
+/// A FileDiff struct showing staged changes, or an error if file has no staged changes
+pub fn get_staged_file_diff(repo_path: &str, file_path: &str) -> Result<FileDiff, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    // Get HEAD tree (if it exists)
+    let head_tree = match repo.head() {
+        Ok(head) => Some(
+            head.peel_to_tree()
+                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?,
+        ),
+        Err(_) => None, // No commits yet
+    };
+
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.pathspec(file_path);
+
+    // Diff HEAD -> index (staged changes only)
+    let diff = repo
+        .diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_opts))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    // Check if file exists in diff
+    if diff.deltas().len() == 0 {
+        return Err(format!("File '{}' has no staged changes", file_path));
+    }
+
+    let delta = diff.get_delta(0).expect("Delta should exist");
+
+    // Check if file actually has changes (not just in index with no diff)
+    if delta.status() == Delta::Unmodified {
+        return Err(format!("File '{}' has no staged changes", file_path));
+    }
+
+    let new_file = delta.new_file();
+    let old_file = delta.old_file();
+
+    let new_path = new_file
+        .path()
+        .or_else(|| old_file.path())
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let old_path = if delta.status() == Delta::Renamed || delta.status() == Delta::Copied {
+        old_file.path().map(|p| p.to_string_lossy().to_string())
+    } else {
+        None
+    };
+
+    // Check if binary
+    let is_binary = new_file.is_binary() || old_file.is_binary();
+
+    if is_binary {
+        return Ok(FileDiff {
+            old_path,
+            new_path,
+            hunks: Vec::new(),
+            is_binary: true,
+        });
+    }
+
+    // Get patch for detailed diff
+    let patch = git2::Patch::from_diff(&diff, 0)
+        .map_err(|e| format!("Failed to create patch: {}", e))?
+        .ok_or_else(|| "Failed to create patch for file".to_string())?;
+
+    let hunks = collect_hunks(&patch)?;
+
+    Ok(FileDiff {
+        old_path,
+        new_path,
+        hunks,
+        is_binary: false,
+    })
+}
+
+/// Gets the diff for unstaged changes of a file (workdir vs index)
+/// Falls back to workdir vs HEAD if file has no staged changes
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Path to the file to get diff for
+///
+/// # Returns
+/// A FileDiff struct showing unstaged changes
+pub fn get_unstaged_file_diff(repo_path: &str, file_path: &str) -> Result<FileDiff, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    // Diff index -> workdir (unstaged changes), including untracked file content
+    let diff = repo
+        .diff_index_to_workdir(None, Some(&mut unstaged_diff_options(file_path, false)))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    // Check if file exists in diff
+    if diff.deltas().len() == 0 {
+        return Err(format!("File '{}' has no unstaged changes", file_path));
+    }
+
+    let delta = diff.get_delta(0).expect("Delta should exist");
+
+    let new_file = delta.new_file();
+    let old_file = delta.old_file();
+
+    let new_path = new_file
+        .path()
+        .or_else(|| old_file.path())
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let old_path = if delta.status() == Delta::Renamed || delta.status() == Delta::Copied {
+        old_file.path().map(|p| p.to_string_lossy().to_string())
+    } else {
+        None
+    };
+
+    // Check if binary
+    let is_binary = new_file.is_binary() || old_file.is_binary();
+
+    if is_binary {
+        return Ok(FileDiff {
+            old_path,
+            new_path,
+            hunks: Vec::new(),
+            is_binary: true,
+        });
+    }
+
+    // Get patch for detailed diff
+    let patch = git2::Patch::from_diff(&diff, 0)
+        .map_err(|e| format!("Failed to create patch: {}", e))?
+        .ok_or_else(|| "Failed to create patch for file".to_string())?;
+
+    let hunks = collect_hunks(&patch)?;
+
+    Ok(FileDiff {
+        old_path,
+        new_path,
+        hunks,
+        is_binary: false,
+    })
+}
+
+/// Gets the full file contents for a working directory change (vs HEAD)
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Path to the file to get contents for
+///
+/// # Returns
+/// A FileContents struct with old (HEAD) and new (working dir) content
+pub fn get_working_file_contents(repo_path: &str, file_path: &str) -> Result<FileContents, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    // Get old content from HEAD (if it exists)
+    let old_content = match repo.head() {
+        Ok(head) => {
+            let tree = head
+                .peel_to_tree()
+                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+
+            match tree.get_path(std::path::Path::new(file_path)) {
+                Ok(entry) => {
+                    let object = entry
+                        .to_object(&repo)
+                        .map_err(|e| format!("Failed to get object: {}", e))?;
+
+                    if let Some(blob) = object.as_blob() {
+                        if blob.is_binary() {
+                            return Ok(FileContents {
+                                old_content: None,
+                                new_content: None,
+                                is_binary: true,
+                            });
+                        }
+                        match std::str::from_utf8(blob.content()) {
+                            Ok(s) => Some(s.to_string()),
+                            Err(_) => {
+                                return Err("File is not valid UTF-8".to_string());
+                            }
+                        }
+                    } else {
+                        return Err("Not a blob".to_string());
+                    }
+                }
+                Err(_) => None, // File doesn't exist in HEAD (new file)
+            }
+        }
+        Err(_) => None, // No HEAD (empty repo)
+    };
+
+    // Get new content from working directory
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+
+    let file_full_path = workdir.join(file_path);
+
+    let new_content = if file_full_path.exists() {
+        let content = std::fs::read(&file_full_path)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+
+        // Check if binary (contains null bytes in first 8000 bytes)
+        let check_len = std::cmp::min(content.len(), 8000);
+        if content[..check_len].contains(&0) {
+            return Ok(FileContents {
+                old_content: None,
+                new_content: None,
+                is_binary: true,
+            });
+        }
+
+        match String::from_utf8(content) {
+            Ok(s) => Some(s),
+            Err(_) => return Err("File is not valid UTF-8".to_string()),
+        }
+    } else {
+        None // File was deleted
+    };
+
+    // Verify there's actually a change
+    if old_content.is_none() && new_content.is_none() {
+        return Err(format!("File '{}' not found", file_path));
+    }
+
+    Ok(FileContents {
+        old_content,
+        new_content,
+        is_binary: false,
+    })
+}
+
+/// Gets the file contents for a staged file (HEAD vs index/staging area)
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Path to the file to get contents for
+///
+/// # Returns
+/// A FileContents struct with old (HEAD) and new (staged/index) content
+pub fn get_staged_file_contents(repo_path: &str, file_path: &str) -> Result<FileContents, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    // Get old content from HEAD (if it exists)
+    let old_content = match repo.head() {
+        Ok(head) => {
+            let tree = head
+                .peel_to_tree()
+                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+
+            match tree.get_path(std::path::Path::new(file_path)) {
+                Ok(entry) => {
+                    let object = entry
+                        .to_object(&repo)
+                        .map_err(|e| format!("Failed to get object: {}", e))?;
+
+                    if let Some(blob) = object.as_blob() {
+                        if blob.is_binary() {
+                            return Ok(FileContents {
+                                old_content: None,
+                                new_content: None,
+                                is_binary: true,
+                            });
+                        }
+                        match std::str::from_utf8(blob.content()) {
+                            Ok(s) => Some(s.to_string()),
+                            Err(_) => {
+                                return Err("File is not valid UTF-8".to_string());
+                            }
+                        }
+                    } else {
+                        return Err("Not a blob".to_string());
+                    }
+                }
+                Err(_) => None, // File doesn't exist in HEAD (new file)
+            }
+        }
+        Err(_) => None, // No HEAD (empty repo)
+    };
+
+    // Get new content from index (staged)
+    let new_content = match repo.index() {
+        Ok(index) => {
+            match index.get_path(std::path::Path::new(file_path), 0) {
+                Some(entry) => {
+                    match repo.find_blob(entry.id) {
+                        Ok(blob) => {
+                            if blob.is_binary() {
+                                return Ok(FileContents {
+                                    old_content: None,
+                                    new_content: None,
+                                    is_binary: true,
+                                });
+                            }
+                            match std::str::from_utf8(blob.content()) {
+                                Ok(s) => Some(s.to_string()),
+                                Err(_) => return Err("File is not valid UTF-8".to_string()),
+                            }
+                        }
+                        Err(_) => None,
+                    }
+                }
+                None => None, // File not in index
+            }
+        }
+        Err(_) => None,
+    };
+
+    // Verify there's actually a change
+    if old_content.is_none() && new_content.is_none() {
+        return Err(format!("File '{}' not found in HEAD or index", file_path));
+    }
+
+    Ok(FileContents {
+        old_content,
+        new_content,
+        is_binary: false,
+    })
+}
+
+/// Gets the file contents for an unstaged file (index vs working directory)
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Path to the file to get contents for
+///
+/// # Returns
+/// A FileContents struct with old (index/staged) and new (working dir) content
+pub fn get_unstaged_file_contents(repo_path: &str, file_path: &str) -> Result<FileContents, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    // Get old content from index (staged)
+    let old_content = match repo.index() {
+        Ok(index) => {
+            match index.get_path(std::path::Path::new(file_path), 0) {
+                Some(entry) => {
+                    match repo.find_blob(entry.id) {
+                        Ok(blob) => {
+                            if blob.is_binary() {
+                                return Ok(FileContents {
+                                    old_content: None,
+                                    new_content: None,
+                                    is_binary: true,
+                                });
+                            }
+                            match std::str::from_utf8(blob.content()) {
+                                Ok(s) => Some(s.to_string()),
+                                Err(_) => return Err("File is not valid UTF-8".to_string()),
+                            }
+                        }
+                        Err(_) => None,
+                    }
+                }
+                None => None, // File not in index (check HEAD as fallback)
+            }
+        }
+        Err(_) => None,
+    };
+
+    // If not in index, try HEAD (file not staged)
+    let old_content = if old_content.is_none() {
+        match repo.head() {
+            Ok(head) => {
+                let tree = head.peel_to_tree()
+                    .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+                
+                match tree.get_path(std::path::Path::new(file_path)) {
+                    Ok(entry) => {
+                        let object = entry.to_object(&repo)
+                            .map_err(|e| format!("Failed to get object: {}", e))?;
+                        
+                        if let Some(blob) = object.as_blob() {
+                            if blob.is_binary() {
+                                return Ok(FileContents {
+                                    old_content: None,
+                                    new_content: None,
+                                    is_binary: true,
+                                });
+                            }
+                            match std::str::from_utf8(blob.content()) {
+                                Ok(s) => Some(s.to_string()),
+                                Err(_) => return Err("File is not valid UTF-8".to_string()),
+                            }
+                        } else {
+                            None
+                        }
+                    }
+                    Err(_) => None,
+                }
+            }
+            Err(_) => None,
+        }
+    } else {
+        old_content
+    };
+
+    // Get new content from working directory
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+
+    let file_full_path = workdir.join(file_path);
+
+    let new_content = if file_full_path.exists() {
+        let content = std::fs::read(&file_full_path)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+
+        // Check if binary (contains null bytes in first 8000 bytes)
+        let check_len = std::cmp::min(content.len(), 8000);
+        if content[..check_len].contains(&0) {
+            return Ok(FileContents {
+                old_content: None,
+                new_content: None,
+                is_binary: true,
+            });
+        }
+
+        match String::from_utf8(content) {
+            Ok(s) => Some(s),
+            Err(_) => return Err("File is not valid UTF-8".to_string()),
+        }
+    } else {
+        None // File was deleted
+    };
+
+    // Verify there's actually a change
+    if old_content.is_none() && new_content.is_none() {
+        return Err(format!("File '{}' not found", file_path));
+    }
+
+    Ok(FileContents {
+        old_content,
+        new_content,
+        is_binary: false,
+    })
+}
+
+/// Stages a file by updating its index entry from the working directory.
+/// Untracked and intent-to-add files are added with their current content,
+/// and files deleted from the working directory have their deletion staged.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Relative path to the file within the repository
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn stage_file(repo_path: &str, file_path: &str) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    let path = std::path::Path::new(file_path);
+
+    // symlink_metadata so that dangling symlinks still count as present
+    if workdir.join(file_path).symlink_metadata().is_ok() {
+        index
+            .add_path(path)
+            .map_err(|e| format!("Failed to stage file: {}", e))?;
+    } else if index.get_path(path, 0).is_some() {
+        // File was deleted from the working directory - stage the deletion
+        index
+            .remove_path(path)
+            .map_err(|e| format!("Failed to stage deletion: {}", e))?;
+    } else {
+        return Err(format!("File '{}' not found", file_path));
+    }
+
+    index
+        .write()
+        .map_err(|e| format!("Failed to write index: {}", e))?;
+
+    Ok(())
+}
+
+/// Stages every change in the working directory, including untracked files
+/// (respecting .gitignore) and deletions.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn stage_all(repo_path: &str) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    // add_all picks up new and modified files, update_all stages deletions
+    index
+        .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+        .map_err(|e| format!("Failed to stage files: {}", e))?;
+    index
+        .update_all(["*"].iter(), None)
+        .map_err(|e| format!("Failed to stage deletions: {}", e))?;
+
+    index
+        .write()
+        .map_err(|e| format!("Failed to write index: {}", e))?;
+
+    Ok(())
+}
+
+/// Unstages a file by resetting its index entry to match HEAD.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Relative path to the file within the repository
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn unstage_file(repo_path: &str, file_path: &str) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    // Get HEAD tree to reset the file to
+    let head = repo.head().map_err(|e| format!("Failed to get HEAD: {}", e))?;
+    let head_commit = head
+        .peel_to_commit()
+        .map_err(|e| format!("Failed to get HEAD commit: {}", e))?;
+    let head_tree = head_commit
+        .tree()
+        .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+
+    // Check if file exists in HEAD
+    let path = std::path::Path::new(file_path);
+    match head_tree.get_path(path) {
+        Ok(entry) => {
+            // File exists in HEAD - restore index entry to HEAD version
+            let blob = repo
+                .find_blob(entry.id())
+                .map_err(|e| format!("Failed to find blob: {}", e))?;
+
+            let index_entry = git2::IndexEntry {
+                ctime: git2::IndexTime::new(0, 0),
+                mtime: git2::IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: entry.filemode() as u32,
+                uid: 0,
+                gid: 0,
+                file_size: blob.size() as u32,
+                id: entry.id(),
+                flags: 0,
+                flags_extended: 0,
+                path: file_path.as_bytes().to_vec(),
+            };
+
+            index
+                .add(&index_entry)
+                .map_err(|e| format!("Failed to reset index entry: {}", e))?;
+        }
+        Err(_) => {
+            // File doesn't exist in HEAD - remove from index (was a new file)
+            index
+                .remove_path(path)
+                .map_err(|e| format!("Failed to remove from index: {}", e))?;
+        }
+    }
+
+    index
+        .write()
+        .map_err(|e| format!("Failed to write index: {}", e))?;
+
+    Ok(())
+}
+
+/// Unstages every file matching a list of paths or pathspecs (directories,
+/// globs) in a single index write
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `pathspecs` - Paths, directories or glob patterns relative to the repository root
+///
+/// # Returns
+/// Ok(()) on success, or an error message if nothing staged matches
+pub fn unstage_paths(repo_path: &str, pathspecs: &[String]) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+    if pathspecs.is_empty() {
+        return Err("No paths to unstage".to_string());
+    }
+
+    // No HEAD yet (empty repo) - resetting to None removes the entries
+    let head_commit = match repo.head() {
+        Ok(head) => Some(
+            head.peel_to_commit()
+                .map_err(|e| format!("Failed to get HEAD commit: {}", e))?,
+        ),
+        Err(_) => None,
+    };
+    let head_tree = match &head_commit {
+        Some(commit) => Some(
+            commit
+                .tree()
+                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?,
+        ),
+        None => None,
+    };
+
+    let mut diff_opts = DiffOptions::new();
+    for pathspec in pathspecs {
+        diff_opts.pathspec(pathspec);
+    }
+    let staged = repo
+        .diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_opts))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+    if staged.deltas().len() == 0 {
+        return Err(format!("No staged changes in {}", pathspecs.join(", ")));
+    }
+
+    repo.reset_default(
+        head_commit.as_ref().map(|c| c.as_object()),
+        pathspecs.iter(),
+    )
+    .map_err(|e| format!("Failed to unstage files: {}", e))?;
+
+    Ok(())
+}
+
+/// Unstages all changes by resetting the index to match HEAD.
+/// In a repository without commits every entry is removed from the index.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn unstage_all(repo_path: &str) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    // No HEAD yet (empty repo) - resetting to None removes the entries
+    let head_commit = match repo.head() {
+        Ok(head) => Some(
+            head.peel_to_commit()
+                .map_err(|e| format!("Failed to get HEAD commit: {}", e))?,
+        ),
+        Err(_) => None,
+    };
+
+    repo.reset_default(head_commit.as_ref().map(|c| c.as_object()), ["*"].iter())
+        .map_err(|e| format!("Failed to unstage files: {}", e))?;
+
+    Ok(())
+}
+
+/// A destructive working directory change recorded so it can be reverted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoEntry {
+    /// Identifier to pass to undo
+    pub id: String,
+    /// What was done, e.g. "Discard changes to src/main.rs"
+    pub description: String,
+    /// Files whose previous contents were recorded
+    pub paths: Vec<String>,
+    /// Unix timestamp of when the change was made
+    pub timestamp: i64,
+}
+
+const UNDO_REF_PREFIX: &str = "refs/recap/undo/";
+
+/// Oldest entries beyond this are dropped from the undo journal
+const MAX_UNDO_ENTRIES: usize = 50;
+
+fn undo_journal_path(repo: &Repository) -> std::path::PathBuf {
+    repo.path().join("recap").join("undo.json")
+}
+
+fn read_undo_journal(repo: &Repository) -> Result<Vec<UndoEntry>, String> {
+    match std::fs::read_to_string(undo_journal_path(repo)) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to read undo journal: {}", e)),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+fn write_undo_journal(repo: &Repository, entries: &[UndoEntry]) -> Result<(), String> {
+    let path = undo_journal_path(repo);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to write undo journal: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(entries)
+        .map_err(|e| format!("Failed to write undo journal: {}", e))?;
+
+    std::fs::write(path, content).map_err(|e| format!("Failed to write undo journal: {}", e))
+}
+
+/// Saves the current working directory contents of `paths` to the object database,
+/// as a tree referenced by refs/recap/undo/<id> so gc keeps it, and adds an entry
+/// to the undo journal. Paths that don't exist are recorded as absent.
+fn record_undo(
+    repo: &Repository,
+    description: &str,
+    paths: &[String],
+) -> Result<UndoEntry, String> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+
+    let mut snapshot = git2::Index::new().map_err(|e| format!("Failed to create index: {}", e))?;
+    for path in paths {
+        let full_path = workdir.join(path);
+        let Ok(metadata) = std::fs::symlink_metadata(&full_path) else {
+            continue;
+        };
+
+        let (id, mode) = if metadata.file_type().is_symlink() {
+            let target = std::fs::read_link(&full_path)
+                .map_err(|e| format!("Failed to read link: {}", e))?;
+            let id = repo
+                .blob(target.to_string_lossy().as_bytes())
+                .map_err(|e| format!("Failed to save file: {}", e))?;
+            (id, 0o120000)
+        } else if metadata.is_file() {
+            let id = repo
+                .blob_path(&full_path)
+                .map_err(|e| format!("Failed to save file: {}", e))?;
+            #[cfg(unix)]
+            let executable = {
+                use std::os::unix::fs::PermissionsExt;
+                metadata.permissions().mode() & 0o111 != 0
+            };
+            #[cfg(not(unix))]
+            let executable = false;
+            (id, if executable { 0o100755 } else { 0o100644 })
+        } else {
+            continue;
+        };
+
+        snapshot
+            .add(&git2::IndexEntry {
+                ctime: git2::IndexTime::new(0, 0),
+                mtime: git2::IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode,
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id,
+                flags: 0,
+                flags_extended: 0,
+                path: path.as_bytes().to_vec(),
+            })
+            .map_err(|e| format!("Failed to save file: {}", e))?;
+    }
+    let tree_id = snapshot
+        .write_tree_to(repo)
+        .map_err(|e| format!("Failed to save files: {}", e))?;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut entries = read_undo_journal(repo)?;
+    let mut id = now.as_millis().to_string();
+    let mut attempt = 1;
+    while entries.iter().any(|entry| entry.id == id) {
+        attempt += 1;
+        id = format!("{}-{}", now.as_millis(), attempt);
+    }
+
+    repo.reference(
+        &format!("{}{}", UNDO_REF_PREFIX, id),
+        tree_id,
+        true,
+        description,
+    )
+    .map_err(|e| format!("Failed to save files: {}", e))?;
+
+    let entry = UndoEntry {
+        id,
+        description: description.to_string(),
+        paths: paths.to_vec(),
+        timestamp: now.as_secs() as i64,
+    };
+    entries.push(entry.clone());
+
+    let excess = entries.len().saturating_sub(MAX_UNDO_ENTRIES);
+    for old in entries.drain(..excess) {
+        if let Ok(mut reference) = repo.find_reference(&format!("{}{}", UNDO_REF_PREFIX, old.id)) {
+            let _ = reference.delete();
+        }
+    }
+    write_undo_journal(repo, &entries)?;
+
+    Ok(entry)
+}
+
+/// Lists the recorded destructive changes, newest first
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A vector of UndoEntry structs or an error message
+pub fn list_undo_entries(repo_path: &str) -> Result<Vec<UndoEntry>, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    let mut entries = read_undo_journal(&repo)?;
+    entries.reverse();
+
+    Ok(entries)
+}
+
+/// Reverts a recorded destructive change, writing back the files as they were
+/// before it. Files that didn't exist then are removed again. The entry is
+/// removed from the journal.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `id` - Identifier of the entry, as returned by list_undo_entries
+///
+/// # Returns
+/// The reverted UndoEntry, or an error message
+pub fn undo(repo_path: &str, id: &str) -> Result<UndoEntry, String> {
+    let repo = open_workdir_repo(repo_path)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+
+    let mut entries = read_undo_journal(&repo)?;
+    let position = entries
+        .iter()
+        .position(|entry| entry.id == id)
+        .ok_or_else(|| format!("No undo entry '{}'", id))?;
+    let ref_name = format!("{}{}", UNDO_REF_PREFIX, id);
+    let tree = repo
+        .find_reference(&ref_name)
+        .and_then(|reference| reference.peel_to_tree())
+        .map_err(|e| format!("Failed to read saved files: {}", e))?;
+
+    for path in &entries[position].paths {
+        let full_path = workdir.join(path);
+        let saved = tree.get_path(std::path::Path::new(path)).ok();
+
+        if std::fs::symlink_metadata(&full_path).is_ok_and(|m| !m.is_dir()) {
+            std::fs::remove_file(&full_path)
+                .map_err(|e| format!("Failed to restore '{}': {}", path, e))?;
+        }
+        let Some(saved) = saved else {
+            continue;
+        };
+
+        let blob = repo
+            .find_blob(saved.id())
+            .map_err(|e| format!("Failed to read saved file: {}", e))?;
+        if let Some(parent) = full_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to restore '{}': {}", path, e))?;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            if saved.filemode() == 0o120000 {
+                let target = String::from_utf8_lossy(blob.content()).to_string();
+                std::os::unix::fs::symlink(target, &full_path)
+                    .map_err(|e| format!("Failed to restore '{}': {}", path, e))?;
+                continue;
+            }
+            std::fs::write(&full_path, blob.content())
+                .map_err(|e| format!("Failed to restore '{}': {}", path, e))?;
+            if saved.filemode() == 0o100755 {
+                std::fs::set_permissions(&full_path, std::fs::Permissions::from_mode(0o755))
+                    .map_err(|e| format!("Failed to restore '{}': {}", path, e))?;
+            }
+        }
+        #[cfg(not(unix))]
+        std::fs::write(&full_path, blob.content())
+            .map_err(|e| format!("Failed to restore '{}': {}", path, e))?;
+    }
+
+    let entry = entries.remove(position);
+    write_undo_journal(&repo, &entries)?;
+    if let Ok(mut reference) = repo.find_reference(&ref_name) {
+        reference
+            .delete()
+            .map_err(|e| format!("Failed to remove saved files: {}", e))?;
+    }
+
+    Ok(entry)
+}
+
+/// What an untracked directory holds, as git sees it
+struct UntrackedDir {
+    /// Untracked files under it, relative to the working directory
+    files: Vec<String>,
+    /// Whether it also holds ignored files, which discarding leaves alone
+    has_ignored: bool,
+}
+
+/// Lists the untracked files under a directory through git's status, so ignored
+/// files are never treated as changes. Ignored directories aren't walked.
+fn untracked_dir_contents(repo: &Repository, dir_path: &str) -> Result<UntrackedDir, String> {
+    let mut status_opts = git2::StatusOptions::new();
+    status_opts
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(true)
+        .recurse_ignored_dirs(false)
+        .pathspec(dir_path);
+    let statuses = repo
+        .statuses(Some(&mut status_opts))
+        .map_err(|e| format!("Failed to get statuses: {}", e))?;
+
+    let prefix = format!("{}/", dir_path);
+    let mut contents = UntrackedDir {
+        files: Vec::new(),
+        has_ignored: false,
+    };
+    for entry in statuses.iter() {
+        let Some(path) = entry.path() else {
+            continue;
+        };
+        if !path.starts_with(&prefix) {
+            continue;
+        }
+        if entry.status().is_ignored() {
+            contents.has_ignored = true;
+        } else if entry.status().is_wt_new() {
+            contents.files.push(path.to_string());
+        }
+    }
+
+    Ok(contents)
+}
+
+/// Deletes or trashes an untracked file or directory
+fn remove_untracked(full_path: &std::path::Path, use_trash: bool) -> Result<(), String> {
+    if use_trash {
+        // The platform trash: NSFileManager on macOS, the recycle bin on Windows
+        // and the freedesktop.org trash on Linux, so the file can be put back
+        return trash::delete(full_path).map_err(|e| {
+            format!(
+                "Failed to move '{}' to the trash: {}",
+                full_path.display(),
+                e
+            )
+        });
+    }
+
+    if full_path.is_dir() {
+        std::fs::remove_dir_all(full_path).map_err(|e| format!("Failed to delete directory: {}", e))
+    } else {
+        std::fs::remove_file(full_path).map_err(|e| format!("Failed to delete file: {}", e))
+    }
+}
+
+/// Discards an untracked directory with everything in it
+fn discard_untracked_dir(repo: &Repository, dir_path: &str, use_trash: bool) -> Result<(), String> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+
+    let prefix = format!("{}/", dir_path);
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    if index
+        .iter()
+        .any(|entry| entry.path.starts_with(prefix.as_bytes()))
+    {
+        return Err(format!(
+            "'{}' contains tracked files; discard them one at a time",
+            dir_path
+        ));
+    }
+
+    let contents = untracked_dir_contents(repo, dir_path)?;
+    if contents.files.is_empty() {
+        return Err(if contents.has_ignored {
+            format!("'{}' only contains ignored files", dir_path)
+        } else {
+            format!("'{}' has no changes to discard", dir_path)
+        });
+    }
+    record_undo(
+        repo,
+        &format!("Discard untracked directory {}", dir_path),
+        &contents.files,
+    )?;
+
+    // Like `git clean` without -x, ignored files stay where they are
+    if contents.has_ignored {
+        for file in &contents.files {
+            let full_path = workdir.join(file);
+            remove_untracked(&full_path, use_trash)?;
+            prune_empty_parents(workdir, &full_path);
+        }
+        return Ok(());
+    }
+
+    remove_untracked(&workdir.join(dir_path), use_trash)
+}
+
+/// Discards changes to a file in the working directory.
+/// For tracked files: restores the file content from the index (or HEAD if not staged).
+/// For untracked files and directories: deletes them, or moves them to the
+/// system trash when `use_trash` is set.
+/// The discarded contents are recorded in the undo journal first.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Relative path to the file (or untracked directory) within the repository
+/// * `use_trash` - Whether to move untracked files to the trash instead of deleting them
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn discard_file(repo_path: &str, file_path: &str, use_trash: bool) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let dir_path = file_path.trim_end_matches('/');
+    if repo
+        .workdir()
+        .and_then(|workdir| std::fs::symlink_metadata(workdir.join(dir_path)).ok())
+        .is_some_and(|metadata| metadata.is_dir())
+    {
+        return discard_untracked_dir(&repo, dir_path, use_trash);
+    }
+
+    // Check the status of the file. Ignored files have nothing to discard.
+    let mut status_opts = git2::StatusOptions::new();
+    status_opts
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false)
+        .pathspec(file_path)
+        .disable_pathspec_match(true);
+    let statuses = repo
+        .statuses(Some(&mut status_opts))
+        .map_err(|e| format!("Failed to get statuses: {}", e))?;
+
+    let file_status = statuses.iter().find(|entry| {
+        entry
+            .path()
+            .map(|p| p == file_path)
+            .unwrap_or(false)
+    });
+
+    let status = file_status
+        .map(|e| e.status())
+        .ok_or_else(|| format!("File '{}' has no changes to discard", file_path))?;
+
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+    let full_path = workdir.join(file_path);
+
+    record_undo(
+        &repo,
+        &format!("Discard changes to {}", file_path),
+        &[file_path.to_string()],
+    )?;
+
+    // Handle untracked files - just delete them
+    if status.is_wt_new() && !status.is_index_new() {
+        if std::fs::symlink_metadata(&full_path).is_ok() {
+            remove_untracked(&full_path, use_trash)?;
+        }
+        return Ok(());
+    }
+
+    // For tracked files, checkout from index (which reflects staged state or HEAD)
+    let mut checkout_opts = CheckoutBuilder::new();
+    checkout_opts.force();
+    checkout_opts.path(file_path);
+
+    repo.checkout_index(None, Some(&mut checkout_opts))
+        .map_err(|e| format!("Failed to discard changes: {}", e))?;
+
+    Ok(())
+}
+
+/// Whether a pathspec contains glob characters
+fn is_glob(pathspec: &str) -> bool {
+    pathspec.contains(['*', '?', '['])
+}
+
+/// Removes the now empty directories above `path`, up to the working directory
+fn prune_empty_parents(workdir: &std::path::Path, path: &std::path::Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == workdir || !current.starts_with(workdir) {
+            break;
+        }
+        if std::fs::remove_dir(current).is_err() {
+            break; // not empty
+        }
+        dir = current.parent();
+    }
+}
+
+/// Discards the changes to every file matching a list of paths or pathspecs
+/// (directories, globs), like `git restore` plus `git clean`. Tracked files are
+/// restored from the index in one checkout; untracked files, and untracked
+/// directories named outright, are deleted or moved to the trash. Ignored files
+/// are left alone.
+///
+/// The whole batch is worked out and checked before anything changes, and is
+/// recorded as a single undo journal entry. Untracked files are only removed
+/// once the checkout has succeeded. A pathspec that matches no file fails the
+/// whole batch, like it does for `git restore`.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `pathspecs` - Paths, directories or glob patterns relative to the repository root
+/// * `use_trash` - Whether to move untracked files to the trash instead of deleting them
+///
+/// # Returns
+/// Ok(()) on success, or an error message if nothing matches
+pub fn discard_paths(repo_path: &str, pathspecs: &[String], use_trash: bool) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+    if pathspecs.is_empty() {
+        return Err("No paths to discard".to_string());
+    }
+
+    let unmatched = unmatched_pathspecs(&repo, pathspecs)?;
+    if !unmatched.is_empty() {
+        return Err(format!(
+            "Pathspec '{}' did not match any files",
+            unmatched.join("', '")
+        ));
+    }
+
+    // Untracked directories named outright go away whole (one trash item each),
+    // unless they hold ignored files that have to stay
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let mut tracked = Vec::new();
+    let mut untracked = Vec::new();
+    let mut untracked_dirs = Vec::new();
+    let mut recorded = Vec::new();
+    let mut specs = Vec::new();
+    for pathspec in pathspecs {
+        let dir = pathspec.trim_end_matches('/');
+        let is_dir = !dir.is_empty()
+            && !is_glob(dir)
+            && std::fs::symlink_metadata(workdir.join(dir)).is_ok_and(|m| m.is_dir());
+        let prefix = format!("{}/", dir);
+        if is_dir
+            && !index
+                .iter()
+                .any(|entry| entry.path.starts_with(prefix.as_bytes()))
+        {
+            let contents = untracked_dir_contents(&repo, dir)?;
+            if contents.has_ignored {
+                untracked.extend(contents.files);
+            } else if !contents.files.is_empty() {
+                untracked_dirs.push(dir.to_string());
+                recorded.extend(contents.files);
+            }
+        } else {
+            specs.push(pathspec.as_str());
+        }
+    }
+
+    if !specs.is_empty() {
+        let mut status_opts = git2::StatusOptions::new();
+        status_opts
+            .include_untracked(true)
+            .recurse_untracked_dirs(true);
+        for pathspec in &specs {
+            status_opts.pathspec(pathspec);
+        }
+        let statuses = repo
+            .statuses(Some(&mut status_opts))
+            .map_err(|e| format!("Failed to get statuses: {}", e))?;
+
+        for entry in statuses.iter() {
+            let status = entry.status();
+            let path = entry.path().unwrap_or_default().to_string();
+
+            if status.is_conflicted() {
+                return Err(format!(
+                    "Cannot discard '{}': it has unresolved conflicts",
+                    path
+                ));
+            }
+            if status.is_wt_new() && !status.is_index_new() {
+                untracked.push(path);
+            } else if status.is_wt_modified() || status.is_wt_deleted() || status.is_wt_typechange()
+            {
+                tracked.push(path);
+            }
+        }
+    }
+
+    // A file can match several pathspecs, or sit in a directory removed whole
+    untracked.retain(|path| {
+        !untracked_dirs
+            .iter()
+            .any(|dir| path.starts_with(&format!("{}/", dir)))
+    });
+    untracked.sort();
+    untracked.dedup();
+    recorded.extend(tracked.iter().chain(&untracked).cloned());
+    if tracked.is_empty() && untracked.is_empty() && untracked_dirs.is_empty() {
+        return Err(format!("No changes to discard in {}", pathspecs.join(", ")));
+    }
+
+    let description = match (recorded.as_slice(), untracked_dirs.as_slice()) {
+        ([path], []) => format!("Discard changes to {}", path),
+        (_, [dir]) if tracked.is_empty() && untracked.is_empty() => {
+            format!("Discard untracked directory {}", dir)
+        }
+        _ => format!("Discard changes to {} files", recorded.len()),
+    };
+    for path in untracked.iter().chain(&untracked_dirs) {
+        check_removable(&workdir.join(path))?;
+    }
+    record_undo(&repo, &description, &recorded)?;
+
+    if !tracked.is_empty() {
+        let mut checkout_opts = CheckoutBuilder::new();
+        checkout_opts.force();
+        for path in &tracked {
+            checkout_opts.path(path);
+        }
+        repo.checkout_index(None, Some(&mut checkout_opts))
+            .map_err(|e| format!("Failed to discard changes: {}", e))?;
+    }
+
+    for path in &untracked {
+        let full_path = workdir.join(path);
+        remove_untracked(&full_path, use_trash)?;
+        prune_empty_parents(workdir, &full_path);
+    }
+    for dir in &untracked_dirs {
+        remove_untracked(&workdir.join(dir), use_trash)?;
+    }
+
+    Ok(())
+}
+
+/// Finds the pathspecs that match no file, neither in the index nor in the
+/// working directory. Paths of ignored files count as matched.
+fn unmatched_pathspecs(repo: &Repository, pathspecs: &[String]) -> Result<Vec<String>, String> {
+    let pathspec =
+        git2::Pathspec::new(pathspecs.iter()).map_err(|e| format!("Invalid pathspec: {}", e))?;
+    let flags = git2::PathspecFlags::FIND_FAILURES;
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    let in_index = pathspec
+        .match_index(&index, flags)
+        .map_err(|e| format!("Failed to match pathspecs: {}", e))?;
+    let in_workdir = pathspec
+        .match_workdir(repo, flags)
+        .map_err(|e| format!("Failed to match pathspecs: {}", e))?;
+
+    let missing_from_index: Vec<&[u8]> = in_index.failed_entries().collect();
+    let workdir = repo.workdir();
+    Ok(in_workdir
+        .failed_entries()
+        .filter(|entry| missing_from_index.contains(entry))
+        .map(|entry| String::from_utf8_lossy(entry).to_string())
+        .filter(|path| {
+            !workdir.is_some_and(|dir| std::fs::symlink_metadata(dir.join(path)).is_ok())
+        })
+        .collect())
+}
+
+/// Checks that an untracked file or directory can be removed from its directory
+fn check_removable(full_path: &std::path::Path) -> Result<(), String> {
+    if std::fs::symlink_metadata(full_path).is_err() {
+        return Err(format!(
+            "Cannot remove '{}': it no longer exists",
+            full_path.display()
+        ));
+    }
+    let parent_writable = full_path
+        .parent()
+        .and_then(|parent| std::fs::metadata(parent).ok())
+        .is_some_and(|metadata| !metadata.permissions().readonly());
+    if !parent_writable {
+        return Err(format!(
+            "Cannot remove '{}': its directory is read-only",
+            full_path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Builds diff options for the unstaged changes of a single file (index -> workdir)
+fn unstaged_diff_options(file_path: &str, reverse: bool) -> DiffOptions {
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.pathspec(file_path);
+    diff_opts.include_untracked(true);
+    diff_opts.recurse_untracked_dirs(true);
+    diff_opts.show_untracked_content(true);
+    diff_opts.reverse(reverse);
+    diff_opts
+}
+
+/// Builds diff options for the staged changes of a single file (HEAD -> index)
+fn staged_diff_options(file_path: &str, reverse: bool) -> DiffOptions {
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.pathspec(file_path);
+    diff_opts.reverse(reverse);
+    diff_opts
+}
+
+/// Finds the position of a hunk in a single-file diff by its header and hash.
+/// Fails if the hunk no longer exists, e.g. because the file changed since
+/// the diff was displayed.
+fn find_hunk_index(
+    diff: &git2::Diff,
+    file_path: &str,
+    hunk_header: &str,
+    hunk_hash: &str,
+) -> Result<usize, String> {
+    if diff.deltas().len() == 0 {
+        return Err(format!("File '{}' has no changes", file_path));
+    }
+
+    let patch = git2::Patch::from_diff(diff, 0)
+        .map_err(|e| format!("Failed to create patch: {}", e))?
+        .ok_or_else(|| "Failed to create patch for file".to_string())?;
+
+    let hunks = collect_hunks(&patch)?;
+
+    hunks
+        .iter()
+        .position(|hunk| hunk.header == hunk_header && hunk.hash == hunk_hash)
+        .ok_or_else(|| {
+            format!(
+                "Hunk '{}' no longer matches the diff of '{}', refresh and try again",
+                hunk_header, file_path
+            )
+        })
+}
+
+/// Applies only the hunk at `hunk_index` of a single-file diff
+fn apply_hunk(
+    repo: &Repository,
+    diff: &git2::Diff,
+    hunk_index: usize,
+    location: ApplyLocation,
+) -> Result<(), String> {
+    let mut current = 0;
+    let mut apply_opts = ApplyOptions::new();
+    apply_opts.hunk_callback(move |_| {
+        let selected = current == hunk_index;
+        current += 1;
+        selected
+    });
+
+    repo.apply(diff, location, Some(&mut apply_opts))
+        .map_err(|e| format!("Failed to apply hunk: {}", e))
+}
+
+/// Stages a single hunk of a file's unstaged changes.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Relative path to the file within the repository
+/// * `hunk_header` - Header of the hunk, as returned by `get_unstaged_file_diff`
+/// * `hunk_hash` - Hash of the hunk, as returned by `get_unstaged_file_diff`
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn stage_hunk(
+    repo_path: &str,
+    file_path: &str,
+    hunk_header: &str,
+    hunk_hash: &str,
+) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    // Index -> workdir, applied forwards onto the index
+    let diff = repo
+        .diff_index_to_workdir(None, Some(&mut unstaged_diff_options(file_path, false)))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    let hunk_index = find_hunk_index(&diff, file_path, hunk_header, hunk_hash)?;
+
+    // An untracked file is a single all-additions hunk that apply can't
+    // target, so staging that hunk means adding the whole file
+    if diff.get_delta(0).map(|d| d.status()) == Some(Delta::Untracked) {
+        return stage_file(repo_path, file_path);
+    }
+
+    apply_hunk(&repo, &diff, hunk_index, ApplyLocation::Index)
+}
+
+/// Unstages a single hunk of a file's staged changes.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Relative path to the file within the repository
+/// * `hunk_header` - Header of the hunk, as returned by `get_staged_file_diff`
+/// * `hunk_hash` - Hash of the hunk, as returned by `get_staged_file_diff`
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn unstage_hunk(
+    repo_path: &str,
+    file_path: &str,
+    hunk_header: &str,
+    hunk_hash: &str,
+) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let head_tree = match repo.head() {
+        Ok(head) => Some(
+            head.peel_to_tree()
+                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?,
+        ),
+        Err(_) => None, // No commits yet
+    };
+
+    // Locate the hunk in the HEAD -> index diff the UI was shown
+    let diff = repo
+        .diff_tree_to_index(
+            head_tree.as_ref(),
+            None,
+            Some(&mut staged_diff_options(file_path, false)),
+        )
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    let hunk_index = find_hunk_index(&diff, file_path, hunk_header, hunk_hash)?;
+
+    // Then apply the same hunk of the reversed diff (index -> HEAD) onto the index
+    let reverse_diff = repo
+        .diff_tree_to_index(
+            head_tree.as_ref(),
+            None,
+            Some(&mut staged_diff_options(file_path, true)),
+        )
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    apply_hunk(&repo, &reverse_diff, hunk_index, ApplyLocation::Index)
+}
+
+/// Discards a single hunk of a file's unstaged changes from the working directory.
+/// The file's previous contents are recorded in the undo journal first.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Relative path to the file within the repository
+/// * `hunk_header` - Header of the hunk, as returned by `get_unstaged_file_diff`
+/// * `hunk_hash` - Hash of the hunk, as returned by `get_unstaged_file_diff`
+/// * `use_trash` - Whether to move an untracked file to the trash instead of deleting it
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn discard_hunk(
+    repo_path: &str,
+    file_path: &str,
+    hunk_header: &str,
+    hunk_hash: &str,
+    use_trash: bool,
+) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let diff = repo
+        .diff_index_to_workdir(None, Some(&mut unstaged_diff_options(file_path, false)))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    let hunk_index = find_hunk_index(&diff, file_path, hunk_header, hunk_hash)?;
+
+    // Discarding the only hunk of an untracked file removes the file
+    if diff.get_delta(0).map(|d| d.status()) == Some(Delta::Untracked) {
+        return discard_file(repo_path, file_path, use_trash);
+    }
+
+    record_undo(
+        &repo,
+        &format!("Discard a change to {}", file_path),
+        &[file_path.to_string()],
+    )?;
+
+    // Reverse the hunk (workdir -> index) in the working directory only
+    let reverse_diff = repo
+        .diff_index_to_workdir(None, Some(&mut unstaged_diff_options(file_path, true)))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    apply_hunk(&repo, &reverse_diff, hunk_index, ApplyLocation::WorkDir)
+}
+
+/// Result of applying a line selection to the old side of a patch
+struct PartialContent {
+    /// The old content with only the selected changes applied
+    content: Vec<u8>,
+    /// Number of change lines that were selected
+    selected: usize,
+    /// Total number of change lines in the patch
+    total: usize,
+}
+
+/// Applies only the selected addition/deletion lines of a single-file patch
+/// to `base`, which must be the old side of the patch. Unselected deletions
+/// are kept as they are and unselected additions are left out.
+fn apply_selected_lines<F>(
+    base: &[u8],
+    patch: &git2::Patch,
+    is_selected: F,
+) -> Result<PartialContent, String>
+where
+    F: Fn(&git2::DiffLine) -> bool,
+{
+    let base_lines: Vec<&[u8]> = base.split_inclusive(|b| *b == b'\n').collect();
+    let mut content = Vec::with_capacity(base.len());
+    let mut next_old = 0usize;
+    let mut selected = 0usize;
+    let mut total = 0usize;
+
+    // Keeps lines separate when a line without a trailing newline is followed by another
+    let push_line = |content: &mut Vec<u8>, line: &[u8]| {
+        if content.last().is_some_and(|b| *b != b'\n') {
+            content.push(b'\n');
+        }
+        content.extend_from_slice(line);
+    };
+
+    let missing_line =
+        || "Diff does not match the file contents, refresh and try again".to_string();
+
+    for hunk_idx in 0..patch.num_hunks() {
+        let (hunk, _) = patch
+            .hunk(hunk_idx)
+            .map_err(|e| format!("Failed to get hunk: {}", e))?;
+
+        // old_start is 1-based, except that a pure insertion starts after that line
+        let hunk_start = if hunk.old_lines() == 0 {
+            hunk.old_start() as usize
+        } else {
+            hunk.old_start().saturating_sub(1) as usize
+        };
+
+        while next_old < hunk_start {
+            let line = base_lines.get(next_old).ok_or_else(missing_line)?;
+            push_line(&mut content, line);
+            next_old += 1;
+        }
+
+        for line_idx in 0..patch.num_lines_in_hunk(hunk_idx).unwrap_or(0) {
+            let line = patch
+                .line_in_hunk(hunk_idx, line_idx)
+                .map_err(|e| format!("Failed to get line: {}", e))?;
+
+            match line.origin() {
+                ' ' => {
+                    let old_line = base_lines.get(next_old).ok_or_else(missing_line)?;
+                    push_line(&mut content, old_line);
+                    next_old += 1;
+                }
+                '-' => {
+                    let old_line = base_lines.get(next_old).ok_or_else(missing_line)?;
+                    total += 1;
+                    if is_selected(&line) {
+                        selected += 1;
+                    } else {
+                        push_line(&mut content, old_line);
+                    }
+                    next_old += 1;
+                }
+                '+' => {
+                    total += 1;
+                    if is_selected(&line) {
+                        selected += 1;
+                        push_line(&mut content, line.content());
+                    }
+                }
+                _ => {} // "No newline at end of file" markers
+            }
+        }
+    }
+
+    while let Some(line) = base_lines.get(next_old) {
+        push_line(&mut content, line);
+        next_old += 1;
+    }
+
+    Ok(PartialContent {
+        content,
+        selected,
+        total,
+    })
+}
+
+/// Checks whether a diff line matches one of the selections.
+/// `line_type`, `old_line_no` and `new_line_no` describe the line as the UI saw it.
+fn is_line_selected(
+    selections: &[DiffLineSelection],
+    line_type: LineType,
+    old_line_no: Option<u32>,
+    new_line_no: Option<u32>,
+) -> bool {
+    selections.iter().any(|selection| {
+        selection.line_type == line_type
+            && match line_type {
+                LineType::Addition => selection.new_line_no == new_line_no,
+                LineType::Deletion => selection.old_line_no == old_line_no,
+                LineType::Context => false,
+            }
+    })
+}
+
+/// Writes `content` as the index entry for `file_path`, keeping the given file mode
+fn write_index_content(
+    index: &mut git2::Index,
+    file_path: &str,
+    mode: u32,
+    content: &[u8],
+) -> Result<(), String> {
+    let index_entry = git2::IndexEntry {
+        ctime: git2::IndexTime::new(0, 0),
+        mtime: git2::IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode,
+        uid: 0,
+        gid: 0,
+        file_size: content.len() as u32,
+        id: git2::Oid::zero(),
+        flags: 0,
+        flags_extended: 0,
+        path: file_path.as_bytes().to_vec(),
+    };
+
+    index
+        .add_frombuffer(&index_entry, content)
+        .map_err(|e| format!("Failed to update index entry: {}", e))?;
+
+    index
+        .write()
+        .map_err(|e| format!("Failed to write index: {}", e))
+}
+
+/// Checks that a line selection matched the current diff
+fn check_line_selection(
+    partial: &PartialContent,
+    selections: &[DiffLineSelection],
+    file_path: &str,
+) -> Result<(), String> {
+    if selections.is_empty() {
+        return Err("At least one line must be selected".to_string());
+    }
+
+    if partial.selected != selections.len() {
+        return Err(format!(
+            "Selected lines no longer match the diff of '{}', refresh and try again",
+            file_path
+        ));
+    }
+
+    Ok(())
+}
+
+/// Stages only the selected addition/deletion lines of a file's unstaged changes.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Relative path to the file within the repository
+/// * `selections` - Lines to stage, taken from `get_unstaged_file_diff`
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn stage_lines(
+    repo_path: &str,
+    file_path: &str,
+    selections: &[DiffLineSelection],
+) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let diff = repo
+        .diff_index_to_workdir(None, Some(&mut unstaged_diff_options(file_path, false)))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    let delta = diff
+        .get_delta(0)
+        .ok_or_else(|| format!("File '{}' has no unstaged changes", file_path))?;
+
+    if delta.new_file().is_binary() || delta.old_file().is_binary() {
+        return Err(format!("Cannot stage lines of binary file '{}'", file_path));
+    }
+
+    let patch = git2::Patch::from_diff(&diff, 0)
+        .map_err(|e| format!("Failed to create patch: {}", e))?
+        .ok_or_else(|| "Failed to create patch for file".to_string())?;
+
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    // The old side of the diff is the index blob (or nothing for untracked files)
+    let (base, mode) = match index.get_path(std::path::Path::new(file_path), 0) {
+        Some(entry) => {
+            let blob = repo
+                .find_blob(entry.id)
+                .map_err(|e| format!("Failed to find blob: {}", e))?;
+            (blob.content().to_vec(), entry.mode)
+        }
+        None => (Vec::new(), u32::from(delta.new_file().mode())),
+    };
+
+    let partial = apply_selected_lines(&base, &patch, |line| {
+        let line_type = match line.origin() {
+            '+' => LineType::Addition,
+            _ => LineType::Deletion,
+        };
+        is_line_selected(selections, line_type, line.old_lineno(), line.new_lineno())
+    })?;
+
+    check_line_selection(&partial, selections, file_path)?;
+
+    // Selecting every change is the same as staging the whole file
+    if partial.selected == partial.total {
+        return stage_file(repo_path, file_path);
+    }
+
+    write_index_content(&mut index, file_path, mode, &partial.content)
+}
+
+/// Unstages only the selected addition/deletion lines of a file's staged changes.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Relative path to the file within the repository
+/// * `selections` - Lines to unstage, taken from `get_staged_file_diff`
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn unstage_lines(
+    repo_path: &str,
+    file_path: &str,
+    selections: &[DiffLineSelection],
+) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let head_tree = match repo.head() {
+        Ok(head) => Some(
+            head.peel_to_tree()
+                .map_err(|e| format!("Failed to get HEAD tree: {}", e))?,
+        ),
+        Err(_) => None, // No commits yet
+    };
+
+    // Reversed (index -> HEAD) so that the old side of the diff is the index blob
+    let diff = repo
+        .diff_tree_to_index(
+            head_tree.as_ref(),
+            None,
+            Some(&mut staged_diff_options(file_path, true)),
+        )
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+
+    let delta = diff
+        .get_delta(0)
+        .ok_or_else(|| format!("File '{}' has no staged changes", file_path))?;
+
+    if delta.new_file().is_binary() || delta.old_file().is_binary() {
+        return Err(format!(
+            "Cannot unstage lines of binary file '{}'",
+            file_path
+        ));
+    }
+
+    let patch = git2::Patch::from_diff(&diff, 0)
+        .map_err(|e| format!("Failed to create patch: {}", e))?
+        .ok_or_else(|| "Failed to create patch for file".to_string())?;
+
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    let (base, mode) = match index.get_path(std::path::Path::new(file_path), 0) {
+        Some(entry) => {
+            let blob = repo
+                .find_blob(entry.id)
+                .map_err(|e| format!("Failed to find blob: {}", e))?;
+            (blob.content().to_vec(), entry.mode)
+        }
+        // Staged deletion - the file only exists in HEAD
+        None => (Vec::new(), u32::from(delta.new_file().mode())),
+    };
+
+    // Map each reversed line back to how it appears in the staged (HEAD -> index) diff
+    let partial = apply_selected_lines(&base, &patch, |line| {
+        let line_type = match line.origin() {
+            '+' => LineType::Deletion,
+            _ => LineType::Addition,
+        };
+        is_line_selected(selections, line_type, line.new_lineno(), line.old_lineno())
+    })?;
+
+    check_line_selection(&partial, selections, file_path)?;
+
+    // Selecting every change is the same as unstaging the whole file
+    if partial.selected == partial.total {
+        if head_tree.is_none() {
+            // No commits yet, so the file only exists in the index
+            index
+                .remove_path(std::path::Path::new(file_path))
+                .map_err(|e| format!("Failed to unstage file: {}", e))?;
+            return index
+                .write()
+                .map_err(|e| format!("Failed to write index: {}", e));
+        }
+        return unstage_file(repo_path, file_path);
+    }
+
+    write_index_content(&mut index, file_path, mode, &partial.content)
+}
+
+/// Moves HEAD to a new commit. When HEAD points to a branch (including an
+/// unborn one) the branch is updated, otherwise HEAD is detached at the commit.
+fn update_head(repo: &Repository, oid: git2::Oid, reflog_message: &str) -> Result<(), String> {
+    let head = repo
+        .find_reference("HEAD")
+        .map_err(|e| format!("Failed to get HEAD: {}", e))?;
+
+    match head.symbolic_target() {
+        Some(branch_ref) => {
+            repo.reference(branch_ref, oid, true, reflog_message)
+                .map_err(|e| format!("Failed to update branch: {}", e))?;
+        }
+        None => {
+            repo.set_head_detached(oid)
+                .map_err(|e| format!("Failed to update HEAD: {}", e))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates a temporary directory only the current user can access, for
+/// short-lived files. It is removed when dropped.
+fn private_temp_dir(prefix: &str) -> std::io::Result<tempfile::TempDir> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(prefix);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o700));
+    }
+
+    builder.tempdir()
+}
+
+/// Writes a file that must not exist yet and that only the current user can read
+fn write_private_file(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)
+}
+
+/// Signs data with an OpenPGP or X.509 key through gpg/gpgsm, like `git commit -S`
+fn sign_with_gpg(program: &str, key: &str, data: &str) -> Result<String, String> {
+    let output = run_with_input(
+        std::process::Command::new(program).args(["--status-fd=2", "-bsau", key]),
+        data.as_bytes(),
+    )
+    .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    let status = String::from_utf8_lossy(&output.stderr);
+
+    // git only trusts the signature when gpg reports that it created one
+    if !output.status.success() || !status.contains("[GNUPG:] SIG_CREATED ") {
+        return Err(format!("Failed to sign commit: {}", status.trim()));
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| "Signature is not valid UTF-8".to_string())
+}
+
+/// Signs data with an SSH key through ssh-keygen, like `git commit -S` with gpg.format=ssh
+fn sign_with_ssh(program: &str, signing_key: &str, data: &str) -> Result<String, String> {
+    // The directory and everything ssh-keygen writes into it are removed on drop
+    let temp_dir = private_temp_dir("recap-ssh-sign")
+        .map_err(|e| format!("Failed to create temporary directory: {}", e))?;
+    let data_path = temp_dir.path().join("buffer");
+    let signature_path = data_path.with_extension("sig");
+
+    write_private_file(&data_path, data.as_bytes())
+        .map_err(|e| format!("Failed to write signing buffer: {}", e))?;
+
+    let mut command = std::process::Command::new(program);
+    command.args(["-Y", "sign", "-n", "git", "-f"]);
+
+    // A literal public key signs through ssh-agent, anything else is a key file
+    let literal_key = signing_key
+        .strip_prefix("key::")
+        .or_else(|| signing_key.starts_with("ssh-").then_some(signing_key));
+    match literal_key {
+        Some(public_key) => {
+            let key_path = temp_dir.path().join("key.pub");
+            write_private_file(&key_path, public_key.as_bytes())
+                .map_err(|e| format!("Failed to write signing key: {}", e))?;
+            command.arg(&key_path).arg("-U");
+        }
+        None => {
+            command.arg(expand_home(signing_key));
+        }
+    }
+
+    let output = command
+        .arg(&data_path)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to sign commit: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    std::fs::read_to_string(&signature_path).map_err(|e| format!("Failed to read signature: {}", e))
+}
+
+/// Expands a leading "~/" to the user's home directory
+fn expand_home(path: &str) -> std::path::PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => std::path::PathBuf::from(home).join(rest),
+        _ => std::path::PathBuf::from(path),
+    }
+}
+
+/// Signs a commit buffer using gpg.format, user.signingkey and the configured
+/// signing program, the same way git does for commit.gpgsign
+fn sign_commit_buffer(
+    config: &git2::Config,
+    committer: &git2::Signature,
+    buffer: &str,
+) -> Result<String, String> {
+    let format = config
+        .get_string("gpg.format")
+        .unwrap_or_else(|_| "openpgp".to_string());
+    let signing_key = config.get_string("user.signingkey").ok();
+
+    match format.as_str() {
+        "openpgp" | "x509" => {
+            let program = config
+                .get_string(&format!("gpg.{}.program", format))
+                .or_else(|_| {
+                    if format == "openpgp" {
+                        config.get_string("gpg.program")
+                    } else {
+                        Ok("gpgsm".to_string())
+                    }
+                })
+                .unwrap_or_else(|_| "gpg".to_string());
+
+            // Without a configured key gpg picks one matching the committer identity
+            let key = signing_key.unwrap_or_else(|| {
+                format!(
+                    "{} <{}>",
+                    committer.name().unwrap_or(""),
+                    committer.email().unwrap_or("")
+                )
+            });
+
+            sign_with_gpg(&program, &key, buffer)
+        }
+        "ssh" => {
+            let program = config
+                .get_string("gpg.ssh.program")
+                .unwrap_or_else(|_| "ssh-keygen".to_string());
+            let key = signing_key.ok_or_else(|| {
+                "user.signingkey must be set to sign commits with SSH".to_string()
+            })?;
+
+            sign_with_ssh(&program, &key, buffer)
+        }
+        other => Err(format!("Unsupported gpg.format '{}'", other)),
+    }
+}
+
+/// Writes a commit object and moves HEAD to it.
+/// The commit is signed when commit.gpgsign is enabled.
+fn write_commit(
+    repo: &Repository,
+    author: &git2::Signature,
+    committer: &git2::Signature,
+    message: &str,
+    tree: &git2::Tree,
+    parents: &[&git2::Commit],
+    reflog_message: &str,
+) -> Result<git2::Oid, String> {
+    let oid = create_commit_object(repo, author, committer, message, tree, parents)?;
+
+    update_head(repo, oid, reflog_message)?;
+
+    Ok(oid)
+}
+
+/// Writes a commit object without moving any reference.
+/// The commit is signed when commit.gpgsign is enabled.
+fn create_commit_object(
+    repo: &Repository,
+    author: &git2::Signature,
+    committer: &git2::Signature,
+    message: &str,
+    tree: &git2::Tree,
+    parents: &[&git2::Commit],
+) -> Result<git2::Oid, String> {
+    let config = repo
+        .config()
+        .map_err(|e| format!("Failed to get config: {}", e))?;
+
+    let oid = if config.get_bool("commit.gpgsign").unwrap_or(false) {
+        let buffer = repo
+            .commit_create_buffer(author, committer, message, tree, parents)
+            .map_err(|e| format!("Failed to create commit: {}", e))?;
+        let buffer = buffer
+            .as_str()
+            .ok_or_else(|| "Commit buffer is not valid UTF-8".to_string())?;
+
+        let signature = sign_commit_buffer(&config, committer, buffer)?;
+
+        repo.commit_signed(buffer, &signature, None)
+            .map_err(|e| format!("Failed to create signed commit: {}", e))?
+    } else {
+        repo.commit(None, author, committer, message, tree, parents)
+            .map_err(|e| format!("Failed to create commit: {}", e))?
+    };
+
+    Ok(oid)
+}
+
+/// Output of a client-side git hook
+#[derive(Debug, Clone, Serialize)]
+pub struct HookOutput {
+    /// Name of the hook (e.g. "pre-commit")
+    pub name: String,
+    /// Whether the hook exited successfully
+    pub success: bool,
+    /// Exit code of the hook, None if it was terminated by a signal
+    pub exit_code: Option<i32>,
+    /// Captured standard output
+    pub stdout: String,
+    /// Captured standard error
+    pub stderr: String,
+}
+
+/// Result of creating a commit, including the output of the hooks that ran
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitResult {
+    /// The created commit, None if a hook rejected the commit
+    pub commit: Option<Commit>,
+    /// Output of each hook that ran, in execution order
+    pub hooks: Vec<HookOutput>,
+}
+
+/// Gets the directory shared by all worktrees of a repository (the main .git directory)
+fn common_dir(repo: &Repository) -> std::path::PathBuf {
+    if repo.is_worktree() {
+        // Linked worktrees point to the shared directory with a "commondir" file
+        if let Ok(commondir) = std::fs::read_to_string(repo.path().join("commondir")) {
+            return repo.path().join(commondir.trim());
+        }
+    }
+
+    repo.path().to_path_buf()
+}
+
+/// Gets the directory hooks are run from, respecting core.hooksPath
+fn hooks_dir(repo: &Repository) -> std::path::PathBuf {
+    let configured = repo
+        .config()
+        .ok()
+        .and_then(|config| config.get_path("core.hooksPath").ok());
+
+    match configured {
+        // Relative hooks paths are relative to the top of the working tree
+        Some(path) if path.is_relative() => {
+            repo.workdir().unwrap_or_else(|| repo.path()).join(path)
+        }
+        Some(path) => path,
+        None => common_dir(repo).join("hooks"),
+    }
+}
+
+/// Runs a client-side hook if it exists and is executable.
+///
+/// # Returns
+/// The hook's output, None if the hook isn't installed, or an error if it couldn't be started
+fn run_hook(repo: &Repository, name: &str, args: &[&str]) -> Result<Option<HookOutput>, String> {
+    let hook_path = hooks_dir(repo).join(name);
+
+    if !hook_path.is_file() {
+        return Ok(None);
+    }
+
+    // Like git, skip hooks that aren't executable
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = std::fs::metadata(&hook_path)
+            .map(|m| m.permissions().mode())
+            .unwrap_or(0);
+        if mode & 0o111 == 0 {
+            return Ok(None);
+        }
+    }
+
+    // Hooks run from the top of the working tree, the way git runs them
+    let cwd = repo.workdir().unwrap_or_else(|| repo.path());
+
+    let output = std::process::Command::new(&hook_path)
+        .args(args)
+        .current_dir(cwd)
+        .env("GIT_INDEX_FILE", repo.path().join("index"))
+        .env("GIT_EDITOR", ":")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run {} hook: {}", name, e))?;
+
+    Ok(Some(HookOutput {
+        name: name.to_string(),
+        success: output.status.success(),
+        exit_code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    }))
+}
+
+/// Runs a hook and records its output.
+///
+/// # Returns
+/// false if the hook ran and failed
+fn run_hook_recorded(
+    repo: &Repository,
+    name: &str,
+    args: &[&str],
+    hooks: &mut Vec<HookOutput>,
+) -> Result<bool, String> {
+    match run_hook(repo, name, args)? {
+        Some(output) => {
+            let success = output.success;
+            hooks.push(output);
+            Ok(success)
+        }
+        None => Ok(true),
+    }
+}
+
+/// Reads the commits being merged from MERGE_HEAD
+fn read_merge_heads(repo: &Repository) -> Result<Vec<git2::Commit<'_>>, String> {
+    let content = std::fs::read_to_string(repo.path().join("MERGE_HEAD")).unwrap_or_default();
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            git2::Oid::from_str(line.trim())
+                .and_then(|oid| repo.find_commit(oid))
+                .map_err(|e| format!("Failed to read MERGE_HEAD: {}", e))
+        })
+        .collect()
+}
+
+/// Creates a commit from the current index on HEAD.
+///
+/// Uses the configured user.name/user.email. When `amend` is set the last
+/// commit is replaced, keeping its author and parents. The pre-commit,
+/// prepare-commit-msg, commit-msg and post-commit hooks run like they do for
+/// `git commit`; if one of the first three fails, no commit is created.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `message` - The commit message
+/// * `amend` - Whether to amend the last commit instead of creating a new one
+///
+/// # Returns
+/// A CommitResult with the created commit and hook output, or an error message
+pub fn create_commit(repo_path: &str, message: &str, amend: bool) -> Result<CommitResult, String> {
+    let repo = open_workdir_repo(repo_path)?;
+    ensure_no_rebase(&repo, "commit")?;
+
+    // Strip comments and surrounding whitespace like `git commit` does
+    let cleaned_message = git2::message_prettify(message, Some(b'#'))
+        .map_err(|e| format!("Failed to clean up commit message: {}", e))?;
+    if cleaned_message.trim().is_empty() {
+        return Err("Commit message cannot be empty".to_string());
+    }
+
+    // No HEAD yet means this is the initial commit
+    let head_commit = match repo.head() {
+        Ok(head) => Some(
+            head.peel_to_commit()
+                .map_err(|e| format!("Failed to get HEAD commit: {}", e))?,
+        ),
+        Err(_) => None,
+    };
+
+    if amend && head_commit.is_none() {
+        return Err("Nothing to amend: the repository has no commits".to_string());
+    }
+
+    // Committing concludes an in-progress merge, revert or cherry-pick
+    let state = repo.state();
+    let in_progress = matches!(
+        state,
+        git2::RepositoryState::Merge
+            | git2::RepositoryState::Revert
+            | git2::RepositoryState::CherryPick
+    );
+    if amend && in_progress {
+        return Err("Cannot amend while a merge, revert or cherry-pick is in progress".to_string());
+    }
+    let merge_heads = if state == git2::RepositoryState::Merge {
+        read_merge_heads(&repo)?
+    } else {
+        Vec::new()
+    };
+
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    let nothing_staged = |index: &mut git2::Index| -> Result<bool, String> {
+        if index.has_conflicts() {
+            return Err("Cannot commit: there are unresolved conflicts".to_string());
+        }
+        let tree_id = index
+            .write_tree()
+            .map_err(|e| format!("Failed to write tree: {}", e))?;
+        Ok(match &head_commit {
+            Some(head_commit) => head_commit.tree_id() == tree_id,
+            None => index.is_empty(),
+        })
+    };
+
+    // A merge commit records the merge even when its tree matches HEAD
+    if !amend && merge_heads.is_empty() && nothing_staged(&mut index)? {
+        return Err("Nothing to commit: no changes are staged".to_string());
+    }
+
+    let mut hooks = Vec::new();
+
+    if !run_hook_recorded(&repo, "pre-commit", &[], &mut hooks)? {
+        return Ok(CommitResult {
+            commit: None,
+            hooks,
+        });
+    }
+
+    // pre-commit may have changed the index (e.g. formatters that re-stage files)
+    index
+        .read(true)
+        .map_err(|e| format!("Failed to read index: {}", e))?;
+    if !amend && merge_heads.is_empty() && nothing_staged(&mut index)? {
+        return Err("Nothing to commit: no changes are staged".to_string());
+    }
+
+    // The message hooks edit the message through COMMIT_EDITMSG
+    let message_path = repo.path().join("COMMIT_EDITMSG");
+    std::fs::write(&message_path, &cleaned_message)
+        .map_err(|e| format!("Failed to write commit message: {}", e))?;
+    let message_path_str = message_path.to_string_lossy().to_string();
+
+    // Like git, an amend passes the commit being amended as the message source
+    let amended_sha = head_commit
+        .as_ref()
+        .map(|c| c.id().to_string())
+        .unwrap_or_default();
+    let prepare_args = if amend {
+        vec![message_path_str.as_str(), "commit", amended_sha.as_str()]
+    } else {
+        vec![message_path_str.as_str(), "message"]
+    };
+
+    if !run_hook_recorded(&repo, "prepare-commit-msg", &prepare_args, &mut hooks)?
+        || !run_hook_recorded(
+            &repo,
+            "commit-msg",
+            &[message_path_str.as_str()],
+            &mut hooks,
+        )?
+    {
+        return Ok(CommitResult {
+            commit: None,
+            hooks,
+        });
+    }
+
+    let hook_message = std::fs::read_to_string(&message_path)
+        .map_err(|e| format!("Failed to read commit message: {}", e))?;
+    let message = git2::message_prettify(hook_message, Some(b'#'))
+        .map_err(|e| format!("Failed to clean up commit message: {}", e))?;
+    if message.trim().is_empty() {
+        return Err("Commit message cannot be empty".to_string());
+    }
+    let subject = message.lines().next().unwrap_or("").to_string();
+
+    let tree_id = index
+        .write_tree()
+        .map_err(|e| format!("Failed to write tree: {}", e))?;
+    let tree = repo
+        .find_tree(tree_id)
+        .map_err(|e| format!("Failed to find tree: {}", e))?;
+
+    let committer = repo
+        .signature()
+        .map_err(|e| format!("Failed to get signature from user.name/user.email: {}", e))?;
+
+    let oid = match (&head_commit, amend) {
+        (Some(head_commit), true) => {
+            let parents: Vec<git2::Commit> = head_commit.parents().collect();
+            let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+            let author = head_commit.author();
+
+            write_commit(
+                &repo,
+                &author,
+                &committer,
+                &message,
+                &tree,
+                &parent_refs,
+                &format!("commit (amend): {}", subject),
+            )?
+        }
+        _ => {
+            let parents: Vec<&git2::Commit> =
+                head_commit.iter().chain(merge_heads.iter()).collect();
+            let reflog_prefix = if head_commit.is_none() {
+                "commit (initial)"
+            } else if !merge_heads.is_empty() {
+                "commit (merge)"
+            } else {
+                "commit"
+            };
+
+            // A cherry-picked commit keeps the original author
+            let author = if state == git2::RepositoryState::CherryPick {
+                repo.revparse_single("CHERRY_PICK_HEAD")
+                    .and_then(|object| object.peel_to_commit())
+                    .map(|picked| picked.author().to_owned())
+                    .unwrap_or_else(|_| committer.clone())
+            } else {
+                committer.clone()
+            };
+
+            write_commit(
+                &repo,
+                &author,
+                &committer,
+                &message,
+                &tree,
+                &parents,
+                &format!("{}: {}", reflog_prefix, subject),
+            )?
+        }
+    };
+
+    if in_progress {
+        repo.cleanup_state()
+            .map_err(|e| format!("Failed to clean up repository state: {}", e))?;
+    }
+
+    // post-commit can't affect the outcome, its output is only reported
+    run_hook_recorded(&repo, "post-commit", &[], &mut hooks)?;
+
+    let commit = repo
+        .find_commit(oid)
+        .map_err(|e| format!("Failed to find commit: {}", e))?;
+
+    // Verify the new commit's signature so a misconfigured signing key shows up right away
+    let mut summary = commit_summary(&repo, &commit);
+    (summary.signature_status, summary.signer) = verify_commit_signature(&repo, oid);
+
+    Ok(CommitResult {
+        commit: Some(summary),
+        hooks,
+    })
+}
+
+/// Gets the full message of the HEAD commit, e.g. to pre-fill an amend
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// The commit message or an error message
+pub fn get_last_commit_message(repo_path: &str) -> Result<String, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    let head = repo
+        .head()
+        .map_err(|e| format!("Failed to get HEAD: {}", e))?;
+    let head_commit = head
+        .peel_to_commit()
+        .map_err(|e| format!("Failed to get HEAD commit: {}", e))?;
+
+    Ok(head_commit.message().unwrap_or("").to_string())
+}
+
+/// How a file conflicts after a merge, revert or cherry-pick
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum ConflictType {
+    /// Both sides changed the file
+    BothModified,
+    /// Both sides added the file with different contents
+    BothAdded,
+    /// We deleted the file, they changed it
+    DeletedByUs,
+    /// They deleted the file, we changed it
+    DeletedByThem,
+    /// Only our side has the file (e.g. a rename/delete conflict)
+    AddedByUs,
+    /// Only their side has the file (e.g. a rename/delete conflict)
+    AddedByThem,
+}
+
+/// A file left with a conflict in the index
+#[derive(Debug, Clone, Serialize)]
+pub struct FileConflict {
+    /// Path to the file
+    pub path: String,
+    /// How the two sides conflict
+    pub conflict_type: ConflictType,
+}
+
+/// Outcome of reverting or cherry-picking one or more commits
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyCommitsResult {
+    /// SHAs of the commits created (only when auto-committing)
+    pub created: Vec<String>,
+    /// SHAs of the selected commits that were applied cleanly
+    pub applied: Vec<String>,
+    /// SHA of the commit whose changes conflicted, if any
+    pub stopped_at: Option<String>,
+    /// Files left with conflicts by `stopped_at`
+    pub conflicts: Vec<FileConflict>,
+    /// SHAs of the selected commits not applied because of the conflict
+    pub remaining: Vec<String>,
+    /// Suggested message for committing the uncommitted result, if any
+    pub message: Option<String>,
+}
+
+/// Describes an index conflict as a FileConflict
+fn file_conflict(conflict: &git2::IndexConflict) -> FileConflict {
+    let conflict_type = match (&conflict.ancestor, &conflict.our, &conflict.their) {
+        (_, Some(_), Some(_)) if conflict.ancestor.is_some() => ConflictType::BothModified,
+        (_, Some(_), Some(_)) => ConflictType::BothAdded,
+        (Some(_), None, Some(_)) => ConflictType::DeletedByUs,
+        (Some(_), Some(_), None) => ConflictType::DeletedByThem,
+        (_, Some(_), None) => ConflictType::AddedByUs,
+        _ => ConflictType::AddedByThem,
+    };
+
+    let path = [&conflict.our, &conflict.their, &conflict.ancestor]
+        .into_iter()
+        .flatten()
+        .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+        .next()
+        .unwrap_or_default();
+
+    FileConflict {
+        path,
+        conflict_type,
+    }
+}
+
+/// Reads the conflicts of an index as FileConflicts
+fn index_conflicts(index: &git2::Index) -> Result<Vec<FileConflict>, String> {
+    let mut conflicts = Vec::new();
+
+    for conflict in index
+        .conflicts()
+        .map_err(|e| format!("Failed to read conflicts: {}", e))?
+    {
+        let conflict = conflict.map_err(|e| format!("Failed to read conflict: {}", e))?;
+        conflicts.push(file_conflict(&conflict));
+    }
+
+    Ok(conflicts)
+}
+
+/// A block of conflict markers in a conflicted file
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ConflictRegion {
+    /// Line number (1-based) of the `<<<<<<<` marker
+    pub start_line: u32,
+    /// Line number (1-based) of the `>>>>>>>` marker
+    pub end_line: u32,
+    /// Label after the `<<<<<<<` marker (e.g. "HEAD")
+    pub ours_label: String,
+    /// Label after the `>>>>>>>` marker (e.g. the commit being merged)
+    pub theirs_label: String,
+    /// Our lines
+    pub ours: Vec<String>,
+    /// Common ancestor lines, present with the diff3 conflict style
+    pub base: Option<Vec<String>>,
+    /// Their lines
+    pub theirs: Vec<String>,
+}
+
+/// A conflicted file with the three versions needed for a three-way view
+#[derive(Debug, Clone, Serialize)]
+pub struct ConflictFile {
+    /// Path to the file
+    pub path: String,
+    /// How the two sides conflict
+    pub conflict_type: ConflictType,
+    /// Whether any version of the file is binary (contents are then omitted)
+    pub is_binary: bool,
+    /// Contents in the common ancestor, None if absent there
+    pub ancestor: Option<String>,
+    /// Contents on our side (HEAD), None if absent there
+    pub ours: Option<String>,
+    /// Contents on their side, None if absent there
+    pub theirs: Option<String>,
+    /// Conflict marker blocks in the working directory file
+    pub regions: Vec<ConflictRegion>,
+}
+
+/// Returns the label of a conflict marker line such as `<<<<<<< HEAD`, or None if
+/// the line isn't that marker
+fn conflict_marker(line: &str, marker: char) -> Option<&str> {
+    let rest = line.strip_prefix(&marker.to_string().repeat(7))?;
+    if rest.is_empty() {
+        Some("")
+    } else {
+        rest.strip_prefix(' ')
+    }
+}
+
+/// Parses the conflict marker blocks of a file, in both the merge and diff3 styles
+fn parse_conflict_regions(content: &str) -> Vec<ConflictRegion> {
+    enum Section {
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let mut regions = Vec::new();
+    let mut current: Option<(ConflictRegion, Section)> = None;
+
+    for (index, line) in content.lines().enumerate() {
+        let line_no = index as u32 + 1;
+
+        current = match current.take() {
+            None => conflict_marker(line, '<').map(|label| {
+                let region = ConflictRegion {
+                    start_line: line_no,
+                    end_line: line_no,
+                    ours_label: label.to_string(),
+                    theirs_label: String::new(),
+                    ours: Vec::new(),
+                    base: None,
+                    theirs: Vec::new(),
+                };
+                (region, Section::Ours)
+            }),
+            Some((mut region, Section::Ours)) => {
+                if conflict_marker(line, '|').is_some() {
+                    region.base = Some(Vec::new());
+                    Some((region, Section::Base))
+                } else if conflict_marker(line, '=') == Some("") {
+                    Some((region, Section::Theirs))
+                } else {
+                    region.ours.push(line.to_string());
+                    Some((region, Section::Ours))
+                }
+            }
+            Some((mut region, Section::Base)) => {
+                if conflict_marker(line, '=') == Some("") {
+                    Some((region, Section::Theirs))
+                } else {
+                    region
+                        .base
+                        .get_or_insert_with(Vec::new)
+                        .push(line.to_string());
+                    Some((region, Section::Base))
+                }
+            }
+            Some((mut region, Section::Theirs)) => {
+                if let Some(label) = conflict_marker(line, '>') {
+                    region.end_line = line_no;
+                    region.theirs_label = label.to_string();
+                    regions.push(region);
+                    None
+                } else {
+                    region.theirs.push(line.to_string());
+                    Some((region, Section::Theirs))
+                }
+            }
+        };
+    }
+
+    regions
+}
+
+/// Operation the repository is in the middle of
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum RepoState {
+    /// No operation in progress
+    Clean,
+    Merge,
+    Revert,
+    CherryPick,
+    Rebase,
+    /// Applying patches with `git am`
+    ApplyMailbox,
+    Bisect,
+}
+
+impl From<git2::RepositoryState> for RepoState {
+    fn from(state: git2::RepositoryState) -> Self {
+        use git2::RepositoryState as S;
+        match state {
+            S::Clean => RepoState::Clean,
+            S::Merge => RepoState::Merge,
+            S::Revert | S::RevertSequence => RepoState::Revert,
+            S::CherryPick | S::CherryPickSequence => RepoState::CherryPick,
+            S::Rebase | S::RebaseInteractive | S::RebaseMerge => RepoState::Rebase,
+            S::ApplyMailbox | S::ApplyMailboxOrRebase => RepoState::ApplyMailbox,
+            S::Bisect => RepoState::Bisect,
+        }
+    }
+}
+
+/// The operation in progress and what it left behind
+#[derive(Debug, Clone, Serialize)]
+pub struct RepoStateInfo {
+    /// Operation in progress
+    pub state: RepoState,
+    /// SHA of the commit being merged, reverted or cherry-picked, if any
+    pub incoming: Option<String>,
+    /// Prepared commit message (MERGE_MSG), if any
+    pub message: Option<String>,
+    /// Files with unresolved conflicts
+    pub conflicts: Vec<FileConflict>,
+}
+
+/// Gets the operation the repository is in the middle of (merge, revert,
+/// cherry-pick, rebase...), along with its unresolved conflicts
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A RepoStateInfo struct or an error message
+pub fn get_repo_state(repo_path: &str) -> Result<RepoStateInfo, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    // git only knows the step being replayed; the todo list says which commit it is
+    if rebase_in_progress(&repo) {
+        let stopped = load_rebase_state(&repo)?.stopped;
+        let index = repo
+            .index()
+            .map_err(|e| format!("Failed to get index: {}", e))?;
+        return Ok(RepoStateInfo {
+            state: RepoState::Rebase,
+            incoming: stopped.map(|item| item.commit),
+            message: None,
+            conflicts: index_conflicts(&index)?,
+        });
+    }
+
+    let state = RepoState::from(repo.state());
+    let incoming = match state {
+        RepoState::Merge => Some("MERGE_HEAD"),
+        RepoState::Revert => Some("REVERT_HEAD"),
+        RepoState::CherryPick => Some("CHERRY_PICK_HEAD"),
+        _ => None,
+    }
+    .and_then(|name| repo.revparse_single(name).ok())
+    .map(|object| object.id().to_string());
+
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    Ok(RepoStateInfo {
+        state,
+        incoming,
+        message: repo.message().ok(),
+        conflicts: index_conflicts(&index)?,
+    })
+}
+
+/// Gets every conflicted file with its ancestor, our and their contents, and the
+/// conflict marker blocks in its working directory version
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A vector of ConflictFile structs or an error message
+pub fn get_conflicts(repo_path: &str) -> Result<Vec<ConflictFile>, String> {
+    let repo = open_workdir_repo(repo_path)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let conflicts = index
+        .conflicts()
+        .map_err(|e| format!("Failed to read conflicts: {}", e))?;
+
+    let mut files = Vec::new();
+    for conflict in conflicts {
+        let conflict = conflict.map_err(|e| format!("Failed to read conflict: {}", e))?;
+        let summary = file_conflict(&conflict);
+        let blobs = [&conflict.ancestor, &conflict.our, &conflict.their].map(|entry| {
+            entry
+                .as_ref()
+                .map(|entry| repo.find_blob(entry.id))
+                .transpose()
+        });
+        let mut versions = Vec::new();
+        for blob in blobs {
+            versions.push(blob.map_err(|e| format!("Failed to read conflict: {}", e))?);
+        }
+
+        let is_binary = versions.iter().flatten().any(|blob| blob.is_binary());
+        let [ancestor, ours, theirs] = [0, 1, 2].map(|i| {
+            versions[i]
+                .as_ref()
+                .filter(|_| !is_binary)
+                .map(|blob| String::from_utf8_lossy(blob.content()).to_string())
+        });
+
+        let regions = if is_binary {
+            Vec::new()
+        } else {
+            std::fs::read(workdir.join(&summary.path))
+                .map(|content| parse_conflict_regions(&String::from_utf8_lossy(&content)))
+                .unwrap_or_default()
+        };
+
+        files.push(ConflictFile {
+            path: summary.path,
+            conflict_type: summary.conflict_type,
+            is_binary,
+            ancestor,
+            ours,
+            theirs,
+            regions,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Which version of a conflicted file to keep
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum ConflictResolution {
+    /// Keep our version (HEAD)
+    Ours,
+    /// Keep their version
+    Theirs,
+    /// Keep the lines of both sides, ours first, without conflict markers
+    Union,
+}
+
+/// Finds the conflict for a path in the index
+fn find_conflict(index: &git2::Index, path: &str) -> Result<git2::IndexConflict, String> {
+    for conflict in index
+        .conflicts()
+        .map_err(|e| format!("Failed to read conflicts: {}", e))?
+    {
+        let conflict = conflict.map_err(|e| format!("Failed to read conflict: {}", e))?;
+        if file_conflict(&conflict).path == path {
+            return Ok(conflict);
+        }
+    }
+
+    Err(format!("'{}' has no unresolved conflict", path))
+}
+
+/// Writes the resolved contents of a file to the working directory and stages it,
+/// which clears its conflict. None resolves the conflict by deleting the file.
+fn stage_resolution(
+    repo: &Repository,
+    index: &mut git2::Index,
+    path: &str,
+    content: Option<&[u8]>,
+) -> Result<(), String> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| "Repository has no working directory".to_string())?;
+    let full_path = workdir.join(path);
+
+    match content {
+        Some(content) => {
+            if let Some(parent) = full_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create directory: {}", e))?;
+            }
+            std::fs::write(&full_path, content)
+                .map_err(|e| format!("Failed to write file: {}", e))?;
+            index
+                .add_path(std::path::Path::new(path))
+                .map_err(|e| format!("Failed to stage file: {}", e))?;
+        }
+        None => {
+            if full_path.exists() {
+                std::fs::remove_file(&full_path)
+                    .map_err(|e| format!("Failed to delete file: {}", e))?;
+            }
+            index
+                .remove_path(std::path::Path::new(path))
+                .map_err(|e| format!("Failed to stage file: {}", e))?;
+        }
+    }
+
+    index
+        .write()
+        .map_err(|e| format!("Failed to write index: {}", e))
+}
+
+/// Merges the sides of a conflict keeping the lines of both
+fn union_merge(repo: &Repository, conflict: &git2::IndexConflict) -> Result<Vec<u8>, String> {
+    let (Some(ours), Some(theirs)) = (&conflict.our, &conflict.their) else {
+        return Err("Cannot combine both sides: the file was deleted on one side".to_string());
+    };
+
+    // Merge one-file trees so libgit2 applies its union file favor
+    let single_file_tree = |entry: Option<&git2::IndexEntry>| -> Result<git2::Tree, String> {
+        let mut index = git2::Index::new().map_err(|e| format!("Failed to create index: {}", e))?;
+        if let Some(entry) = entry {
+            let entry = git2::IndexEntry {
+                ctime: entry.ctime,
+                mtime: entry.mtime,
+                dev: entry.dev,
+                ino: entry.ino,
+                mode: entry.mode,
+                uid: entry.uid,
+                gid: entry.gid,
+                file_size: entry.file_size,
+                id: entry.id,
+                flags: entry.flags & !0x3000, // clear GIT_INDEX_ENTRY_STAGEMASK for stage 0
+                flags_extended: entry.flags_extended,
+                path: entry.path.clone(),
+            };
+            index
+                .add(&entry)
+                .map_err(|e| format!("Failed to build tree: {}", e))?;
+        }
+        let tree_id = index
+            .write_tree_to(repo)
+            .map_err(|e| format!("Failed to build tree: {}", e))?;
+        repo.find_tree(tree_id)
+            .map_err(|e| format!("Failed to find tree: {}", e))
+    };
+
+    let mut merge_opts = git2::MergeOptions::new();
+    merge_opts.file_favor(git2::FileFavor::Union);
+    let merged = repo
+        .merge_trees(
+            &single_file_tree(conflict.ancestor.as_ref())?,
+            &single_file_tree(Some(ours))?,
+            &single_file_tree(Some(theirs))?,
+            Some(&merge_opts),
+        )
+        .map_err(|e| format!("Failed to merge file: {}", e))?;
+
+    let entry = merged
+        .get_path(
+            std::path::Path::new(&String::from_utf8_lossy(&ours.path).to_string()),
+            0,
+        )
+        .ok_or_else(|| "Failed to merge file: both sides could not be combined".to_string())?;
+    let blob = repo
+        .find_blob(entry.id)
+        .map_err(|e| format!("Failed to read merged file: {}", e))?;
+
+    Ok(blob.content().to_vec())
+}
+
+/// Resolves a conflicted file by keeping our version, their version, or the
+/// lines of both, and marks it resolved
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Path to the conflicted file
+/// * `resolution` - Which version to keep
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn resolve_conflict(
+    repo_path: &str,
+    file_path: &str,
+    resolution: ConflictResolution,
+) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let conflict = find_conflict(&index, file_path)?;
+
+    let content = match resolution {
+        ConflictResolution::Ours | ConflictResolution::Theirs => {
+            let side = if resolution == ConflictResolution::Ours {
+                &conflict.our
+            } else {
+                &conflict.their
+            };
+            // A side without the file resolves the conflict as a deletion
+            match side {
+                Some(entry) => Some(
+                    repo.find_blob(entry.id)
+                        .map_err(|e| format!("Failed to read file: {}", e))?
+                        .content()
+                        .to_vec(),
+                ),
+                None => None,
+            }
+        }
+        ConflictResolution::Union => Some(union_merge(&repo, &conflict)?),
+    };
+
+    stage_resolution(&repo, &mut index, file_path, content.as_deref())
+}
+
+/// Marks a conflicted file resolved, optionally writing the merged result first.
+/// Without content, the working directory version is staged as is, and a file
+/// deleted from the working directory is resolved as deleted.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `file_path` - Path to the conflicted file
+/// * `content` - Merged contents to write, or None to keep the working directory file
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn mark_resolved(
+    repo_path: &str,
+    file_path: &str,
+    content: Option<&str>,
+) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    find_conflict(&index, file_path)?;
+
+    let content = match content {
+        Some(content) => Some(content.as_bytes().to_vec()),
+        None => {
+            let full_path = repo
+                .workdir()
+                .ok_or_else(|| "Repository has no working directory".to_string())?
+                .join(file_path);
+            if full_path.exists() {
+                Some(std::fs::read(&full_path).map_err(|e| format!("Failed to read file: {}", e))?)
+            } else {
+                None
+            }
+        }
+    };
+
+    stage_resolution(&repo, &mut index, file_path, content.as_deref())
+}
+
+/// Fails unless a merge, revert or cherry-pick is in progress
+fn require_operation_in_progress(repo: &Repository) -> Result<(), String> {
+    match repo.state() {
+        git2::RepositoryState::Merge
+        | git2::RepositoryState::Revert
+        | git2::RepositoryState::CherryPick => Ok(()),
+        _ => Err("No merge, revert or cherry-pick is in progress".to_string()),
+    }
+}
+
+/// Concludes an in-progress merge, revert or cherry-pick by committing the
+/// resolved index, running the commit hooks like create_commit
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `message` - Commit message, or None to use the prepared one (MERGE_MSG)
+///
+/// # Returns
+/// A CommitResult struct or an error message
+pub fn continue_operation(repo_path: &str, message: Option<&str>) -> Result<CommitResult, String> {
+    let repo = open_workdir_repo(repo_path)?;
+    require_operation_in_progress(&repo)?;
+
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let conflicts = index_conflicts(&index)?;
+    if !conflicts.is_empty() {
+        return Err(format!(
+            "Cannot continue: {} file(s) still have conflicts",
+            conflicts.len()
+        ));
+    }
+
+    let message = match message {
+        Some(message) => message.to_string(),
+        None => repo
+            .message()
+            .map_err(|_| "No prepared commit message; please provide one".to_string())?,
+    };
+
+    create_commit(repo_path, &message, false)
+}
+
+/// Abandons an in-progress merge, revert or cherry-pick, like `git merge --abort`.
+/// Files touched by the operation return to HEAD; other local changes are kept.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn abort_operation(repo_path: &str) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+    require_operation_in_progress(&repo)?;
+
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| format!("Failed to get HEAD commit: {}", e))?;
+    let head_tree = head
+        .tree()
+        .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+
+    // The operation's changes are the conflicted and staged paths
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let mut paths: Vec<String> = index_conflicts(&index)?
+        .into_iter()
+        .map(|conflict| conflict.path)
+        .collect();
+    let staged = repo
+        .diff_tree_to_index(Some(&head_tree), Some(&index), None)
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+    for delta in staged.deltas() {
+        for file in [delta.old_file(), delta.new_file()] {
+            if let Some(path) = file.path().and_then(|p| p.to_str()) {
+                if !paths.iter().any(|p| p == path) {
+                    paths.push(path.to_string());
+                }
+            }
+        }
+    }
+
+    if !paths.is_empty() {
+        repo.reset_default(Some(head.as_object()), paths.iter())
+            .map_err(|e| format!("Failed to reset index: {}", e))?;
+
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force().remove_untracked(true);
+        for path in &paths {
+            checkout.path(path);
+        }
+        repo.checkout_head(Some(&mut checkout))
+            .map_err(|e| format!("Failed to restore files: {}", e))?;
+    }
+
+    repo.cleanup_state()
+        .map_err(|e| format!("Failed to clean up repository state: {}", e))
+}
+
+/// Orders commits oldest first, following the commit graph
+fn order_commits<'r>(
+    repo: &'r Repository,
+    commit_ids: &[String],
+) -> Result<Vec<git2::Commit<'r>>, String> {
+    if commit_ids.is_empty() {
+        return Err("At least one commit must be selected".to_string());
+    }
+
+    let mut selected = std::collections::HashSet::new();
+    let mut revwalk = repo
+        .revwalk()
+        .map_err(|e| format!("Failed to create revwalk: {}", e))?;
+    revwalk
+        .set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)
+        .map_err(|e| format!("Failed to sort commits: {}", e))?;
+
+    for commit_id in commit_ids {
+        let oid = git2::Oid::from_str(commit_id)
+            .map_err(|e| format!("Invalid commit ID '{}': {}", commit_id, e))?;
+        let commit = repo
+            .find_commit(oid)
+            .map_err(|e| format!("Failed to find commit: {}", e))?;
+        if commit.parent_count() > 1 {
+            return Err(format!(
+                "Commit '{}' is a merge commit and can't be applied on its own",
+                commit_id
+            ));
+        }
+
+        revwalk
+            .push(oid)
+            .map_err(|e| format!("Failed to walk commits: {}", e))?;
+        selected.insert(oid);
+    }
+
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        let oid = oid.map_err(|e| format!("Failed to walk commits: {}", e))?;
+        if selected.contains(&oid) {
+            commits.push(
+                repo.find_commit(oid)
+                    .map_err(|e| format!("Failed to find commit: {}", e))?,
+            );
+        }
+    }
+
+    Ok(commits)
+}
+
+/// Message git uses for the commit reverting `commit`
+fn revert_message(commit: &git2::Commit) -> String {
+    format!(
+        "Revert \"{}\"\n\nThis reverts commit {}.\n",
+        commit.summary().unwrap_or(""),
+        commit.id()
+    )
+}
+
+/// Reverts (newest first) or cherry-picks (oldest first) commits on top of HEAD.
+///
+/// Each commit is applied in memory on top of the previous result. The final tree
+/// is checked out safely, so unrelated local changes survive, and with `auto_commit`
+/// every step becomes a commit. When a commit conflicts, the clean steps before it
+/// are kept and its conflicts are written to the index and working directory, with
+/// CHERRY_PICK_HEAD/REVERT_HEAD and MERGE_MSG recorded like git does.
+fn apply_commits(
+    repo: &Repository,
+    commit_ids: &[String],
+    revert: bool,
+    auto_commit: bool,
+) -> Result<ApplyCommitsResult, String> {
+    let operation = if revert { "revert" } else { "cherry-pick" };
+
+    if repo.state() != git2::RepositoryState::Clean || rebase_in_progress(repo) {
+        return Err(format!(
+            "Cannot {}: another operation is in progress",
+            operation
+        ));
+    }
+
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|_| format!("Cannot {} before the first commit", operation))?;
+    let head_tree = head
+        .tree()
+        .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let staged = repo
+        .diff_tree_to_index(Some(&head_tree), Some(&index), None)
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+    if index.has_conflicts() || staged.deltas().len() > 0 {
+        return Err(format!(
+            "Cannot {}: commit or unstage your staged changes first",
+            operation
+        ));
+    }
+
+    let mut commits = order_commits(repo, commit_ids)?;
+    if revert {
+        commits.reverse();
+    }
+
+    let committer = repo
+        .signature()
+        .map_err(|e| format!("Failed to get signature: {}", e))?;
+
+    let mut base = head.clone();
+    let mut result = ApplyCommitsResult {
+        created: Vec::new(),
+        applied: Vec::new(),
+        stopped_at: None,
+        conflicts: Vec::new(),
+        remaining: Vec::new(),
+        message: None,
+    };
+    let mut messages = Vec::new();
+    let mut conflicted = None;
+
+    for (position, commit) in commits.iter().enumerate() {
+        let mut merged = if revert {
+            repo.revert_commit(commit, &base, 0, None)
+        } else {
+            repo.cherrypick_commit(commit, &base, 0, None)
+        }
+        .map_err(|e| format!("Failed to {} commit {}: {}", operation, commit.id(), e))?;
+
+        let message = if revert {
+            revert_message(commit)
+        } else {
+            commit.message().unwrap_or("").to_string()
+        };
+
+        if merged.has_conflicts() {
+            result.stopped_at = Some(commit.id().to_string());
+            result.conflicts = index_conflicts(&merged)?;
+            result.remaining = commits[position + 1..]
+                .iter()
+                .map(|c| c.id().to_string())
+                .collect();
+            messages.push(message);
+            conflicted = Some((commit.id(), merged));
+            break;
+        }
+
+        let tree_id = merged
+            .write_tree_to(repo)
+            .map_err(|e| format!("Failed to write tree: {}", e))?;
+        let tree = repo
+            .find_tree(tree_id)
+            .map_err(|e| format!("Failed to find tree: {}", e))?;
+
+        // Without auto-commit, intermediate commits only serve as the next base
+        let author = if revert {
+            committer.clone()
+        } else {
+            commit.author()
+        };
+        let step = if auto_commit {
+            create_commit_object(repo, &author, &committer, &message, &tree, &[&base])?
+        } else {
+            repo.commit(None, &author, &committer, &message, &tree, &[&base])
+                .map_err(|e| format!("Failed to create commit: {}", e))?
+        };
+        base = repo
+            .find_commit(step)
+            .map_err(|e| format!("Failed to find commit: {}", e))?;
+
+        result.applied.push(commit.id().to_string());
+        if auto_commit {
+            result.created.push(step.to_string());
+        } else {
+            messages.push(message);
+        }
+    }
+
+    // Land the clean steps: check out their result, then move HEAD when committing
+    if base.id() != head.id() {
+        let target = base
+            .tree()
+            .map_err(|e| format!("Failed to get tree: {}", e))?;
+        let blocked = std::cell::RefCell::new(Vec::new());
+        let checkout = {
+            let mut checkout_opts = CheckoutBuilder::new();
+            checkout_opts
+                .safe()
+                .notify_on(git2::CheckoutNotificationType::CONFLICT)
+                .notify(|_, path, _, _, _| {
+                    if let Some(path) = path {
+                        blocked
+                            .borrow_mut()
+                            .push(path.to_string_lossy().to_string());
+                    }
+                    true
+                });
+            repo.checkout_tree(target.as_object(), Some(&mut checkout_opts))
+        };
+        if let Err(e) = checkout {
+            let blocked = blocked.into_inner();
+            return Err(if blocked.is_empty() {
+                format!("Failed to {}: {}", operation, e)
+            } else {
+                format!(
+                    "Cannot {}: your local changes to the following files would be overwritten: {}",
+                    operation,
+                    blocked.join(", ")
+                )
+            });
+        }
+
+        if auto_commit {
+            let reflog = format!("{}: {}", operation, base.summary().unwrap_or(""));
+            update_head(repo, base.id(), &reflog)?;
+        }
+    }
+
+    if let Some((commit_id, mut merged)) = conflicted {
+        let mut checkout_opts = CheckoutBuilder::new();
+        checkout_opts
+            .safe()
+            .allow_conflicts(true)
+            .conflict_style_merge(true);
+        repo.checkout_index(Some(&mut merged), Some(&mut checkout_opts))
+            .map_err(|e| {
+                format!(
+                    "Cannot {}: local changes are in the way of conflicted files ({})",
+                    operation,
+                    e.message()
+                )
+            })?;
+
+        // Record the operation so it can be continued or aborted like git's
+        let git_dir = repo.path();
+        let state_file = if revert {
+            "REVERT_HEAD"
+        } else {
+            "CHERRY_PICK_HEAD"
+        };
+        std::fs::write(git_dir.join(state_file), format!("{}\n", commit_id))
+            .map_err(|e| format!("Failed to write {}: {}", state_file, e))?;
+        std::fs::write(
+            git_dir.join("MERGE_MSG"),
+            messages.last().unwrap_or(&String::new()),
+        )
+        .map_err(|e| format!("Failed to write MERGE_MSG: {}", e))?;
+    }
+
+    if !messages.is_empty() {
+        result.message = Some(messages.join("\n"));
+    }
+
+    Ok(result)
+}
+
+/// Reverts one commit or a range of commits
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `commit_ids` - SHAs of the commits to revert, in any order
+/// * `auto_commit` - Whether to commit each revert, or only stage the changes
+///
+/// # Returns
+/// An ApplyCommitsResult describing what was applied and any conflicts, or an error message
+pub fn revert_commit(
+    repo_path: &str,
+    commit_ids: &[String],
+    auto_commit: bool,
+) -> Result<ApplyCommitsResult, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    apply_commits(&repo, commit_ids, true, auto_commit)
+}
+
+/// Cherry-picks one commit or a range of commits onto HEAD
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `commit_ids` - SHAs of the commits to cherry-pick, in any order
+/// * `auto_commit` - Whether to commit each cherry-pick, or only stage the changes
+///
+/// # Returns
+/// An ApplyCommitsResult describing what was applied and any conflicts, or an error message
+pub fn cherry_pick_commit(
+    repo_path: &str,
+    commit_ids: &[String],
+    auto_commit: bool,
+) -> Result<ApplyCommitsResult, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    apply_commits(&repo, commit_ids, false, auto_commit)
+}
+
+/// How far a reset rewinds, mirroring `git reset --soft/--mixed/--hard`
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum ResetMode {
+    /// Move HEAD only, keeping the index and working directory
+    Soft,
+    /// Move HEAD and reset the index, keeping the working directory
+    Mixed,
+    /// Move HEAD and reset both the index and the working directory
+    Hard,
+}
+
+/// Outcome of a reset
+#[derive(Debug, Clone, Serialize)]
+pub struct ResetResult {
+    /// Commit HEAD points to after the reset
+    pub head: String,
+    /// Token for undo_reset, naming the backup taken before the reset
+    pub undo_token: String,
+}
+
+const RESET_BACKUP_PREFIX: &str = "refs/recap/backups/";
+
+/// Records the current HEAD, index and working directory under
+/// refs/recap/backups/<token>/ before a reset. `head` points to the previous HEAD
+/// commit, `branch` (unless HEAD is detached) is a symbolic ref to the branch
+/// being reset, and `changes` (only when something is dirty) points to a
+/// stash-like commit whose tree holds the working directory, including untracked
+/// files, and whose second parent holds the index.
+fn backup_before_reset(repo: &Repository, head: &git2::Commit) -> Result<String, String> {
+    let short_id = &head.id().to_string()[..7];
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut token = format!("{}-{}", timestamp, short_id);
+    let mut attempt = 1;
+    while repo
+        .find_reference(&format!("{}{}/head", RESET_BACKUP_PREFIX, token))
+        .is_ok()
+    {
+        attempt += 1;
+        token = format!("{}-{}-{}", timestamp, short_id, attempt);
+    }
+
+    let mut status_opts = git2::StatusOptions::new();
+    status_opts
+        .include_untracked(true)
+        .recurse_untracked_dirs(true);
+    let dirty = !repo
+        .statuses(Some(&mut status_opts))
+        .map_err(|e| format!("Failed to get status: {}", e))?
+        .is_empty();
+
+    let log_message = format!("recap: backup before reset from {}", short_id);
+    if dirty {
+        let head_tree = head
+            .tree()
+            .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+        // Unresolved conflicts can't be written as a tree; record the index as HEAD
+        let index_tree_id = repo
+            .index()
+            .and_then(|mut index| index.write_tree())
+            .unwrap_or_else(|_| head_tree.id());
+
+        // Stage everything on a separate handle, whose index is never written back
+        let scratch = Repository::open(repo.path())
+            .map_err(|e| format!("Failed to open repository: {}", e))?;
+        let mut scratch_index = scratch
+            .index()
+            .map_err(|e| format!("Failed to get index: {}", e))?;
+        scratch_index
+            .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+            .and_then(|_| scratch_index.update_all(["*"].iter(), None))
+            .map_err(|e| format!("Failed to snapshot working directory: {}", e))?;
+        let work_tree_id = scratch_index
+            .write_tree()
+            .map_err(|e| format!("Failed to snapshot working directory: {}", e))?;
+
+        let signature = repo
+            .signature()
+            .map_err(|e| format!("Failed to get signature: {}", e))?;
+        let branch = repo
+            .head()
+            .ok()
+            .filter(|head| head.is_branch())
+            .and_then(|head| head.shorthand().map(|s| s.to_string()))
+            .unwrap_or_else(|| "(no branch)".to_string());
+        let find_tree = |id| {
+            repo.find_tree(id)
+                .map_err(|e| format!("Failed to find tree: {}", e))
+        };
+
+        let index_commit_id = repo
+            .commit(
+                None,
+                &signature,
+                &signature,
+                &format!("index on {}", stash_message_suffix(head, &branch)),
+                &find_tree(index_tree_id)?,
+                &[head],
+            )
+            .map_err(|e| format!("Failed to create backup: {}", e))?;
+        let index_commit = repo
+            .find_commit(index_commit_id)
+            .map_err(|e| format!("Failed to create backup: {}", e))?;
+        let changes_id = repo
+            .commit(
+                None,
+                &signature,
+                &signature,
+                &stash_message(head, &branch, Some("backup before reset")),
+                &find_tree(work_tree_id)?,
+                &[head, &index_commit],
+            )
+            .map_err(|e| format!("Failed to create backup: {}", e))?;
+
+        repo.reference(
+            &format!("{}{}/changes", RESET_BACKUP_PREFIX, token),
+            changes_id,
+            false,
+            &log_message,
+        )
+        .map_err(|e| format!("Failed to create backup: {}", e))?;
+    }
+
+    repo.reference(
+        &format!("{}{}/head", RESET_BACKUP_PREFIX, token),
+        head.id(),
+        false,
+        &log_message,
+    )
+    .map_err(|e| format!("Failed to create backup: {}", e))?;
+
+    if let Some(branch) = current_branch_ref(repo) {
+        repo.reference_symbolic(
+            &format!("{}{}/branch", RESET_BACKUP_PREFIX, token),
+            &branch,
+            false,
+            &log_message,
+        )
+        .map_err(|e| format!("Failed to create backup: {}", e))?;
+    }
+
+    Ok(token)
+}
+
+/// Full name of the checked out branch (e.g. "refs/heads/main"), None if HEAD is detached
+fn current_branch_ref(repo: &Repository) -> Option<String> {
+    repo.head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.name().map(|name| name.to_string()))
+}
+
+/// Backs up the current state, then resets HEAD (and the current branch) to `target`
+fn reset_with_backup(
+    repo: &Repository,
+    target: &git2::Commit,
+    mode: ResetMode,
+) -> Result<ResetResult, String> {
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|_| "Cannot reset before the first commit".to_string())?;
+    let undo_token = backup_before_reset(repo, &head)?;
+
+    let kind = match mode {
+        ResetMode::Soft => git2::ResetType::Soft,
+        ResetMode::Mixed => git2::ResetType::Mixed,
+        ResetMode::Hard => git2::ResetType::Hard,
+    };
+    repo.reset(target.as_object(), kind, None)
+        .map_err(|e| format!("Failed to reset: {}", e))?;
+
+    Ok(ResetResult {
+        head: target.id().to_string(),
+        undo_token,
+    })
+}
+
+/// Resets HEAD to a commit, like `git reset`. The previous HEAD and any
+/// uncommitted changes, untracked files included, are first recorded under
+/// refs/recap/backups/ so the reset can be undone.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `commit` - Commit SHA or revision to reset to
+/// * `mode` - Soft, mixed or hard
+///
+/// # Returns
+/// The new HEAD and a token for undo_reset, or an error message
+pub fn reset_to_commit(
+    repo_path: &str,
+    commit: &str,
+    mode: ResetMode,
+) -> Result<ResetResult, String> {
+    let repo = open_workdir_repo(repo_path)?;
+    ensure_no_rebase(&repo, "reset")?;
+
+    let target = repo
+        .revparse_single(commit)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| format!("Failed to find commit: {}", e))?;
+
+    reset_with_backup(&repo, &target, mode)
+}
+
+/// Restores the HEAD, index and working directory recorded before a reset.
+/// The state being replaced is backed up in turn, so the undo can itself be undone.
+/// The reset branch must still be checked out, so that undoing never moves
+/// another branch.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `undo_token` - Token returned by reset_to_commit
+///
+/// # Returns
+/// The restored HEAD and a token to undo the restore, or an error message
+pub fn undo_reset(repo_path: &str, undo_token: &str) -> Result<ResetResult, String> {
+    let repo = open_workdir_repo(repo_path)?;
+    ensure_no_rebase(&repo, "undo a reset")?;
+
+    let head_ref = format!("{}{}/head", RESET_BACKUP_PREFIX, undo_token);
+    let changes_ref = format!("{}{}/changes", RESET_BACKUP_PREFIX, undo_token);
+    let branch_ref = format!("{}{}/branch", RESET_BACKUP_PREFIX, undo_token);
+    let head = repo
+        .find_reference(&head_ref)
+        .and_then(|reference| reference.peel_to_commit())
+        .map_err(|_| format!("No reset backup found for '{}'", undo_token))?;
+
+    let reset_branch = repo
+        .find_reference(&branch_ref)
+        .ok()
+        .and_then(|reference| reference.symbolic_target().map(|t| t.to_string()));
+    if reset_branch != current_branch_ref(&repo) {
+        return Err(match reset_branch {
+            Some(branch) => {
+                let short = branch.strip_prefix("refs/heads/").unwrap_or(&branch);
+                format!(
+                    "Cannot undo the reset: it was made on '{}'. Check out '{}' first",
+                    short, short
+                )
+            }
+            None => "Cannot undo the reset: it was made with a detached HEAD".to_string(),
+        });
+    }
+    let changes = repo
+        .find_reference(&changes_ref)
+        .ok()
+        .and_then(|reference| reference.peel_to_commit().ok());
+
+    let result = reset_with_backup(&repo, &head, ResetMode::Hard)?;
+
+    if let Some(changes) = changes {
+        let index_tree = changes
+            .parent(1)
+            .and_then(|index_commit| index_commit.tree())
+            .map_err(|e| format!("Failed to read backup: {}", e))?;
+
+        // The working directory was just reset, and anything it held is in the new backup
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force();
+        repo.checkout_tree(changes.as_object(), Some(&mut checkout))
+            .map_err(|e| format!("Failed to restore working directory: {}", e))?;
+
+        let mut index = repo
+            .index()
+            .map_err(|e| format!("Failed to get index: {}", e))?;
+        index
+            .read_tree(&index_tree)
+            .map_err(|e| format!("Failed to restore index: {}", e))?;
+        index
+            .write()
+            .map_err(|e| format!("Failed to write index: {}", e))?;
+    }
+
+    for name in [head_ref, changes_ref, branch_ref] {
+        if let Ok(mut reference) = repo.find_reference(&name) {
+            reference
+                .delete()
+                .map_err(|e| format!("Failed to remove backup: {}", e))?;
+        }
+    }
+
+    Ok(result)
+}
+
+/// What an interactive rebase does with a commit
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RebaseAction {
+    /// Replay the commit as is
+    Pick,
+    /// Replay the commit with a new message
+    Reword,
+    /// Fold the commit into the previous one, combining their messages
+    Squash,
+    /// Fold the commit into the previous one, keeping the previous message
+    Fixup,
+    /// Leave the commit out
+    Drop,
+}
+
+/// One line of an interactive rebase todo list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebaseTodoItem {
+    /// What to do with the commit
+    pub action: RebaseAction,
+    /// SHA of the commit
+    pub commit: String,
+    /// New message for reword, or the combined message for squash
+    pub message: Option<String>,
+}
+
+/// Progress of an interactive rebase
+#[derive(Debug, Clone, Serialize)]
+pub struct RebaseStatus {
+    /// Whether every step was applied and the branch updated
+    pub completed: bool,
+    /// SHA of the last rewritten commit (the new branch tip once completed)
+    pub head: String,
+    /// The step whose changes conflicted, if stopped
+    pub stopped_at: Option<RebaseTodoItem>,
+    /// Files left with conflicts by `stopped_at`
+    pub conflicts: Vec<FileConflict>,
+    /// Steps still to apply after `stopped_at`
+    pub remaining: Vec<RebaseTodoItem>,
+}
+
+/// What's needed to resume or abort a stopped rebase. It's kept in git's own
+/// rebase-merge directory next to the state of the step being replayed, so a
+/// `git rebase --abort` from the command line clears it too.
+#[derive(Debug, Serialize, Deserialize)]
+struct RebaseState {
+    /// Branch being rebased, None if HEAD was detached
+    head_name: Option<String>,
+    /// Commit HEAD pointed to before the rebase
+    orig_head: String,
+    /// Commit the todo list is replayed onto
+    onto: String,
+    /// Step waiting for its conflicts to be resolved
+    stopped: Option<RebaseTodoItem>,
+    /// Commit HEAD was detached at when the rebase stopped
+    #[serde(default)]
+    stopped_tip: Option<String>,
+    /// Steps not yet applied
+    todo: Vec<RebaseTodoItem>,
+}
+
+fn rebase_state_path(repo: &Repository) -> std::path::PathBuf {
+    repo.path().join("rebase-merge").join("recap-todo.json")
+}
+
+/// Whether an interactive rebase started by start_rebase is waiting to be continued
+fn rebase_in_progress(repo: &Repository) -> bool {
+    rebase_state_path(repo).exists()
+}
+
+/// Refuses an operation that moves HEAD or rewrites the index while a rebase is
+/// stopped. Continuing replays the rest of the todo list on top of the detached
+/// HEAD, so it must still be where the rebase left it.
+fn ensure_no_rebase(repo: &Repository, action: &str) -> Result<(), String> {
+    if rebase_in_progress(repo) {
+        return Err(format!(
+            "Cannot {} while a rebase is in progress: continue or abort it first",
+            action
+        ));
+    }
+    Ok(())
+}
+
+fn load_rebase_state(repo: &Repository) -> Result<RebaseState, String> {
+    let content = std::fs::read_to_string(rebase_state_path(repo))
+        .map_err(|_| "No rebase is in progress".to_string())?;
+
+    serde_json::from_str(&content).map_err(|e| format!("Failed to read rebase state: {}", e))
+}
+
+fn save_rebase_state(repo: &Repository, state: &RebaseState) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(state)
+        .map_err(|e| format!("Failed to save rebase state: {}", e))?;
+
+    std::fs::write(rebase_state_path(repo), content)
+        .map_err(|e| format!("Failed to save rebase state: {}", e))
+}
+
+/// The part of a squashed commit's message that goes into the combined message.
+/// Like `git rebase --autosquash`, a `squash! <subject>` line is left out.
+fn squash_message_body(message: &str) -> &str {
+    let message = message.trim_end();
+    if !message.starts_with("squash! ") {
+        return message;
+    }
+    match message.split_once('\n') {
+        Some((_, body)) => body.trim_start_matches('\n'),
+        None => "",
+    }
+}
+
+/// Creates the commit for one rebase step on top of `tip`, whose changes are `tree`.
+/// Squash and fixup replace `tip` instead of adding a commit after it.
+fn commit_rebase_step(
+    repo: &Repository,
+    tip: &git2::Commit,
+    item: &RebaseTodoItem,
+    commit: &git2::Commit,
+    tree: &git2::Tree,
+) -> Result<git2::Oid, String> {
+    let committer = repo
+        .signature()
+        .map_err(|e| format!("Failed to get signature: {}", e))?;
+    let original = commit.message().unwrap_or("");
+
+    match item.action {
+        RebaseAction::Squash | RebaseAction::Fixup => {
+            let message = match (&item.message, item.action) {
+                (Some(message), _) => message.clone(),
+                (None, RebaseAction::Squash) => {
+                    let tip_message = tip.message().unwrap_or("").trim_end();
+                    match squash_message_body(original) {
+                        "" => format!("{}\n", tip_message),
+                        body => format!("{}\n\n{}", tip_message, body),
+                    }
+                }
+                (None, _) => tip.message().unwrap_or("").to_string(),
+            };
+            let parents: Vec<git2::Commit> = tip.parents().collect();
+            let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+
+            create_commit_object(
+                repo,
+                &tip.author(),
+                &committer,
+                &message,
+                tree,
+                &parent_refs,
+            )
+        }
+        _ => {
+            // A commit already sitting on the tip needs no rewriting
+            if item.action == RebaseAction::Pick && commit.parent_id(0).ok() == Some(tip.id()) {
+                return Ok(commit.id());
+            }
+            let message = match (&item.message, item.action) {
+                (Some(message), RebaseAction::Reword) => message.as_str(),
+                _ => original,
+            };
+
+            create_commit_object(repo, &commit.author(), &committer, message, tree, &[tip])
+        }
+    }
+}
+
+/// Replays a single commit on top of `tip` with an on-disk git2::Rebase of just
+/// that commit. HEAD is left detached at `tip`, with the result, or its
+/// conflicts, in the index and working directory.
+fn replay_commit<'r>(
+    repo: &'r Repository,
+    commit: &git2::Commit,
+    tip: &git2::Commit,
+) -> Result<git2::Rebase<'r>, String> {
+    let parent = commit
+        .parent_id(0)
+        .map_err(|_| format!("Cannot rebase root commit {}", commit.id()))?;
+    let annotated = |oid| {
+        repo.find_annotated_commit(oid)
+            .map_err(|e| format!("Failed to find commit: {}", e))
+    };
+    let (branch, upstream, onto) = (
+        annotated(commit.id())?,
+        annotated(parent)?,
+        annotated(tip.id())?,
+    );
+
+    let mut checkout_opts = CheckoutBuilder::new();
+    checkout_opts
+        .safe()
+        .allow_conflicts(true)
+        .conflict_style_merge(true);
+    let mut options = git2::RebaseOptions::new();
+    options.checkout_options(checkout_opts);
+
+    let mut rebase = repo
+        .rebase(
+            Some(&branch),
+            Some(&upstream),
+            Some(&onto),
+            Some(&mut options),
+        )
+        .map_err(|e| format!("Failed to start rebase: {}", e))?;
+    if let Some(Err(e)) = rebase.next() {
+        let _ = rebase.abort();
+        return Err(format!("Failed to apply commit {}: {}", commit.id(), e));
+    }
+
+    Ok(rebase)
+}
+
+/// Applies the remaining todo steps on top of `tip`. Stops at the first conflict,
+/// leaving HEAD detached at the last rewritten commit with the conflicts checked
+/// out, and otherwise moves the branch to the result. Any other failure puts
+/// everything back the way it was before the rebase.
+fn run_rebase<'r>(
+    repo: &'r Repository,
+    mut state: RebaseState,
+    tip: git2::Commit<'r>,
+) -> Result<RebaseStatus, String> {
+    run_rebase_steps(repo, &mut state, tip).map_err(|e| match restore_orig_head(repo, &state) {
+        Ok(()) => format!("{}. The rebase was aborted", e),
+        Err(_) => e,
+    })
+}
+
+fn run_rebase_steps<'r>(
+    repo: &'r Repository,
+    state: &mut RebaseState,
+    mut tip: git2::Commit<'r>,
+) -> Result<RebaseStatus, String> {
+    while !state.todo.is_empty() {
+        let item = state.todo.remove(0);
+        if item.action == RebaseAction::Drop {
+            continue;
+        }
+
+        let commit = repo
+            .find_commit(
+                git2::Oid::from_str(&item.commit)
+                    .map_err(|e| format!("Invalid commit {}: {}", item.commit, e))?,
+            )
+            .map_err(|e| format!("Failed to find commit: {}", e))?;
+        // A commit already sitting on the tip needs no replaying
+        if item.action == RebaseAction::Pick && commit.parent_id(0).ok() == Some(tip.id()) {
+            tip = commit;
+            continue;
+        }
+
+        let mut rebase = replay_commit(repo, &commit, &tip)?;
+        let mut index = repo
+            .index()
+            .map_err(|e| format!("Failed to get index: {}", e))?;
+
+        if index.has_conflicts() {
+            let conflicts = index_conflicts(&index)?;
+            let remaining = state.todo.clone();
+            state.stopped = Some(item.clone());
+            state.stopped_tip = Some(tip.id().to_string());
+            save_rebase_state(repo, state)?;
+
+            return Ok(RebaseStatus {
+                completed: false,
+                head: tip.id().to_string(),
+                stopped_at: Some(item),
+                conflicts,
+                remaining,
+            });
+        }
+
+        let tree_id = index
+            .write_tree()
+            .map_err(|e| format!("Failed to write tree: {}", e))?;
+        // Like git, drop picks whose changes are already on the tip
+        if tree_id != tip.tree_id()
+            || !matches!(item.action, RebaseAction::Pick | RebaseAction::Reword)
+        {
+            let tree = repo
+                .find_tree(tree_id)
+                .map_err(|e| format!("Failed to find tree: {}", e))?;
+            let step = commit_rebase_step(repo, &tip, &item, &commit, &tree)?;
+            repo.set_head_detached(step)
+                .map_err(|e| format!("Failed to update HEAD: {}", e))?;
+            tip = repo
+                .find_commit(step)
+                .map_err(|e| format!("Failed to find commit: {}", e))?;
+        }
+        // HEAD is detached, so this only removes git's state for the step
+        rebase
+            .finish(None)
+            .map_err(|e| format!("Failed to finish rebase step: {}", e))?;
+    }
+
+    // Check out the result detached, then move the branch and attach HEAD to it
+    switch_to(repo, tip.id(), None, false)?;
+    if let Some(head_name) = &state.head_name {
+        repo.reference(
+            head_name,
+            tip.id(),
+            true,
+            &format!("rebase (finish): {} onto {}", head_name, state.onto),
+        )
+        .map_err(|e| format!("Failed to update branch: {}", e))?;
+        repo.set_head(head_name)
+            .map_err(|e| format!("Failed to update HEAD: {}", e))?;
+    }
+    std::fs::write(
+        repo.path().join("ORIG_HEAD"),
+        format!("{}\n", state.orig_head),
+    )
+    .map_err(|e| format!("Failed to write ORIG_HEAD: {}", e))?;
+
+    Ok(RebaseStatus {
+        completed: true,
+        head: tip.id().to_string(),
+        stopped_at: None,
+        conflicts: Vec::new(),
+        remaining: Vec::new(),
+    })
+}
+
+/// Rewrites the commits between `onto` and HEAD following a todo list, like
+/// `git rebase -i`. Steps can be reordered, and commits left out of the list are
+/// dropped: each step replays its commit with a git2::Rebase of that one commit,
+/// in the order of the list. The rewritten commits are created (and signed) like
+/// any other commit, and the branch only moves once every step is applied.
+/// While a step is stopped on conflicts, git sees a rebase in progress.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `onto` - Commit to replay onto, usually the parent of the oldest selected commit
+/// * `todo` - Steps to apply, in order
+///
+/// # Returns
+/// A RebaseStatus, stopped if a step conflicted, or an error message
+pub fn start_rebase(
+    repo_path: &str,
+    onto: &str,
+    todo: Vec<RebaseTodoItem>,
+) -> Result<RebaseStatus, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    if repo.state() != git2::RepositoryState::Clean || rebase_in_progress(&repo) {
+        return Err("Cannot rebase: another operation is in progress".to_string());
+    }
+    if has_uncommitted_changes(&repo)? {
+        return Err("Cannot rebase: commit or stash your local changes first".to_string());
+    }
+
+    let head = repo
+        .head()
+        .map_err(|_| "Cannot rebase before the first commit".to_string())?;
+    let head_name = if head.is_branch() {
+        head.name().map(|name| name.to_string())
+    } else {
+        None
+    };
+    let head_commit = head
+        .peel_to_commit()
+        .map_err(|e| format!("Failed to get HEAD commit: {}", e))?;
+    let onto_commit = repo
+        .revparse_single(onto)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| format!("Failed to find commit: {}", e))?;
+
+    let mut revwalk = repo
+        .revwalk()
+        .map_err(|e| format!("Failed to create revwalk: {}", e))?;
+    revwalk
+        .push(head_commit.id())
+        .and_then(|_| revwalk.hide(onto_commit.id()))
+        .map_err(|e| format!("Failed to walk commits: {}", e))?;
+    let range = revwalk
+        .collect::<Result<std::collections::HashSet<_>, _>>()
+        .map_err(|e| format!("Failed to walk commits: {}", e))?;
+
+    let mut steps = Vec::new();
+    for item in todo {
+        let commit = repo
+            .revparse_single(&item.commit)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| format!("Failed to find commit {}: {}", item.commit, e))?;
+        if !range.contains(&commit.id()) {
+            return Err(format!(
+                "Commit {} is not between {} and HEAD",
+                item.commit, onto
+            ));
+        }
+        if commit.parent_count() > 1 {
+            return Err(format!("Cannot rebase merge commit {}", item.commit));
+        }
+        if steps
+            .iter()
+            .any(|step: &RebaseTodoItem| step.commit == commit.id().to_string())
+        {
+            return Err(format!("Commit {} appears more than once", item.commit));
+        }
+
+        steps.push(RebaseTodoItem {
+            commit: commit.id().to_string(),
+            ..item
+        });
+    }
+    if let Some(first) = steps.iter().find(|step| step.action != RebaseAction::Drop) {
+        if matches!(first.action, RebaseAction::Squash | RebaseAction::Fixup) {
+            return Err(
+                "The first commit can't be squashed: there is nothing before it".to_string(),
+            );
+        }
+    }
+
+    let state = RebaseState {
+        head_name,
+        orig_head: head_commit.id().to_string(),
+        onto: onto_commit.id().to_string(),
+        stopped: None,
+        stopped_tip: None,
+        todo: steps,
+    };
+
+    run_rebase(&repo, state, onto_commit)
+}
+
+/// Continues a stopped rebase once its conflicts are resolved, committing the
+/// resolved step and applying the rest of the todo list
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A RebaseStatus, stopped again if a later step conflicted, or an error message
+pub fn continue_rebase(repo_path: &str) -> Result<RebaseStatus, String> {
+    let repo = open_workdir_repo(repo_path)?;
+    let mut state = load_rebase_state(&repo)?;
+
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let conflicts = index_conflicts(&index)?;
+    if !conflicts.is_empty() {
+        return Err(format!(
+            "Cannot continue: {} file(s) still have conflicts",
+            conflicts.len()
+        ));
+    }
+
+    let mut tip = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| format!("Failed to get HEAD commit: {}", e))?;
+
+    // Replaying onto anything else would rewrite whatever was checked out meanwhile
+    let stopped_tip = state.stopped_tip.take();
+    let head_moved = match &stopped_tip {
+        Some(stopped_tip) => {
+            *stopped_tip != tip.id().to_string()
+                || !repo
+                    .head_detached()
+                    .map_err(|e| format!("Failed to read HEAD: {}", e))?
+        }
+        None => false,
+    };
+    if head_moved {
+        return Err(format!(
+            "Cannot continue: HEAD moved since the rebase stopped at {}. Check it out again or abort the rebase",
+            stopped_tip.unwrap_or_default()
+        ));
+    }
+
+    let mut rebase = repo
+        .open_rebase(None)
+        .map_err(|e| format!("Failed to open rebase: {}", e))?;
+    if let Some(item) = state.stopped.take() {
+        let commit = repo
+            .revparse_single(&item.commit)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| format!("Failed to find commit: {}", e))?;
+        let tree_id = index
+            .write_tree()
+            .map_err(|e| format!("Failed to write tree: {}", e))?;
+
+        // A step resolved to no changes is dropped, unless it folds into the tip
+        if tree_id != tip.tree_id()
+            || matches!(item.action, RebaseAction::Squash | RebaseAction::Fixup)
+        {
+            let tree = repo
+                .find_tree(tree_id)
+                .map_err(|e| format!("Failed to find tree: {}", e))?;
+            let step = commit_rebase_step(&repo, &tip, &item, &commit, &tree)?;
+            repo.set_head_detached(step)
+                .map_err(|e| format!("Failed to update HEAD: {}", e))?;
+            tip = repo
+                .find_commit(step)
+                .map_err(|e| format!("Failed to find commit: {}", e))?;
+        }
+    }
+    rebase
+        .finish(None)
+        .map_err(|e| format!("Failed to finish rebase step: {}", e))?;
+
+    run_rebase(&repo, state, tip)
+}
+
+/// Abandons a stopped rebase, restoring the branch, index and working directory
+/// to where they were before it started
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn abort_rebase(repo_path: &str) -> Result<(), String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    abort_stopped_rebase(&repo)
+}
+
+/// Restores HEAD, the index and the working directory of a stopped rebase to
+/// where they were before it started, and forgets the rebase
+fn abort_stopped_rebase(repo: &Repository) -> Result<(), String> {
+    let state = load_rebase_state(repo)?;
+
+    restore_orig_head(repo, &state)
+}
+
+/// Puts HEAD, the index and the working directory back to where they were
+/// before a rebase started, dropping git's state for the step being replayed
+fn restore_orig_head(repo: &Repository, state: &RebaseState) -> Result<(), String> {
+    if let Ok(mut rebase) = repo.open_rebase(None) {
+        rebase
+            .abort()
+            .map_err(|e| format!("Failed to abort rebase: {}", e))?;
+    }
+
+    let orig_head = repo
+        .revparse_single(&state.orig_head)
+        .map_err(|e| format!("Failed to find commit: {}", e))?;
+
+    // The branch itself only moves once the rebase completes
+    match &state.head_name {
+        Some(head_name) => repo.set_head(head_name),
+        None => repo.set_head_detached(orig_head.id()),
+    }
+    .map_err(|e| format!("Failed to update HEAD: {}", e))?;
+    repo.reset(&orig_head, git2::ResetType::Hard, None)
+        .map_err(|e| format!("Failed to reset: {}", e))?;
+
+    Ok(())
+}
+
+/// Gets the progress of a stopped rebase, e.g. to resume it after a restart
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// The RebaseStatus, None if no rebase is in progress, or an error message
+pub fn get_rebase_status(repo_path: &str) -> Result<Option<RebaseStatus>, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+    if !rebase_in_progress(&repo) {
+        return Ok(None);
+    }
+    let state = load_rebase_state(&repo)?;
+
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| format!("Failed to get HEAD commit: {}", e))?;
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+
+    Ok(Some(RebaseStatus {
+        completed: false,
+        head: head.id().to_string(),
+        stopped_at: state.stopped,
+        conflicts: index_conflicts(&index)?,
+        remaining: state.todo,
+    }))
+}
+
+/// Commits the staged changes as a fixup of an earlier commit, with the message
+/// `fixup! <subject>` that autosquash recognizes
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `commit` - SHA or revision of the commit to fix up
+///
+/// # Returns
+/// A CommitResult struct or an error message
+pub fn create_fixup_commit(repo_path: &str, commit: &str) -> Result<CommitResult, String> {
+    let summary = {
+        let repo =
+            Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+        let target = repo
+            .revparse_single(commit)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|e| format!("Failed to find commit: {}", e))?;
+        target.summary().unwrap_or("").to_string()
+    };
+
+    create_commit(repo_path, &format!("fixup! {}", summary), false)
+}
+
+/// Splits a `fixup! ` or `squash! ` message into its action and the subject it
+/// targets, following nested prefixes like `fixup! fixup! subject`
+fn autosquash_target(summary: &str) -> Option<(RebaseAction, &str)> {
+    let (action, mut target) = if let Some(rest) = summary.strip_prefix("fixup! ") {
+        (RebaseAction::Fixup, rest)
+    } else if let Some(rest) = summary.strip_prefix("squash! ") {
+        (RebaseAction::Squash, rest)
+    } else {
+        return None;
+    };
+
+    while let Some(rest) = target
+        .strip_prefix("fixup! ")
+        .or_else(|| target.strip_prefix("squash! "))
+    {
+        target = rest;
+    }
+
+    Some((action, target))
+}
+
+/// Builds the autosquash todo list: every commit in order, with each `fixup!` or
+/// `squash!` commit moved after the commit it targets (matched by subject, then
+/// subject prefix, then SHA prefix). Returns None if nothing is to be squashed.
+fn autosquash_todo(commits: &[git2::Commit]) -> Option<Vec<RebaseTodoItem>> {
+    // Each group is a target followed by its fixups
+    let mut groups: Vec<Vec<RebaseTodoItem>> = Vec::new();
+    let mut targets: Vec<(String, String)> = Vec::new(); // (sha, summary) per group
+    let mut moved = false;
+
+    for commit in commits {
+        let summary = commit.summary().unwrap_or("");
+        let item = |action| RebaseTodoItem {
+            action,
+            commit: commit.id().to_string(),
+            message: None,
+        };
+
+        let group = autosquash_target(summary).and_then(|(action, target)| {
+            let position = targets
+                .iter()
+                .position(|(_, summary)| summary == target)
+                .or_else(|| {
+                    targets
+                        .iter()
+                        .position(|(_, summary)| summary.starts_with(target))
+                })
+                .or_else(|| {
+                    targets
+                        .iter()
+                        .position(|(sha, _)| target.len() >= 4 && sha.starts_with(target))
+                })?;
+            Some((position, action))
+        });
+
+        match group {
+            Some((position, action)) => {
+                groups[position].push(item(action));
+                moved = true;
+            }
+            None => {
+                groups.push(vec![item(RebaseAction::Pick)]);
+                targets.push((commit.id().to_string(), summary.to_string()));
+            }
+        }
+    }
+
+    moved.then(|| groups.into_iter().flatten().collect())
+}
+
+/// Squashes every `fixup!` and `squash!` commit of the current branch into the
+/// commit it targets, like `git rebase -i --autosquash`
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `onto` - Where the branch starts; defaults to its merge base with the
+///   upstream, or else with the default branch
+///
+/// # Returns
+/// A RebaseStatus, stopped if a fixup conflicted, or an error message
+pub fn autosquash(repo_path: &str, onto: Option<&str>) -> Result<RebaseStatus, String> {
+    let (onto, todo) = {
+        let repo = open_workdir_repo(repo_path)?;
+        let head = repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(|_| "Cannot autosquash before the first commit".to_string())?;
+
+        let onto = match onto {
+            Some(onto) => repo
+                .revparse_single(onto)
+                .and_then(|object| object.peel_to_commit())
+                .map(|commit| commit.id())
+                .map_err(|e| format!("Failed to find commit: {}", e))?,
+            None => {
+                let base_tip = repo
+                    .revparse_single("@{upstream}")
+                    .map(|object| object.id())
+                    .ok()
+                    .or_else(|| default_branch_tip(&repo))
+                    .ok_or_else(|| {
+                        "Cannot tell where the branch starts; choose the base commit".to_string()
+                    })?;
+                repo.merge_base(head.id(), base_tip)
+                    .map_err(|e| format!("Failed to find merge base: {}", e))?
+            }
+        };
+
+        let mut revwalk = repo
+            .revwalk()
+            .map_err(|e| format!("Failed to create revwalk: {}", e))?;
+        revwalk
+            .set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)
+            .and_then(|_| revwalk.push(head.id()))
+            .and_then(|_| revwalk.hide(onto))
+            .map_err(|e| format!("Failed to walk commits: {}", e))?;
+        let commits = revwalk
+            .map(|oid| oid.and_then(|oid| repo.find_commit(oid)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to walk commits: {}", e))?;
+
+        let todo = autosquash_todo(&commits)
+            .ok_or_else(|| "No fixup commits to squash on this branch".to_string())?;
+        (onto.to_string(), todo)
+    };
+
+    start_rebase(repo_path, &onto, todo)
+}
+
+/// A configured remote
+#[derive(Debug, Clone, Serialize)]
+pub struct Remote {
+    /// Name of the remote (e.g. "origin")
+    pub name: String,
+    /// Fetch URL of the remote
+    pub url: Option<String>,
+    /// Push URL of the remote, when it differs from the fetch URL
+    pub push_url: Option<String>,
+}
+
+/// Progress of a fetch or push, reported while objects are transferred
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteProgress {
+    /// Operation in progress: "fetch" or "push"
+    pub operation: String,
+    /// Name of the remote
+    pub remote: String,
+    /// Objects transferred so far
+    pub current: usize,
+    /// Total objects to transfer
+    pub total: usize,
+    /// Bytes transferred so far
+    pub bytes: usize,
+    /// Progress text sent by the server (e.g. "Counting objects: 42% (5/12)")
+    pub message: Option<String>,
+}
+
+/// How pull integrates upstream changes into the current branch
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum PullMode {
+    /// Only fast-forward, failing when the branches have diverged
+    FastForwardOnly,
+    /// Rebase local commits on top of the upstream branch
+    Rebase,
+}
+
+/// Outcome of a pull
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum PullResult {
+    /// The branch already contained the upstream commits
+    UpToDate,
+    /// The branch was fast-forwarded to its upstream
+    FastForwarded,
+    /// Local commits were rebased on top of the upstream branch
+    Rebased,
+}
+
+/// Lists the configured remotes
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A vector of Remote structs or an error message
+pub fn list_remotes(repo_path: &str) -> Result<Vec<Remote>, String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    let names = repo
+        .remotes()
+        .map_err(|e| format!("Failed to list remotes: {}", e))?;
+
+    let mut remotes = Vec::new();
+    for name in names.iter().flatten() {
+        let remote = repo
+            .find_remote(name)
+            .map_err(|e| format!("Failed to find remote '{}': {}", name, e))?;
+
+        remotes.push(Remote {
+            name: name.to_string(),
+            url: remote.url().map(|u| u.to_string()),
+            push_url: remote.pushurl().map(|u| u.to_string()),
+        });
+    }
+
+    Ok(remotes)
+}
+
+/// Builds the callbacks shared by fetch and push: credentials from ssh-agent or
+/// the configured credential helper, and throttled progress reporting
+fn remote_callbacks<'a>(
+    config: &'a git2::Config,
+    operation: &'a str,
+    remote: &'a str,
+    progress: &'a dyn Fn(RemoteProgress),
+) -> git2::RemoteCallbacks<'a> {
+    let report = move |current: usize, total: usize, bytes: usize, message: Option<String>| {
+        progress(RemoteProgress {
+            operation: operation.to_string(),
+            remote: remote.to_string(),
+            current,
+            total,
+            bytes,
+            message,
+        })
+    };
+
+    let mut callbacks = git2::RemoteCallbacks::new();
+
+    // libgit2 asks again after a rejected credential; give up instead of looping
+    let mut attempts = 0;
+    callbacks.credentials(move |url, username_from_url, allowed| {
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+
+        if allowed.contains(git2::CredentialType::USERNAME) {
+            return git2::Cred::username(username_from_url.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            return git2::Cred::ssh_key_from_agent(username_from_url.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            return git2::Cred::credential_helper(config, url, username_from_url);
+        }
+        git2::Cred::default()
+    });
+
+    // Report at most once per percent, so the UI isn't flooded with events
+    let mut last_fetch_percent = None;
+    callbacks.transfer_progress(move |stats| {
+        let total = stats.total_objects();
+        let current = stats.received_objects();
+        let percent = (current * 100).checked_div(total).unwrap_or(100);
+        if last_fetch_percent != Some(percent) {
+            last_fetch_percent = Some(percent);
+            report(current, total, stats.received_bytes(), None);
+        }
+        true
+    });
+
+    let mut last_push_percent = None;
+    callbacks.push_transfer_progress(move |current, total, bytes| {
+        let percent = (current * 100).checked_div(total).unwrap_or(100);
+        if last_push_percent != Some(percent) {
+            last_push_percent = Some(percent);
+            report(current, total, bytes, None);
+        }
+    });
+
+    callbacks.sideband_progress(move |data| {
+        let message = String::from_utf8_lossy(data).trim().to_string();
+        if !message.is_empty() {
+            report(0, 0, 0, Some(message));
+        }
+        true
+    });
+
+    callbacks
+}
+
+/// Fetches one remote
+fn fetch_remote(
+    repo: &Repository,
+    remote_name: &str,
+    prune: bool,
+    progress: &dyn Fn(RemoteProgress),
+) -> Result<(), String> {
+    let config = repo
+        .config()
+        .map_err(|e| format!("Failed to read config: {}", e))?;
+    let mut remote = repo
+        .find_remote(remote_name)
+        .map_err(|e| format!("Remote '{}' not found: {}", remote_name, e))?;
+
+    let mut fetch_opts = git2::FetchOptions::new();
+    fetch_opts.remote_callbacks(remote_callbacks(&config, "fetch", remote_name, progress));
+    if prune {
+        fetch_opts.prune(git2::FetchPrune::On);
+    }
+
+    // An empty refspec list uses the remote's configured refspecs
+    remote
+        .fetch(&[] as &[&str], Some(&mut fetch_opts), None)
+        .map_err(|e| format!("Failed to fetch from '{}': {}", remote_name, e))
+}
+
+/// Fetches from one remote or from all of them
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `remote_name` - Remote to fetch, or None for all remotes
+/// * `prune` - Whether to remove remote branches that no longer exist on the remote
+/// * `progress` - Called with transfer progress
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn fetch(
+    repo_path: &str,
+    remote_name: Option<&str>,
+    prune: bool,
+    progress: &dyn Fn(RemoteProgress),
+) -> Result<(), String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    let names: Vec<String> = match remote_name {
+        Some(name) => vec![name.to_string()],
+        None => repo
+            .remotes()
+            .map_err(|e| format!("Failed to list remotes: {}", e))?
+            .iter()
+            .flatten()
+            .map(|name| name.to_string())
+            .collect(),
+    };
+
+    for name in &names {
+        fetch_remote(&repo, name, prune, progress)?;
+    }
+
+    Ok(())
+}
+
+/// Whether tracked files have uncommitted changes
+fn has_uncommitted_changes(repo: &Repository) -> Result<bool, String> {
+    let mut status_opts = git2::StatusOptions::new();
+    status_opts.include_untracked(false);
+
+    let statuses = repo
+        .statuses(Some(&mut status_opts))
+        .map_err(|e| format!("Failed to get status: {}", e))?;
+
+    Ok(!statuses.is_empty())
+}
+
+/// Fetches the upstream of the current branch and integrates it, either by
+/// fast-forwarding or by rebasing local commits on top of it
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `mode` - Fast-forward only, or rebase
+/// * `progress` - Called with transfer progress
+///
+/// # Returns
+/// How the branch was updated, or an error message
+pub fn pull(
+    repo_path: &str,
+    mode: PullMode,
+    progress: &dyn Fn(RemoteProgress),
+) -> Result<PullResult, String> {
+    let repo = open_workdir_repo(repo_path)?;
+    ensure_no_rebase(&repo, "pull")?;
+
+    let head = repo
+        .head()
+        .map_err(|e| format!("Failed to get HEAD: {}", e))?;
+    if !head.is_branch() {
+        return Err("Cannot pull with a detached HEAD".to_string());
+    }
+    let refname = head
+        .name()
+        .ok_or_else(|| "Branch reference name is not valid UTF-8".to_string())?
+        .to_string();
+    let branch_name = head.shorthand().unwrap_or("").to_string();
+
+    let remote_name = repo
+        .branch_upstream_remote(&refname)
+        .map_err(|_| format!("Branch '{}' has no upstream", branch_name))?;
+    let remote_name = remote_name
+        .as_str()
+        .ok_or_else(|| "Remote name is not valid UTF-8".to_string())?
+        .to_string();
+
+    fetch_remote(&repo, &remote_name, false, progress)?;
+
+    let local_tip = head
+        .peel_to_commit()
+        .map_err(|e| format!("Failed to get HEAD commit: {}", e))?
+        .id();
+    let upstream_tip = repo
+        .find_branch(&branch_name, BranchType::Local)
+        .and_then(|branch| branch.upstream())
+        .and_then(|upstream| upstream.get().peel_to_commit())
+        .map_err(|e| format!("Failed to find upstream of '{}': {}", branch_name, e))?
+        .id();
+
+    let (ahead, behind) = repo
+        .graph_ahead_behind(local_tip, upstream_tip)
+        .map_err(|e| format!("Failed to compare with upstream: {}", e))?;
+
+    if behind == 0 {
+        return Ok(PullResult::UpToDate);
+    }
+
+    if ahead == 0 {
+        // Safe checkout carries local changes over unless they're in the way
+        switch_to(&repo, upstream_tip, Some(&refname), false)?;
+        repo.reference(
+            &refname,
+            upstream_tip,
+            true,
+            &format!("pull: Fast-forward to {}", upstream_tip),
+        )
+        .map_err(|e| format!("Failed to fast-forward branch: {}", e))?;
+        return Ok(PullResult::FastForwarded);
+    }
+
+    match mode {
+        PullMode::FastForwardOnly => Err(format!(
+            "Cannot fast-forward: '{}' has diverged from its upstream ({} local, {} remote commits)",
+            branch_name, ahead, behind
+        )),
+        PullMode::Rebase => {
+            rebase_onto_upstream(&repo, &refname, upstream_tip)?;
+            Ok(PullResult::Rebased)
+        }
+    }
+}
+
+/// Rebases the branch `refname` (checked out) onto `upstream`, replaying its
+/// commits through the same steps as start_rebase so they're signed like any
+/// other commit. Aborts and reports the conflicted files when a commit doesn't
+/// apply cleanly.
+fn rebase_onto_upstream(
+    repo: &Repository,
+    refname: &str,
+    upstream: git2::Oid,
+) -> Result<(), String> {
+    if rebase_in_progress(repo) {
+        return Err("Cannot rebase: another rebase is in progress".to_string());
+    }
+    if has_uncommitted_changes(repo)? {
+        return Err("Cannot rebase: commit or stash your changes first".to_string());
+    }
+
+    let branch_tip = repo
+        .refname_to_id(refname)
+        .map_err(|e| format!("Failed to find branch: {}", e))?;
+    let upstream_commit = repo
+        .find_commit(upstream)
+        .map_err(|e| format!("Failed to find upstream commit: {}", e))?;
+
+    // Like `git rebase`, replay the branch's own commits oldest first, leaving out merges
+    let mut revwalk = repo
+        .revwalk()
+        .map_err(|e| format!("Failed to create revwalk: {}", e))?;
+    revwalk
+        .set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)
+        .and_then(|_| revwalk.push(branch_tip))
+        .and_then(|_| revwalk.hide(upstream))
+        .map_err(|e| format!("Failed to walk commits: {}", e))?;
+    let mut todo = Vec::new();
+    for oid in revwalk {
+        let oid = oid.map_err(|e| format!("Failed to walk commits: {}", e))?;
+        let commit = repo
+            .find_commit(oid)
+            .map_err(|e| format!("Failed to find commit: {}", e))?;
+        if commit.parent_count() <= 1 {
+            todo.push(RebaseTodoItem {
+                action: RebaseAction::Pick,
+                commit: oid.to_string(),
+                message: None,
+            });
+        }
+    }
+
+    let state = RebaseState {
+        head_name: Some(refname.to_string()),
+        orig_head: branch_tip.to_string(),
+        onto: upstream.to_string(),
+        stopped: None,
+        stopped_tip: None,
+        todo,
+    };
+
+    let status = run_rebase(repo, state, upstream_commit)?;
+    if !status.completed {
+        let conflicts: Vec<String> = status.conflicts.into_iter().map(|c| c.path).collect();
+        abort_stopped_rebase(repo)?;
+        return Err(format!(
+            "Rebase stopped on conflicts and was aborted: {}",
+            conflicts.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Pushes the current branch. Without an upstream, the branch is pushed under
+/// the same name and, when `set_upstream` is set, tracked afterwards.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `remote_name` - Remote to push to (defaults to the upstream's remote, then "origin")
+/// * `set_upstream` - Whether to make the pushed branch the upstream of the local one
+/// * `progress` - Called with transfer progress
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn push(
+    repo_path: &str,
+    remote_name: Option<&str>,
+    set_upstream: bool,
+    progress: &dyn Fn(RemoteProgress),
+) -> Result<(), String> {
+    let repo =
+        Repository::open(repo_path).map_err(|e| format!("Failed to open repository: {}", e))?;
+
+    let head = repo
+        .head()
+        .map_err(|e| format!("Failed to get HEAD: {}", e))?;
+    if !head.is_branch() {
+        return Err("Cannot push with a detached HEAD".to_string());
+    }
+    let refname = head
+        .name()
+        .ok_or_else(|| "Branch reference name is not valid UTF-8".to_string())?
+        .to_string();
+    let branch_name = head.shorthand().unwrap_or("").to_string();
+
+    let upstream_remote = repo
+        .branch_upstream_remote(&refname)
+        .ok()
+        .and_then(|name| name.as_str().map(|n| n.to_string()));
+    let remote_name = remote_name
+        .map(|n| n.to_string())
+        .or_else(|| upstream_remote.clone())
+        .unwrap_or_else(|| "origin".to_string());
+
+    let config = repo
+        .config()
+        .map_err(|e| format!("Failed to read config: {}", e))?;
+
+    // Push to the upstream branch when it lives on this remote, else to the same name
+    let upstream_merge = config
+        .get_string(&format!("branch.{}.merge", branch_name))
+        .ok();
+    let destination = match (&upstream_remote, upstream_merge) {
+        (Some(upstream_remote), Some(merge)) if *upstream_remote == remote_name => merge,
+        _ => refname.clone(),
+    };
+    let mut remote = repo
+        .find_remote(&remote_name)
+        .map_err(|e| format!("Remote '{}' not found: {}", remote_name, e))?;
+
+    let rejected = std::cell::RefCell::new(Vec::new());
+    {
+        let mut callbacks = remote_callbacks(&config, "push", &remote_name, progress);
+        callbacks.push_update_reference(|reference, status| {
+            if let Some(status) = status {
+                rejected
+                    .borrow_mut()
+                    .push(format!("{} ({})", reference, status));
+            }
+            Ok(())
+        });
+
+        let mut push_opts = git2::PushOptions::new();
+        push_opts.remote_callbacks(callbacks);
+
+        remote
+            .push(
+                &[format!("{}:{}", refname, destination)],
+                Some(&mut push_opts),
+            )
+            .map_err(|e| format!("Failed to push to '{}': {}", remote_name, e))?;
+    }
+
+    let rejected = rejected.into_inner();
+    if !rejected.is_empty() {
+        return Err(format!(
+            "Push was rejected by '{}': {}",
+            remote_name,
+            rejected.join(", ")
+        ));
+    }
+
+    if set_upstream {
+        let upstream_name = format!(
+            "{}/{}",
+            remote_name,
+            destination.trim_start_matches("refs/heads/")
+        );
+        repo.find_branch(&branch_name, BranchType::Local)
+            .and_then(|mut branch| branch.set_upstream(Some(&upstream_name)))
+            .map_err(|e| format!("Failed to set upstream: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// A stash entry
+#[derive(Debug, Clone, Serialize)]
+pub struct Stash {
+    /// Position in the stash list (0 is the most recent, like stash@{0})
+    pub index: usize,
+    /// SHA of the stash commit
+    pub id: String,
+    /// Stash message
+    pub message: String,
+    /// Unix timestamp of when the stash was created
+    pub timestamp: i64,
+}
+
+/// Outcome of applying or popping a stash
+#[derive(Debug, Clone, Serialize)]
+pub struct StashApplyResult {
+    /// Files left with conflict markers after applying the stash
+    pub conflicts: Vec<String>,
+    /// Whether the stash was removed from the list
+    pub dropped: bool,
+}
+
+/// Finds the commit of stash@{index}
+fn find_stash<'r>(repo: &'r Repository, index: usize) -> Result<git2::Commit<'r>, String> {
+    let reflog = repo
+        .reflog("refs/stash")
+        .map_err(|e| format!("Failed to read stash list: {}", e))?;
+
+    let entry = reflog
+        .get(index)
+        .ok_or_else(|| format!("Stash '{}' not found", index))?;
+
+    repo.find_commit(entry.id_new())
+        .map_err(|e| format!("Failed to find stash commit: {}", e))
+}
+
+/// Lists the stashes of a repository, most recent first
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+///
+/// # Returns
+/// A vector of Stash structs or an error message
+pub fn list_stashes(repo_path: &str) -> Result<Vec<Stash>, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    read_stashes(&repo)
+}
+
+/// Reads the stash list from the refs/stash reflog, most recent first
+fn read_stashes(repo: &Repository) -> Result<Vec<Stash>, String> {
+    let reflog = repo
+        .reflog("refs/stash")
+        .map_err(|e| format!("Failed to read stash list: {}", e))?;
+
+    let mut stashes = Vec::new();
+    for (index, entry) in reflog.iter().enumerate() {
+        let commit = repo
+            .find_commit(entry.id_new())
+            .map_err(|e| format!("Failed to find stash commit: {}", e))?;
+
+        stashes.push(Stash {
+            index,
+            id: commit.id().to_string(),
+            message: entry.message().unwrap_or("").to_string(),
+            timestamp: commit.time().seconds(),
+        });
+    }
+
+    Ok(stashes)
+}
+
+/// "<branch>: <short sha> <subject>", the tail of git's generated stash messages
+fn stash_message_suffix(head: &git2::Commit, branch: &str) -> String {
+    let id = head.id().to_string();
+    format!("{}: {} {}", branch, &id[..7], head.summary().unwrap_or(""))
+}
+
+/// Builds the stash message git uses: "On <branch>: <message>" or
+/// "WIP on <branch>: <short sha> <subject>"
+fn stash_message(head: &git2::Commit, branch: &str, message: Option<&str>) -> String {
+    match message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}", stash_message_suffix(head, branch)),
+    }
+}
+
+/// Stashes only the staged changes, like `git stash push --staged`.
+/// The stash commit holds the index tree, and the staged changes are removed
+/// from both the index and the working directory.
+fn stash_staged_changes(
+    repo: &Repository,
+    stasher: &git2::Signature,
+    message: Option<&str>,
+) -> Result<git2::Oid, String> {
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|_| "Cannot stash changes before the first commit".to_string())?;
+    let head_tree = head
+        .tree()
+        .map_err(|e| format!("Failed to get HEAD tree: {}", e))?;
+
+    let mut index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    if index.has_conflicts() {
+        return Err("Cannot stash changes while there are unresolved conflicts".to_string());
+    }
+    let index_tree_id = index
+        .write_tree()
+        .map_err(|e| format!("Failed to write index tree: {}", e))?;
+    if index_tree_id == head_tree.id() {
+        return Err("No staged changes to stash".to_string());
+    }
+    let index_tree = repo
+        .find_tree(index_tree_id)
+        .map_err(|e| format!("Failed to find index tree: {}", e))?;
+
+    // Remove the staged changes from the working directory first, so a file whose
+    // unstaged changes conflict with them aborts the stash before anything is written
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.reverse(true);
+    let staged = repo
+        .diff_tree_to_index(Some(&head_tree), Some(&index), Some(&mut diff_opts))
+        .map_err(|e| format!("Failed to create diff: {}", e))?;
+    repo.apply(&staged, ApplyLocation::WorkDir, None)
+        .map_err(|e| {
+            format!(
+                "Cannot stash staged changes: they conflict with unstaged changes ({})",
+                e.message()
+            )
+        })?;
+    repo.apply(&staged, ApplyLocation::Index, None)
+        .map_err(|e| format!("Failed to unstage changes: {}", e))?;
+
+    let branch = repo
+        .head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.shorthand().map(|s| s.to_string()))
+        .unwrap_or_else(|| "(no branch)".to_string());
+    let stash_message = stash_message(&head, &branch, message);
+
+    let index_commit_id = repo
+        .commit(
+            None,
+            stasher,
+            stasher,
+            &format!("index on {}", stash_message_suffix(&head, &branch)),
+            &index_tree,
+            &[&head],
+        )
+        .map_err(|e| format!("Failed to create stash: {}", e))?;
+    let index_commit = repo
+        .find_commit(index_commit_id)
+        .map_err(|e| format!("Failed to create stash: {}", e))?;
+
+    let stash_id = repo
+        .commit(
+            None,
+            stasher,
+            stasher,
+            &stash_message,
+            &index_tree,
+            &[&head, &index_commit],
+        )
+        .map_err(|e| format!("Failed to create stash: {}", e))?;
+
+    repo.reference_ensure_log("refs/stash")
+        .map_err(|e| format!("Failed to create stash: {}", e))?;
+    repo.reference("refs/stash", stash_id, true, &stash_message)
+        .map_err(|e| format!("Failed to create stash: {}", e))?;
+
+    Ok(stash_id)
+}
+
+/// Stashes the local changes
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `message` - Optional stash message
+/// * `include_untracked` - Whether untracked files are stashed too
+/// * `staged_only` - Whether only the staged changes are stashed
+///
+/// # Returns
+/// The new stash entry (stash@{0}) or an error message
+pub fn create_stash(
+    repo_path: &str,
+    message: Option<&str>,
+    include_untracked: bool,
+    staged_only: bool,
+) -> Result<Stash, String> {
+    let mut repo = open_workdir_repo(repo_path)?;
+    ensure_no_rebase(&repo, "stash")?;
+
+    let stasher = repo
+        .signature()
+        .map_err(|e| format!("Failed to get signature: {}", e))?;
+    let message = message.map(str::trim).filter(|m| !m.is_empty());
+
+    if staged_only {
+        if include_untracked {
+            return Err(
+                "Cannot include untracked files when stashing only staged changes".to_string(),
+            );
+        }
+        stash_staged_changes(&repo, &stasher, message)?;
+    } else {
+        let mut flags = git2::StashFlags::DEFAULT;
+        if include_untracked {
+            flags |= git2::StashFlags::INCLUDE_UNTRACKED;
+        }
+
+        repo.stash_save2(&stasher, message, Some(flags))
+            .map_err(|e| match e.code() {
+                git2::ErrorCode::NotFound => "No local changes to stash".to_string(),
+                _ => format!("Failed to create stash: {}", e),
+            })?;
+    }
+
+    read_stashes(&repo)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "Failed to create stash".to_string())
+}
+
+/// Applies stash@{index} to the working directory.
+/// Merge conflicts are left in the index and working directory and reported;
+/// local changes that would be overwritten abort the apply.
+fn apply_stash_at(repo: &mut Repository, index: usize) -> Result<Vec<String>, String> {
+    ensure_no_rebase(repo, "apply a stash")?;
+
+    // Make sure the stash exists for a clearer error than libgit2's
+    find_stash(repo, index)?;
+
+    let blocked = std::cell::RefCell::new(Vec::new());
+    let result = {
+        let mut checkout_opts = CheckoutBuilder::new();
+        checkout_opts
+            .notify_on(git2::CheckoutNotificationType::CONFLICT)
+            .notify(|_, path, _, _, _| {
+                if let Some(path) = path {
+                    blocked
+                        .borrow_mut()
+                        .push(path.to_string_lossy().to_string());
+                }
+                true
+            });
+
+        let mut apply_opts = git2::StashApplyOptions::new();
+        apply_opts.checkout_options(checkout_opts);
+
+        repo.stash_apply(index, Some(&mut apply_opts))
+    };
+
+    if let Err(e) = result {
+        let blocked = blocked.into_inner();
+        return Err(match e.code() {
+            git2::ErrorCode::Uncommitted => {
+                "Cannot apply stash: commit or unstage your staged changes first".to_string()
+            }
+            git2::ErrorCode::Conflict if !blocked.is_empty() => format!(
+                "Cannot apply stash: your local changes to the following files would be overwritten: {}",
+                blocked.join(", ")
+            ),
+            _ => format!("Failed to apply stash: {}", e),
+        });
+    }
+
+    let index = repo
+        .index()
+        .map_err(|e| format!("Failed to get index: {}", e))?;
+    let conflicts = index
+        .conflicts()
+        .map_err(|e| format!("Failed to read conflicts: {}", e))?
+        .filter_map(|conflict| conflict.ok())
+        .filter_map(|conflict| conflict.our.or(conflict.their).or(conflict.ancestor))
+        .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+        .collect();
+
+    Ok(conflicts)
+}
+
+/// Applies a stash, keeping it in the stash list
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `index` - Position of the stash in the stash list
+///
+/// # Returns
+/// The files left with conflicts, or an error message
+pub fn apply_stash(repo_path: &str, index: usize) -> Result<StashApplyResult, String> {
+    let mut repo = open_workdir_repo(repo_path)?;
+
+    let conflicts = apply_stash_at(&mut repo, index)?;
+
+    Ok(StashApplyResult {
+        conflicts,
+        dropped: false,
+    })
+}
+
+/// Applies a stash and drops it. Like `git stash pop`, the stash is kept
+/// when applying it results in conflicts.
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `index` - Position of the stash in the stash list
+///
+/// # Returns
+/// The files left with conflicts and whether the stash was dropped, or an error message
+pub fn pop_stash(repo_path: &str, index: usize) -> Result<StashApplyResult, String> {
+    let mut repo = open_workdir_repo(repo_path)?;
+
+    let conflicts = apply_stash_at(&mut repo, index)?;
+    let dropped = conflicts.is_empty();
+
+    if dropped {
+        repo.stash_drop(index)
+            .map_err(|e| format!("Failed to drop stash: {}", e))?;
+    }
+
+    Ok(StashApplyResult { conflicts, dropped })
+}
+
+/// Removes a stash from the stash list
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `index` - Position of the stash in the stash list
+///
+/// # Returns
+/// Ok(()) on success, or an error message
+pub fn drop_stash(repo_path: &str, index: usize) -> Result<(), String> {
+    let mut repo = open_workdir_repo(repo_path)?;
+
+    find_stash(&repo, index)?;
+
+    repo.stash_drop(index)
+        .map_err(|e| format!("Failed to drop stash: {}", e))
+}
+
+/// Diffs of a stash: the stashed changes against the commit the stash was made on,
+/// and the untracked files it holds, if any
+fn stash_diffs<'r>(
+    repo: &'r Repository,
+    stash: &git2::Commit,
+    file_path: Option<&str>,
+) -> Result<Vec<git2::Diff<'r>>, String> {
+    let tree = stash
+        .tree()
+        .map_err(|e| format!("Failed to get stash tree: {}", e))?;
+    let base_tree = stash
+        .parent(0)
+        .and_then(|parent| parent.tree())
+        .map_err(|e| format!("Failed to get stash base tree: {}", e))?;
+
+    let mut diff_opts = DiffOptions::new();
+    if let Some(file_path) = file_path {
+        diff_opts.pathspec(file_path);
+    }
+
+    let mut diffs = vec![repo
+        .diff_tree_to_tree(Some(&base_tree), Some(&tree), Some(&mut diff_opts))
+        .map_err(|e| format!("Failed to create diff: {}", e))?];
+
+    // The third parent holds the untracked files stashed with --include-untracked
+    if stash.parent_count() > 2 {
+        let untracked_tree = stash
+            .parent(2)
+            .and_then(|parent| parent.tree())
+            .map_err(|e| format!("Failed to get untracked files of stash: {}", e))?;
+
+        diffs.push(
+            repo.diff_tree_to_tree(None, Some(&untracked_tree), Some(&mut diff_opts))
+                .map_err(|e| format!("Failed to create diff: {}", e))?,
+        );
+    }
+
+    Ok(diffs)
+}
+
+/// Gets the list of files changed in a stash
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `index` - Position of the stash in the stash list
+///
+/// # Returns
+/// A vector of ChangedFile structs or an error message
+pub fn get_stash_files(repo_path: &str, index: usize) -> Result<Vec<ChangedFile>, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let stash = find_stash(&repo, index)?;
+
+    let mut files = Vec::new();
+    for diff in stash_diffs(&repo, &stash, None)? {
+        files.extend(collect_changed_files(&diff)?);
+    }
+
+    Ok(files)
+}
+
+/// Gets the diff of a single file in a stash
+///
+/// # Arguments
+/// * `repo_path` - Path to the git repository
+/// * `index` - Position of the stash in the stash list
+/// * `file_path` - Path to the file to get diff for
+///
+/// # Returns
+/// A FileDiff struct or an error message
+pub fn get_stash_file_diff(
+    repo_path: &str,
+    index: usize,
+    file_path: &str,
+) -> Result<FileDiff, String> {
+    let repo = open_workdir_repo(repo_path)?;
+
+    let stash = find_stash(&repo, index)?;
+
+    for diff in stash_diffs(&repo, &stash, Some(file_path))? {
+        if let Some(file_diff) = first_file_diff(&diff)? {
+            return Ok(file_diff);
+        }
+    }
+
+    Err(format!("File '{}' not found in stash", file_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    fn create_test_repo() -> TempDir {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let path = temp_dir.path();
+
+        // Initialize git repo
+        Command::new("git")
+            .args(["init"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to init git repo");
+
+        // Configure git user for the test repo
+        Command::new("git")
+            .args(["config", "user.email", "test@example.com"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to set git email");
+
+        Command::new("git")
+            .args(["config", "user.name", "Test User"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to set git name");
+
+        // Create initial commit
+        std::fs::write(path.join("README.md"), "# Test").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Initial commit"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        // Create second commit
+        std::fs::write(path.join("file.txt"), "content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Add file"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        temp_dir
+    }
+
+    #[test]
+    fn test_list_commits_returns_commits() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].message, "Add file");
+        assert_eq!(commits[1].message, "Initial commit");
+    }
+
+    #[test]
+    fn test_list_commits_respects_limit() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, Some(1)).expect("Should return commits");
+
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].message, "Add file");
+    }
+
+    #[test]
+    fn test_list_commits_includes_author_info() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+
+        assert_eq!(commits[0].author, "Test User");
+        assert_eq!(commits[0].email, "test@example.com");
+    }
+
+    #[test]
+    fn test_list_commits_invalid_path() {
+        let result = list_commits("/nonexistent/path", None);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Failed to open repository"));
+    }
+
+    #[test]
+    fn test_list_commits_not_a_repo() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = list_commits(path, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_commit_has_valid_sha() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+
+        // SHA should be 40 hex characters
+        assert_eq!(commits[0].id.len(), 40);
+        assert!(commits[0].id.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_commit_has_timestamp() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+
+        // Timestamp should be a reasonable Unix timestamp (after 2020)
+        assert!(commits[0].timestamp > 1577836800); // 2020-01-01
+    }
+
+    // Tests for get_commit_files
+
+    #[test]
+    fn test_get_commit_files_returns_added_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+        let latest_commit = &commits[0]; // "Add file" commit
+
+        let files =
+            get_commit_files(path, &latest_commit.id).expect("Should return changed files");
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "file.txt");
+        assert_eq!(files[0].status, FileStatus::Added);
+        assert!(files[0].additions > 0);
+    }
+
+    #[test]
+    fn test_get_commit_files_initial_commit() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+        let initial_commit = &commits[1]; // "Initial commit"
+
+        let files =
+            get_commit_files(path, &initial_commit.id).expect("Should return changed files");
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "README.md");
+        assert_eq!(files[0].status, FileStatus::Added);
+    }
+
+    #[test]
+    fn test_get_commit_files_modified_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify existing file
+        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Modify file"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
+        let modify_commit = &commits[0];
+
+        let files =
+            get_commit_files(path_str, &modify_commit.id).expect("Should return changed files");
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "file.txt");
+        assert_eq!(files[0].status, FileStatus::Modified);
+    }
+
+    #[test]
+    fn test_get_commit_files_deleted_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Delete file
+        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Delete file"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
+        let delete_commit = &commits[0];
+
+        let files =
+            get_commit_files(path_str, &delete_commit.id).expect("Should return changed files");
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "file.txt");
+        assert_eq!(files[0].status, FileStatus::Deleted);
+    }
+
+    #[test]
+    fn test_get_commit_files_invalid_commit() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = get_commit_files(path, "0000000000000000000000000000000000000000");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Failed to find commit"));
+    }
+
+    #[test]
+    fn test_get_commit_files_invalid_commit_id_format() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = get_commit_files(path, "not-a-valid-sha");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Invalid commit ID"));
+    }
+
+    #[test]
+    fn test_get_commit_range_files_for_consecutive_commits() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+        let commit_ids = vec![commits[1].id.clone(), commits[0].id.clone()];
+
+        let files = get_commit_range_files(path, &commit_ids).expect("Should return changed files");
+
+        assert!(!files.is_empty());
+        assert!(files.iter().any(|file| file.path == "README.md"));
+        assert!(files.iter().any(|file| file.path == "file.txt"));
+    }
+
+    #[test]
+    fn test_get_commit_range_files_rejects_non_consecutive_selection() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("extra.txt"), "extra content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Add extra file"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, None).expect("Should return commits");
+        let non_consecutive_ids = vec![commits[0].id.clone(), commits[2].id.clone()];
+
+        let result = get_commit_range_files(path_str, &non_consecutive_ids);
+
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .contains("Unable to display diff for multiple non-consecutive commits"));
+    }
+
+    // Tests for get_file_diff
+
+    #[test]
+    fn test_get_file_diff_returns_diff() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+        let latest_commit = &commits[0]; // "Add file" commit
+
+        let diff = get_file_diff(path, &latest_commit.id, "file.txt").expect("Should return diff");
+
+        assert_eq!(diff.new_path, "file.txt");
+        assert!(!diff.is_binary);
+        assert!(!diff.hunks.is_empty());
+
+        // Check that hunks have lines
+        let hunk = &diff.hunks[0];
+        assert!(!hunk.lines.is_empty());
+
+        // Added file should have all additions
+        let additions: Vec<_> = hunk
+            .lines
+            .iter()
+            .filter(|l| l.line_type == LineType::Addition)
+            .collect();
+        assert!(!additions.is_empty());
+    }
+
+    #[test]
+    fn test_get_file_diff_modified_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify existing file
+        std::fs::write(path.join("file.txt"), "line1\nline2\nmodified").expect("Failed to write");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Modify file"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
+
+        let diff =
+            get_file_diff(path_str, &commits[0].id, "file.txt").expect("Should return diff");
+
+        assert_eq!(diff.new_path, "file.txt");
+        assert!(!diff.is_binary);
+
+        // Should have both deletions and additions
+        let has_deletion = diff
+            .hunks
+            .iter()
+            .any(|h| h.lines.iter().any(|l| l.line_type == LineType::Deletion));
+        let has_addition = diff
+            .hunks
+            .iter()
+            .any(|h| h.lines.iter().any(|l| l.line_type == LineType::Addition));
+
+        assert!(has_deletion || has_addition);
+    }
+
+    #[test]
+    fn test_get_file_diff_file_not_in_commit() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+        let latest_commit = &commits[0];
+
+        let result = get_file_diff(path, &latest_commit.id, "nonexistent.txt");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found in commit"));
+    }
+
+    #[test]
+    fn test_get_file_diff_line_numbers() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+        let latest_commit = &commits[0];
+
+        let diff = get_file_diff(path, &latest_commit.id, "file.txt").expect("Should return diff");
+
+        // For added file, lines should have new_line_no set
+        for hunk in &diff.hunks {
+            for line in &hunk.lines {
+                if line.line_type == LineType::Addition {
+                    assert!(line.new_line_no.is_some());
+                }
+            }
+        }
+    }
+
+    // Tests for get_current_branch
+
+    #[test]
+    fn test_get_current_branch_returns_branch_name() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let branch = get_current_branch(path).expect("Should return branch name");
+
+        // Default branch could be "master" or "main" depending on git config
+        assert!(branch == "master" || branch == "main");
+    }
+
+    #[test]
+    fn test_get_current_branch_new_branch() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create and checkout new branch
+        Command::new("git")
+            .args(["checkout", "-b", "feature-branch"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+
+        let path_str = path.to_str().unwrap();
+        let branch = get_current_branch(path_str).expect("Should return branch name");
+
+        assert_eq!(branch, "feature-branch");
+    }
+
+    #[test]
+    fn test_get_current_branch_detached_head() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, None).expect("Should return commits");
+
+        // Checkout specific commit (detached HEAD)
+        Command::new("git")
+            .args(["checkout", &commits[1].id])
+            .current_dir(path)
+            .output()
+            .expect("Failed to checkout commit");
+
+        let branch = get_current_branch(path_str).expect("Should return something");
+
+        // Should return the commit SHA when in detached HEAD
+        assert_eq!(branch.len(), 40);
+        assert!(branch.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_get_current_branch_invalid_path() {
+        let result = get_current_branch("/nonexistent/path");
+        assert!(result.is_err());
+    }
+
+    // Tests for validate_repo
+
+    #[test]
+    fn test_validate_repo_returns_info() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let info = validate_repo(path).expect("Should return repo info");
+
+        assert!(info.path.len() > 0);
+        assert!(info.name.len() > 0);
+        assert!(info.branch == "master" || info.branch == "main");
+    }
+
+    #[test]
+    fn test_validate_repo_invalid_path() {
+        let result = validate_repo("/nonexistent/path");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Not a valid git repository"));
+    }
+
+    #[test]
+    fn test_validate_repo_not_a_repo() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = validate_repo(path);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_repo_path_is_normalized() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let info = validate_repo(path).expect("Should return repo info");
+
+        // Path should be absolute
+        assert!(info.path.starts_with('/'));
+    }
+
+    // Tests for get_file_contents
+
+    #[test]
+    fn test_get_file_contents_added_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+        let add_commit = &commits[0]; // "Add file" commit
+
+        let contents =
+            get_file_contents(path, &add_commit.id, "file.txt").expect("Should return contents");
+
+        assert!(!contents.is_binary);
+        assert!(contents.old_content.is_none()); // File didn't exist before
+        assert_eq!(contents.new_content, Some("content".to_string()));
+    }
+
+    #[test]
+    fn test_get_file_contents_modified_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify existing file
+        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Modify file"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
+
+        let contents = get_file_contents(path_str, &commits[0].id, "file.txt")
+            .expect("Should return contents");
+
+        assert!(!contents.is_binary);
+        assert_eq!(contents.old_content, Some("content".to_string()));
+        assert_eq!(contents.new_content, Some("modified content".to_string()));
+    }
+
+    #[test]
+    fn test_get_file_contents_deleted_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Delete file
+        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Delete file"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
+
+        let contents = get_file_contents(path_str, &commits[0].id, "file.txt")
+            .expect("Should return contents");
+
+        assert!(!contents.is_binary);
+        assert_eq!(contents.old_content, Some("content".to_string()));
+        assert!(contents.new_content.is_none()); // File was deleted
+    }
+
+    #[test]
+    fn test_get_file_contents_file_not_in_commit() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should return commits");
+
+        let result = get_file_contents(path, &commits[0].id, "nonexistent.txt");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found in commit"));
+    }
+
+    // Tests for get_working_changes
+
+    #[test]
+    fn test_get_working_changes_no_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let changes = get_working_changes(path).expect("Should return changes");
+
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn test_get_working_changes_modified_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify file without committing
+        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "file.txt");
+        assert_eq!(changes[0].status, FileStatus::Modified);
+    }
+
+    #[test]
+    fn test_get_working_changes_untracked_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create new file without adding
+        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "newfile.txt");
+        assert_eq!(changes[0].status, FileStatus::Untracked);
+    }
+
+    #[test]
+    fn test_get_working_changes_staged_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create and stage new file
+        std::fs::write(path.join("staged.txt"), "staged content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "staged.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "staged.txt");
+        assert_eq!(changes[0].status, FileStatus::Added);
+    }
+
+    #[test]
+    fn test_get_working_changes_deleted_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Delete tracked file
+        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "file.txt");
+        assert_eq!(changes[0].status, FileStatus::Deleted);
+    }
+
+    #[test]
+    fn test_get_working_changes_multiple_files() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create multiple changes
+        std::fs::write(path.join("file.txt"), "modified").expect("Failed to write");
+        std::fs::write(path.join("new.txt"), "new").expect("Failed to write");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 2);
+    }
+
+    // Tests for get_working_file_diff
+
+    #[test]
+    fn test_get_working_file_diff_modified() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify file
+        std::fs::write(path.join("file.txt"), "line1\nline2\nnew line").expect("Failed to write");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_working_file_diff(path_str, "file.txt").expect("Should return diff");
+
+        assert_eq!(diff.new_path, "file.txt");
+        assert!(!diff.is_binary);
+        assert!(!diff.hunks.is_empty());
+
+        // Should have additions
+        let has_addition = diff
+            .hunks
+            .iter()
+            .any(|h| h.lines.iter().any(|l| l.line_type == LineType::Addition));
+        assert!(has_addition);
+    }
+
+    #[test]
+    fn test_get_working_file_diff_untracked() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create untracked file
+        std::fs::write(path.join("untracked.txt"), "new file content").expect("Failed to write");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_working_file_diff(path_str, "untracked.txt").expect("Should return diff");
+
+        assert_eq!(diff.new_path, "untracked.txt");
+        assert!(!diff.is_binary);
+
+        // All lines should be additions
+        for hunk in &diff.hunks {
+            for line in &hunk.lines {
+                assert_eq!(line.line_type, LineType::Addition);
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_working_file_diff_no_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = get_working_file_diff(path, "file.txt");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("no changes"));
+    }
+
+    #[test]
+    fn test_get_working_file_diff_nonexistent() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = get_working_file_diff(path, "nonexistent.txt");
+        assert!(result.is_err());
+    }
+
+    // Tests for get_working_file_contents
+
+    #[test]
+    fn test_get_working_file_contents_modified() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify file
+        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write");
+
+        let path_str = path.to_str().unwrap();
+        let contents =
+            get_working_file_contents(path_str, "file.txt").expect("Should return contents");
+
+        assert!(!contents.is_binary);
+        assert_eq!(contents.old_content, Some("content".to_string()));
+        assert_eq!(contents.new_content, Some("modified content".to_string()));
+    }
+
+    #[test]
+    fn test_get_working_file_contents_new_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create new file
+        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write");
+
+        let path_str = path.to_str().unwrap();
+        let contents =
+            get_working_file_contents(path_str, "newfile.txt").expect("Should return contents");
+
+        assert!(!contents.is_binary);
+        assert!(contents.old_content.is_none()); // File didn't exist in HEAD
+        assert_eq!(contents.new_content, Some("new content".to_string()));
+    }
+
+    #[test]
+    fn test_get_working_file_contents_deleted_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Delete file
+        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete");
+
+        let path_str = path.to_str().unwrap();
+        let contents =
+            get_working_file_contents(path_str, "file.txt").expect("Should return contents");
+
+        assert!(!contents.is_binary);
+        assert_eq!(contents.old_content, Some("content".to_string()));
+        assert!(contents.new_content.is_none()); // File was deleted
+    }
+
+    #[test]
+    fn test_get_working_file_contents_nonexistent() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = get_working_file_contents(path, "nonexistent.txt");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found"));
+    }
+
+    // Tests for list_branches
+
+    #[test]
+    fn test_list_branches_returns_current_branch() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let branches = list_branches(path).expect("Should return branches");
+
+        assert!(!branches.is_empty());
+        // Should have at least one branch marked as current
+        let current = branches.iter().find(|b| b.is_current);
+        assert!(current.is_some());
+        // Current branch should be master or main
+        let current_name = &current.unwrap().name;
+        assert!(current_name == "master" || current_name == "main");
+    }
+
+    #[test]
+    fn test_list_branches_multiple_branches() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create additional branches
+        Command::new("git")
+            .args(["branch", "feature-a"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+        Command::new("git")
+            .args(["branch", "feature-b"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+
+        let path_str = path.to_str().unwrap();
+        let branches = list_branches(path_str).expect("Should return branches");
+
+        // Should have 3 local branches
+        let local_branches: Vec<_> = branches.iter().filter(|b| !b.is_remote).collect();
+        assert_eq!(local_branches.len(), 3);
+    }
+
+    #[test]
+    fn test_list_branches_current_first() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create another branch
+        Command::new("git")
+            .args(["branch", "zzz-last"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+
+        let path_str = path.to_str().unwrap();
+        let branches = list_branches(path_str).expect("Should return branches");
+
+        // Current branch should be first
+        assert!(branches[0].is_current);
+    }
+
+    #[test]
+    fn test_list_branches_has_commit_id() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let branches = list_branches(path).expect("Should return branches");
+
+        // All branches should have a commit ID
+        for branch in &branches {
+            assert!(!branch.commit_id.is_empty());
+            assert_eq!(branch.commit_id.len(), 40);
+        }
+    }
+
+    #[test]
+    fn test_list_branches_includes_tip_summary() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        let head = list_commits(path_str, Some(1)).unwrap().remove(0);
+
+        let branches = list_branches(path_str).expect("Should list branches");
+
+        let current = branches.iter().find(|b| b.is_current).unwrap();
+        assert_eq!(current.summary, "Add file");
+        assert_eq!(current.timestamp, head.timestamp);
+        assert!(current.upstream.is_none());
+        assert_eq!((current.ahead, current.behind), (0, 0));
+    }
+
+    #[test]
+    fn test_list_branches_reports_ahead_behind_upstream() {
+        let origin = create_test_repo();
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        let current = get_current_branch(path_str).unwrap();
+
+        commit_on_branch(clone.path(), &current, "local");
+        commit_on_branch(origin.path(), &current, "remote 1");
+        commit_on_branch(origin.path(), &current, "remote 2");
+        git_fetch(clone.path());
+
+        let branches = list_branches(path_str).expect("Should list branches");
+
+        let local = branches.iter().find(|b| b.name == current).unwrap();
+        assert_eq!(local.upstream, Some(format!("origin/{}", current)));
+        assert_eq!((local.ahead, local.behind), (1, 2));
+        let remote = branches
+            .iter()
+            .find(|b| b.name == format!("origin/{}", current))
+            .unwrap();
+        assert!(remote.upstream.is_none());
+    }
+
+    #[test]
+    fn test_list_branches_reports_merged_into_default_branch() {
+        let temp_dir = create_repo_with_diverging_branch();
+        let path_str = temp_dir.path().to_str().unwrap();
+        create_branch(path_str, "old", Some("HEAD~1"), false).expect("Should create branch");
+
+        let branches = list_branches(path_str).expect("Should list branches");
+
+        let find = |name: &str| branches.iter().find(|b| b.name == name).unwrap();
+        assert!(find("old").is_merged);
+        assert!(!find("feature").is_merged);
+        assert!(branches.iter().find(|b| b.is_current).unwrap().is_merged);
+    }
+
+    #[test]
+    fn test_list_branches_tolerates_dangling_ref() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        std::fs::write(
+            path.join(".git/refs/heads/dangling"),
+            "1111111111111111111111111111111111111111\n",
+        )
+        .expect("Failed to write ref");
+
+        let branches = list_branches(path.to_str().unwrap()).expect("Should list branches");
+
+        assert_eq!(branches.len(), 2);
+        let dangling = branches.iter().find(|b| b.name == "dangling").unwrap();
+        assert_eq!(dangling.commit_id, "");
+        assert_eq!(dangling.summary, "");
+        assert!(!dangling.is_merged);
+        assert!(branches
+            .iter()
+            .any(|b| b.is_current && !b.commit_id.is_empty()));
+    }
+
+    #[test]
+    fn test_list_branches_invalid_path() {
+        let result = list_branches("/nonexistent/path");
+        assert!(result.is_err());
+    }
+
+    // Tests for checkout_branch
+
+    #[test]
+    fn test_checkout_branch_switches_branch() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create a new branch
+        Command::new("git")
+            .args(["branch", "feature"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+
+        let path_str = path.to_str().unwrap();
+
+        // Checkout the new branch
+        checkout_branch(path_str, "feature", CheckoutMode::RequireClean)
+            .expect("Should checkout branch");
+
+        // Verify we're on the new branch
+        let current = get_current_branch(path_str).expect("Should get current branch");
+        assert_eq!(current, "feature");
+    }
+
+    #[test]
+    fn test_checkout_branch_nonexistent() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = checkout_branch(path, "nonexistent-branch", CheckoutMode::RequireClean);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found"));
+    }
+
+    #[test]
+    fn test_checkout_branch_with_uncommitted_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create a new branch
+        Command::new("git")
+            .args(["branch", "feature"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+
+        // Make uncommitted changes
+        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let result = checkout_branch(path_str, "feature", CheckoutMode::RequireClean);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("uncommitted changes"));
+    }
+
+    #[test]
+    fn test_checkout_branch_with_untracked_files_allowed() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create a new branch
+        Command::new("git")
+            .args(["branch", "feature"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+
+        // Create untracked file (should not block checkout)
+        std::fs::write(path.join("untracked.txt"), "new file").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let result = checkout_branch(path_str, "feature", CheckoutMode::RequireClean);
+
+        // Untracked files should not block checkout
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_checkout_branch_updates_working_directory() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        // Get the current branch name (master or main)
+        let main_branch = get_current_branch(path_str).expect("Should get branch");
+
+        // Create a new branch and add a file
+        Command::new("git")
+            .args(["checkout", "-b", "feature"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+
+        std::fs::write(path.join("feature-file.txt"), "feature content")
+            .expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Add feature file"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to commit");
+
+        // Go back to main/master using git command
+        Command::new("git")
+            .args(["checkout", &main_branch])
+            .current_dir(path)
+            .output()
+            .expect("Failed to checkout main");
+
+        // File should not exist on main branch
+        assert!(!path.join("feature-file.txt").exists());
+
+        // Checkout feature branch using our function
+        checkout_branch(path_str, "feature", CheckoutMode::RequireClean)
+            .expect("Should checkout branch");
+
+        // File should now exist
+        assert!(path.join("feature-file.txt").exists());
+    }
+
+    #[test]
+    fn test_checkout_branch_invalid_path() {
+        let result = checkout_branch("/nonexistent/path", "main", CheckoutMode::RequireClean);
+        assert!(result.is_err());
+    }
+
+    /// Commits a ten-line lines.txt on the current branch, then creates a
+    /// "feature" branch whose last line differs. Leaves the original branch checked out.
+    fn create_repo_with_diverging_branch() -> TempDir {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        let lines: Vec<String> = (1..=10).map(|i| format!("line {}", i)).collect();
+        std::fs::write(path.join("lines.txt"), lines.join("\n") + "\n")
+            .expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "."])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Add lines"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to commit");
+
+        Command::new("git")
+            .args(["checkout", "-b", "feature"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create branch");
+        let mut feature_lines = lines.clone();
+        feature_lines[9] = "line 10 on feature".to_string();
+        std::fs::write(path.join("lines.txt"), feature_lines.join("\n") + "\n")
+            .expect("Failed to write file");
+        std::fs::write(path.join("file.txt"), "feature").expect("Failed to write file");
+        Command::new("git")
+            .args(["commit", "-am", "Change on feature"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to commit");
+
+        Command::new("git")
+            .args(["checkout", "-"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to checkout original branch");
+
+        temp_dir
+    }
+
+    #[test]
+    fn test_checkout_branch_safe_carries_unrelated_changes() {
+        let temp_dir = create_repo_with_diverging_branch();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(path.join("README.md"), "# Local").expect("Failed to write file");
+
+        let result =
+            checkout_branch(path_str, "feature", CheckoutMode::Safe).expect("Should checkout");
+
+        assert!(result.conflicts.is_empty());
+        assert_eq!(get_current_branch(path_str).unwrap(), "feature");
+        assert_eq!(
+            std::fs::read_to_string(path.join("README.md")).unwrap(),
+            "# Local"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "feature"
+        );
+    }
+
+    #[test]
+    fn test_checkout_branch_safe_fails_on_conflicting_changes() {
+        let temp_dir = create_repo_with_diverging_branch();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let original = get_current_branch(path_str).unwrap();
+
+        std::fs::write(path.join("file.txt"), "local").expect("Failed to write file");
+
+        let result = checkout_branch(path_str, "feature", CheckoutMode::Safe);
+
+        assert!(result.is_err());
+        let error = result.unwrap_err();
+        assert!(error.contains("would be overwritten"));
+        assert!(error.contains("file.txt"));
+        assert_eq!(get_current_branch(path_str).unwrap(), original);
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "local"
+        );
+    }
+
+    #[test]
+    fn test_checkout_branch_autostash_reapplies_changes() {
+        let temp_dir = create_repo_with_diverging_branch();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        let content = std::fs::read_to_string(path.join("lines.txt")).unwrap();
+        std::fs::write(
+            path.join("lines.txt"),
+            content.replace("line 1\n", "line 1 local\n"),
+        )
+        .expect("Failed to write file");
+
+        let result =
+            checkout_branch(path_str, "feature", CheckoutMode::AutoStash).expect("Should checkout");
+
+        assert!(result.conflicts.is_empty());
+        assert!(result.stash.is_none());
+        assert_eq!(get_current_branch(path_str).unwrap(), "feature");
+        let content = std::fs::read_to_string(path.join("lines.txt")).unwrap();
+        assert!(content.contains("line 1 local\n"));
+        assert!(content.contains("line 10 on feature\n"));
+        assert!(list_stashes(path_str).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_checkout_branch_autostash_reports_conflicts() {
+        let temp_dir = create_repo_with_diverging_branch();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(path.join("file.txt"), "local").expect("Failed to write file");
+
+        let result = checkout_branch(path_str, "feature", CheckoutMode::AutoStash)
+            .expect("Should checkout with conflicts");
+
+        assert_eq!(result.conflicts, vec!["file.txt".to_string()]);
+        let stash = result.stash.expect("Stash should be kept");
+        assert!(stash.message.ends_with(": autostash"));
+        assert_eq!(get_current_branch(path_str).unwrap(), "feature");
+        assert_eq!(list_stashes(path_str).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_checkout_branch_autostash_without_changes() {
+        let temp_dir = create_repo_with_diverging_branch();
+        let path_str = temp_dir.path().to_str().unwrap();
+
+        let result =
+            checkout_branch(path_str, "feature", CheckoutMode::AutoStash).expect("Should checkout");
+
+        assert!(result.conflicts.is_empty());
+        assert!(result.stash.is_none());
+        assert_eq!(get_current_branch(path_str).unwrap(), "feature");
+    }
+
+    /// Clones `origin` into a new temp directory, with the same test identity
+    fn clone_test_repo(origin: &std::path::Path) -> TempDir {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+
+        Command::new("git")
+            .arg("clone")
+            .arg(origin)
+            .arg(temp_dir.path())
+            .output()
+            .expect("Failed to clone repo");
+
+        for (key, value) in [
+            ("user.email", "test@example.com"),
+            ("user.name", "Test User"),
+        ] {
+            Command::new("git")
+                .args(["config", key, value])
+                .current_dir(temp_dir.path())
+                .output()
+                .expect("Failed to set git config");
+        }
+
+        temp_dir
+    }
+
+    /// Commits a change to file.txt on `branch` in `path`, creating the branch if needed,
+    /// then switches back to the previously checked out branch
+    fn commit_on_branch(path: &std::path::Path, branch: &str, content: &str) {
+        let existing = Command::new("git")
+            .args(["checkout", branch])
+            .current_dir(path)
+            .output()
+            .expect("Failed to checkout branch");
+        if !existing.status.success() {
+            Command::new("git")
+                .args(["checkout", "-b", branch])
+                .current_dir(path)
+                .output()
+                .expect("Failed to create branch");
+        }
+        std::fs::write(path.join("file.txt"), content).expect("Failed to write file");
+        Command::new("git")
+            .args(["commit", "-am", content])
+            .current_dir(path)
+            .output()
+            .expect("Failed to commit");
+        Command::new("git")
+            .args(["checkout", "-"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to switch back");
+    }
+
+    fn git_fetch(path: &std::path::Path) {
+        Command::new("git")
+            .args(["fetch", "origin"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to fetch");
+    }
+
+    #[test]
+    fn test_checkout_remote_branch_creates_tracking_branch() {
+        let origin = create_test_repo();
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        commit_on_branch(origin.path(), "feature-x", "remote feature");
+        git_fetch(clone.path());
+
+        checkout_remote_branch(path_str, "origin/feature-x", CheckoutMode::Safe)
+            .expect("Should checkout remote branch");
+
+        assert_eq!(get_current_branch(path_str).unwrap(), "feature-x");
+        assert_eq!(
+            std::fs::read_to_string(clone.path().join("file.txt")).unwrap(),
+            "remote feature"
+        );
+        let repo = Repository::open(clone.path()).unwrap();
+        let local = repo.find_branch("feature-x", BranchType::Local).unwrap();
+        let upstream = local.upstream().expect("Upstream should be set");
+        assert_eq!(upstream.name().unwrap(), Some("origin/feature-x"));
+    }
+
+    #[test]
+    fn test_checkout_remote_branch_fast_forwards_local_branch() {
+        let origin = create_test_repo();
+        commit_on_branch(origin.path(), "feature-x", "first");
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        Command::new("git")
+            .args(["branch", "--track", "feature-x", "origin/feature-x"])
+            .current_dir(clone.path())
+            .output()
+            .expect("Failed to create tracking branch");
+
+        commit_on_branch(origin.path(), "feature-x", "second");
+        git_fetch(clone.path());
+
+        checkout_remote_branch(path_str, "origin/feature-x", CheckoutMode::Safe)
+            .expect("Should checkout remote branch");
+
+        assert_eq!(get_current_branch(path_str).unwrap(), "feature-x");
+        assert_eq!(
+            std::fs::read_to_string(clone.path().join("file.txt")).unwrap(),
+            "second"
+        );
+    }
+
+    #[test]
+    fn test_checkout_remote_branch_refuses_diverged_local_branch() {
+        let origin = create_test_repo();
+        commit_on_branch(origin.path(), "feature-x", "first");
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        let original = get_current_branch(path_str).unwrap();
+
+        Command::new("git")
+            .args(["branch", "--track", "feature-x", "origin/feature-x"])
+            .current_dir(clone.path())
+            .output()
+            .expect("Failed to create tracking branch");
+        commit_on_branch(clone.path(), "feature-x", "local");
+        commit_on_branch(origin.path(), "feature-x", "remote");
+        git_fetch(clone.path());
+
+        let result = checkout_remote_branch(path_str, "origin/feature-x", CheckoutMode::Safe);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("diverged"));
+        assert_eq!(get_current_branch(path_str).unwrap(), original);
+    }
+
+    #[test]
+    fn test_checkout_remote_branch_diverged_keeps_upstream_unset() {
+        let origin = create_test_repo();
+        commit_on_branch(origin.path(), "feature-x", "first");
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+
+        git_in(
+            clone.path(),
+            &["branch", "--no-track", "feature-x", "origin/feature-x"],
+        );
+        commit_on_branch(clone.path(), "feature-x", "local");
+        commit_on_branch(origin.path(), "feature-x", "remote");
+        git_fetch(clone.path());
+
+        let result = checkout_remote_branch(path_str, "origin/feature-x", CheckoutMode::Safe);
+
+        assert!(result.unwrap_err().contains("diverged"));
+        let repo = Repository::open(clone.path()).unwrap();
+        let local = repo.find_branch("feature-x", BranchType::Local).unwrap();
+        assert!(local.upstream().is_err());
+    }
+
+    #[test]
+    fn test_checkout_remote_branch_removes_created_branch_when_blocked() {
+        let origin = create_test_repo();
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        commit_on_branch(origin.path(), "feature-x", "remote feature");
+        git_fetch(clone.path());
+        std::fs::write(clone.path().join("file.txt"), "local").expect("Failed to write file");
+
+        let result = checkout_remote_branch(path_str, "origin/feature-x", CheckoutMode::Safe);
+
+        assert!(result.is_err());
+        let repo = Repository::open(clone.path()).unwrap();
+        assert!(repo.find_branch("feature-x", BranchType::Local).is_err());
+    }
+
+    #[test]
+    fn test_checkout_remote_branch_not_found() {
+        let origin = create_test_repo();
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+
+        let result = checkout_remote_branch(path_str, "origin/missing", CheckoutMode::Safe);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found"));
+    }
+
+    #[test]
+    fn test_checkout_detached_commit() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        let commits = list_commits(path_str, None).unwrap();
+
+        checkout_detached(path_str, &commits[1].id, CheckoutMode::Safe)
+            .expect("Should checkout commit");
+
+        let repo = Repository::open(temp_dir.path()).unwrap();
+        assert!(repo.head_detached().unwrap());
+        assert_eq!(get_current_branch(path_str).unwrap(), commits[1].id);
+        assert!(!temp_dir.path().join("file.txt").exists());
+    }
+
+    #[test]
+    fn test_checkout_detached_tag() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, None).unwrap();
+        Command::new("git")
+            .args(["tag", "-a", "v1.0", "-m", "Release", &commits[1].id])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create tag");
+
+        checkout_detached(path_str, "v1.0", CheckoutMode::RequireClean)
+            .expect("Should checkout tag");
+
+        assert_eq!(get_current_branch(path_str).unwrap(), commits[1].id);
+    }
+
+    #[test]
+    fn test_checkout_detached_unknown_revision() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+
+        let result = checkout_detached(path_str, "no-such-tag", CheckoutMode::Safe);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found"));
+    }
+
+    // Tests for branch management
+
+    #[test]
+    fn test_create_branch_from_head() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        let head = list_commits(path_str, Some(1)).unwrap()[0].id.clone();
+
+        let branch = create_branch(path_str, "feature", None, false).expect("Should create branch");
+
+        assert_eq!(branch.name, "feature");
+        assert_eq!(branch.commit_id, head);
+        assert!(!branch.is_current);
+        assert_ne!(get_current_branch(path_str).unwrap(), "feature");
+    }
+
+    #[test]
+    fn test_create_branch_from_revision_and_checkout() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, None).unwrap();
+        std::fs::write(path.join("README.md"), "# Local").expect("Failed to write file");
+
+        let branch =
+            create_branch(path_str, "old", Some("HEAD~1"), true).expect("Should create branch");
+
+        assert_eq!(branch.commit_id, commits[1].id);
+        assert!(branch.is_current);
+        assert_eq!(get_current_branch(path_str).unwrap(), "old");
+        assert!(!path.join("file.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(path.join("README.md")).unwrap(),
+            "# Local"
+        );
+    }
+
+    #[test]
+    fn test_create_branch_checkout_blocked_removes_branch() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        std::fs::write(path.join("file.txt"), "local").expect("Failed to write file");
+
+        let result = create_branch(path_str, "old", Some("HEAD~1"), true);
+
+        assert!(result.is_err());
+        assert!(list_branches(path_str)
+            .unwrap()
+            .iter()
+            .all(|b| b.name != "old"));
+    }
+
+    #[test]
+    fn test_create_branch_rejects_existing_and_invalid_names() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        create_branch(path_str, "feature", None, false).expect("Should create branch");
+
+        let existing = create_branch(path_str, "feature", None, false);
+        assert!(existing.unwrap_err().contains("already exists"));
+
+        let invalid = create_branch(path_str, "bad..name", None, false);
+        assert!(invalid.unwrap_err().contains("not a valid branch name"));
+    }
+
+    #[test]
+    fn test_rename_branch() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        create_branch(path_str, "feature", None, false).expect("Should create branch");
+
+        let renamed = rename_branch(path_str, "feature", "renamed").expect("Should rename");
+
+        assert_eq!(renamed.name, "renamed");
+        let names: Vec<String> = list_branches(path_str)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.name)
+            .collect();
+        assert!(names.contains(&"renamed".to_string()));
+        assert!(!names.contains(&"feature".to_string()));
+    }
+
+    #[test]
+    fn test_rename_current_branch_keeps_it_checked_out() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        let current = get_current_branch(path_str).unwrap();
+
+        let renamed = rename_branch(path_str, &current, "trunk").expect("Should rename");
+
+        assert!(renamed.is_current);
+        assert_eq!(get_current_branch(path_str).unwrap(), "trunk");
+    }
+
+    #[test]
+    fn test_delete_merged_branch() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        create_branch(path_str, "merged", Some("HEAD~1"), false).expect("Should create branch");
+
+        delete_branch(path_str, "merged", false).expect("Should delete merged branch");
+
+        assert!(list_branches(path_str)
+            .unwrap()
+            .iter()
+            .all(|b| b.name != "merged"));
+    }
+
+    #[test]
+    fn test_delete_unmerged_branch_requires_force() {
+        let temp_dir = create_repo_with_diverging_branch();
+        let path_str = temp_dir.path().to_str().unwrap();
+
+        let result = delete_branch(path_str, "feature", false);
+        assert!(result.unwrap_err().contains("not fully merged"));
+
+        delete_branch(path_str, "feature", true).expect("Should force delete");
+        assert!(list_branches(path_str)
+            .unwrap()
+            .iter()
+            .all(|b| b.name != "feature"));
+    }
+
+    #[test]
+    fn test_delete_current_branch_fails() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        let current = get_current_branch(path_str).unwrap();
+
+        let result = delete_branch(path_str, &current, true);
+
+        assert!(result.unwrap_err().contains("currently checked out"));
+    }
+
+    #[test]
+    fn test_set_and_unset_branch_upstream() {
+        let origin = create_test_repo();
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        let current = get_current_branch(path_str).unwrap();
+        create_branch(path_str, "feature", None, false).expect("Should create branch");
+        let upstream = format!("origin/{}", current);
+
+        set_branch_upstream(path_str, "feature", Some(&upstream)).expect("Should set upstream");
+
+        let repo = Repository::open(clone.path()).unwrap();
+        let branch = repo.find_branch("feature", BranchType::Local).unwrap();
+        assert_eq!(
+            branch.upstream().unwrap().name().unwrap(),
+            Some(upstream.as_str())
+        );
+
+        set_branch_upstream(path_str, "feature", None).expect("Should unset upstream");
+
+        let branch = repo.find_branch("feature", BranchType::Local).unwrap();
+        assert!(branch.upstream().is_err());
+    }
+
+    // Tests for get_working_changes_ex with staged/unstaged split
+
+    #[test]
+    fn test_get_working_changes_ex_returns_empty_when_no_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let changes = get_working_changes_ex(path).expect("Should return changes");
+
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn test_get_working_changes_ex_unstaged_file_appears_in_unstaged_only() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify file without staging
+        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "file.txt");
+        assert!(changes[0].unstaged_status.is_some());
+        assert!(changes[0].staged_status.is_none());
+        assert_eq!(changes[0].unstaged_status, Some(FileStatus::Modified));
+    }
+
+    #[test]
+    fn test_get_working_changes_ex_staged_file_appears_in_staged_only() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify and stage file
+        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "file.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "file.txt");
+        assert!(changes[0].staged_status.is_some());
+        assert!(changes[0].unstaged_status.is_none());
+        assert_eq!(changes[0].staged_status, Some(FileStatus::Modified));
+    }
+
+    #[test]
+    fn test_get_working_changes_ex_file_with_both_staged_and_unstaged_appears_twice() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify and stage file
+        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "file.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        // Modify again (now has both staged and unstaged changes)
+        std::fs::write(path.join("file.txt"), "staged and unstaged content")
+            .expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 2);
+
+        // Find staged entry
+        let staged = changes
+            .iter()
+            .find(|c| c.staged_status.is_some())
+            .expect("Should have staged entry");
+        assert_eq!(staged.path, "file.txt");
+        assert_eq!(staged.staged_status, Some(FileStatus::Modified));
+        assert!(staged.unstaged_status.is_none());
+
+        // Find unstaged entry
+        let unstaged = changes
+            .iter()
+            .find(|c| c.unstaged_status.is_some())
+            .expect("Should have unstaged entry");
+        assert_eq!(unstaged.path, "file.txt");
+        assert_eq!(unstaged.unstaged_status, Some(FileStatus::Modified));
+        assert!(unstaged.staged_status.is_none());
+    }
+
+    #[test]
+    fn test_get_working_changes_ex_tracks_line_counts_separately() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create file with staged changes
+        std::fs::write(path.join("file.txt"), "line1\nline2").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "file.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        // Modify with unstaged changes
+        std::fs::write(path.join("file.txt"), "line1\nline2\nline3").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 2);
+
+        let staged = changes.iter().find(|c| c.staged_status.is_some()).unwrap();
+        let unstaged = changes.iter().find(|c| c.unstaged_status.is_some()).unwrap();
+
+        // Staged should have counts for staging "line1\nline2"
+        assert!(staged.staged_additions > 0);
+        assert_eq!(staged.unstaged_additions, 0);
+
+        // Unstaged should have counts for adding "line3"
+        assert!(unstaged.unstaged_additions > 0);
+        assert_eq!(unstaged.staged_additions, 0);
+    }
+
+    #[test]
+    fn test_get_working_changes_ex_new_file_staged_only() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create and stage new file
+        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "newfile.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "newfile.txt");
+        assert_eq!(changes[0].staged_status, Some(FileStatus::Added));
+        assert!(changes[0].unstaged_status.is_none());
+    }
+
+    #[test]
+    fn test_get_working_changes_ex_untracked_file_appears_in_unstaged() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create new file without adding
+        std::fs::write(path.join("untracked.txt"), "untracked content").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "untracked.txt");
+        assert_eq!(changes[0].unstaged_status, Some(FileStatus::Untracked));
+        assert!(changes[0].staged_status.is_none());
+    }
+
+    // Tests for get_staged_file_diff
+
+    #[test]
+    fn test_get_staged_file_diff_shows_staged_changes_only() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify and stage the file
+        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "file.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        // Modify again (unstaged changes)
+        std::fs::write(path.join("file.txt"), "staged and unstaged content")
+            .expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_staged_file_diff(path_str, "file.txt").expect("Should return diff");
+
+        // Staged diff should only show "staged content", not the unstaged changes
+        assert_eq!(diff.new_path, "file.txt");
+        assert!(!diff.is_binary);
+        assert!(!diff.hunks.is_empty());
+
+        // The diff should contain "staged content" (what was staged)
+        let content: String = diff
+            .hunks
+            .iter()
+            .flat_map(|h| &h.lines)
+            .map(|l| &l.content[..])
+            .collect();
+        assert!(content.contains("staged content"));
+        assert!(!content.contains("unstaged"));
+    }
+
+    #[test]
+    fn test_get_staged_file_diff_returns_error_for_unstaged_only_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify file without staging
+        std::fs::write(path.join("file.txt"), "unstaged changes").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let result = get_staged_file_diff(path_str, "file.txt");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_get_staged_file_diff_new_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create and stage new file
+        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "newfile.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_staged_file_diff(path_str, "newfile.txt").expect("Should return diff");
+
+        assert_eq!(diff.new_path, "newfile.txt");
+        assert!(!diff.is_binary);
+        assert!(!diff.hunks.is_empty());
+
+        // Should have at least one addition line (new file)
+        let has_addition = diff
+            .hunks
+            .iter()
+            .flat_map(|h| &h.lines)
+            .any(|l| l.line_type == LineType::Addition);
+        assert!(has_addition, "New file should have addition lines");
+    }
+
+    // Tests for get_unstaged_file_diff
+
+    #[test]
+    fn test_get_unstaged_file_diff_shows_unstaged_changes_only() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify and stage
+        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "file.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        // Modify again (unstaged)
+        std::fs::write(path.join("file.txt"), "staged and unstaged content")
+            .expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_unstaged_file_diff(path_str, "file.txt").expect("Should return diff");
+
+        // Unstaged diff should show the difference between staged and working
+        assert_eq!(diff.new_path, "file.txt");
+        assert!(!diff.is_binary);
+
+        // The diff should show the transition from "staged content" to final content
+        let has_context_or_change = diff
+            .hunks
+            .iter()
+            .flat_map(|h| &h.lines)
+            .any(|l| l.line_type == LineType::Addition || l.line_type == LineType::Context);
+        assert!(has_context_or_change);
+    }
+
+    #[test]
+    fn test_get_unstaged_file_diff_unstaged_only_shows_vs_head() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify without staging
+        std::fs::write(path.join("file.txt"), "only unstaged").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_unstaged_file_diff(path_str, "file.txt").expect("Should return diff");
+
+        assert_eq!(diff.new_path, "file.txt");
+        assert!(!diff.is_binary);
+    }
+
+    #[test]
+    fn test_get_unstaged_file_diff_returns_error_when_no_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        // No changes to file.txt
+        let result = get_unstaged_file_diff(path, "file.txt");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_get_unstaged_file_diff_untracked_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create untracked file
+        std::fs::write(path.join("untracked.txt"), "new untracked").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_unstaged_file_diff(path_str, "untracked.txt").expect("Should return diff");
+
+        assert_eq!(diff.new_path, "untracked.txt");
+        assert!(!diff.is_binary);
+    }
+
+    // Tests for stage_file
+
+    #[test]
+    fn test_stage_file_stages_modification() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        stage_file(path_str, "file.txt").expect("Should stage file");
+
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].section, "staged");
+        assert_eq!(changes[0].staged_status, Some(FileStatus::Modified));
+    }
+
+    #[test]
+    fn test_stage_file_adds_untracked_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::create_dir(path.join("dir")).expect("Failed to create dir");
+        std::fs::write(path.join("dir/new.txt"), "new content").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        stage_file(path_str, "dir/new.txt").expect("Should stage file");
+
+        let contents =
+            get_staged_file_contents(path_str, "dir/new.txt").expect("Should get contents");
+        assert_eq!(contents.old_content, None);
+        assert_eq!(contents.new_content, Some("new content".to_string()));
+    }
+
+    #[test]
+    fn test_stage_file_stages_deletion() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete file");
+
+        let path_str = path.to_str().unwrap();
+        stage_file(path_str, "file.txt").expect("Should stage deletion");
+
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].staged_status, Some(FileStatus::Deleted));
+        assert_eq!(changes[0].unstaged_status, None);
+    }
+
+    #[test]
+    fn test_stage_file_replaces_intent_to_add_entry() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("intent.txt"), "intended").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "-N", "intent.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to mark intent to add");
+
+        let path_str = path.to_str().unwrap();
+        stage_file(path_str, "intent.txt").expect("Should stage file");
+
+        let contents =
+            get_staged_file_contents(path_str, "intent.txt").expect("Should get contents");
+        assert_eq!(contents.new_content, Some("intended".to_string()));
+
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes
+            .iter()
+            .all(|c| c.path != "intent.txt" || c.unstaged_status.is_none()));
+    }
+
+    #[test]
+    fn test_stage_file_nonexistent() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let result = stage_file(path, "nonexistent.txt");
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found"));
+    }
+
+    #[test]
+    fn test_stage_all_stages_every_change() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("file.txt"), "modified").expect("Failed to write file");
+        std::fs::remove_file(path.join("README.md")).expect("Failed to delete file");
+        std::fs::write(path.join("new.txt"), "new").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        stage_all(path_str).expect("Should stage all");
+
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert_eq!(changes.len(), 3);
+        assert!(changes.iter().all(|c| c.section == "staged"));
+        assert!(changes
+            .iter()
+            .any(|c| c.path == "README.md" && c.staged_status == Some(FileStatus::Deleted)));
+        assert!(changes
+            .iter()
+            .any(|c| c.path == "new.txt" && c.staged_status == Some(FileStatus::Added)));
+    }
+
+    // Tests for hunk staging
+
+    /// Commits a 20-line file and modifies lines 2 and 19, producing two hunks
+    fn create_repo_with_two_hunks() -> TempDir {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        let original: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(path.join("lines.txt"), &original).expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "lines.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to add files");
+        Command::new("git")
+            .args(["commit", "-m", "Add lines"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to create commit");
+
+        let modified = original
+            .replace("line 2\n", "line 2 changed\n")
+            .replace("line 19\n", "line 19 changed\n");
+        std::fs::write(path.join("lines.txt"), modified).expect("Failed to write file");
+
+        temp_dir
+    }
+
+    #[test]
+    fn test_diff_hunks_have_header_and_hash() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let diff = get_unstaged_file_diff(path, "lines.txt").expect("Should return diff");
+
+        assert_eq!(diff.hunks.len(), 2);
+        assert!(diff.hunks[0].header.starts_with("@@ -1,5 +1,5 @@"));
+        assert_eq!(diff.hunks[0].hash.len(), 40);
+        assert_ne!(diff.hunks[0].hash, diff.hunks[1].hash);
+    }
+
+    #[test]
+    fn test_stage_hunk_stages_only_selected_hunk() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let diff = get_unstaged_file_diff(path, "lines.txt").expect("Should return diff");
+        let hunk = &diff.hunks[0];
+
+        stage_hunk(path, "lines.txt", &hunk.header, &hunk.hash).expect("Should stage hunk");
+
+        let staged = get_staged_file_contents(path, "lines.txt").expect("Should get contents");
+        let staged_content = staged.new_content.unwrap();
+        assert!(staged_content.contains("line 2 changed\n"));
+        assert!(staged_content.contains("line 19\n"));
+
+        let unstaged = get_unstaged_file_diff(path, "lines.txt").expect("Should return diff");
+        assert_eq!(unstaged.hunks.len(), 1);
+        assert_eq!(unstaged.hunks[0].hash, diff.hunks[1].hash);
+    }
+
+    #[test]
+    fn test_stage_hunk_adds_untracked_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("new.txt"), "one\ntwo\n").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_unstaged_file_diff(path_str, "new.txt").expect("Should return diff");
+        let hunk = &diff.hunks[0];
+
+        stage_hunk(path_str, "new.txt", &hunk.header, &hunk.hash).expect("Should stage hunk");
+
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].staged_status, Some(FileStatus::Added));
+    }
+
+    #[test]
+    fn test_stage_hunk_rejects_stale_hunk() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        let diff = get_unstaged_file_diff(path_str, "lines.txt").expect("Should return diff");
+        let hunk = &diff.hunks[0];
+
+        // The file changes after the diff was displayed
+        let content = std::fs::read_to_string(path.join("lines.txt")).unwrap();
+        std::fs::write(
+            path.join("lines.txt"),
+            content.replace("line 3\n", "line 3 also\n"),
+        )
+        .expect("Failed to write file");
+
+        let result = stage_hunk(path_str, "lines.txt", &hunk.header, &hunk.hash);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("no longer matches"));
+        assert!(get_staged_file_diff(path_str, "lines.txt").is_err());
+    }
+
+    #[test]
+    fn test_unstage_hunk_unstages_only_selected_hunk() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path().to_str().unwrap();
+
+        stage_file(path, "lines.txt").expect("Should stage file");
+        let diff = get_staged_file_diff(path, "lines.txt").expect("Should return diff");
+        let hunk = &diff.hunks[1];
+
+        unstage_hunk(path, "lines.txt", &hunk.header, &hunk.hash).expect("Should unstage hunk");
+
+        let staged = get_staged_file_diff(path, "lines.txt").expect("Should return diff");
+        assert_eq!(staged.hunks.len(), 1);
+        assert_eq!(staged.hunks[0].hash, diff.hunks[0].hash);
+
+        let unstaged = get_unstaged_file_diff(path, "lines.txt").expect("Should return diff");
+        assert_eq!(unstaged.hunks.len(), 1);
+        assert!(unstaged.hunks[0]
+            .lines
+            .iter()
+            .any(|l| l.line_type == LineType::Addition && l.content == "line 19 changed\n"));
+    }
+
+    #[test]
+    fn test_discard_hunk_reverts_only_selected_hunk() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        let diff = get_unstaged_file_diff(path_str, "lines.txt").expect("Should return diff");
+        let hunk = &diff.hunks[0];
+
+        discard_hunk(path_str, "lines.txt", &hunk.header, &hunk.hash, false)
+            .expect("Should discard hunk");
+
+        let content = std::fs::read_to_string(path.join("lines.txt")).unwrap();
+        assert!(content.contains("line 2\n"));
+        assert!(content.contains("line 19 changed\n"));
+        assert!(get_staged_file_diff(path_str, "lines.txt").is_err());
+    }
+
+    // Tests for line staging
+
+    fn select_line(diff: &FileDiff, content: &str, line_type: LineType) -> DiffLineSelection {
+        let line = diff
+            .hunks
+            .iter()
+            .flat_map(|h| h.lines.iter())
+            .find(|l| l.content == content && l.line_type == line_type)
+            .expect("Line should be in diff");
+
+        DiffLineSelection {
+            line_type: line.line_type.clone(),
+            old_line_no: line.old_line_no,
+            new_line_no: line.new_line_no,
+        }
+    }
+
+    #[test]
+    fn test_stage_lines_stages_only_selected_addition() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let diff = get_unstaged_file_diff(path, "lines.txt").expect("Should return diff");
+        let selection = select_line(&diff, "line 2 changed\n", LineType::Addition);
+
+        stage_lines(path, "lines.txt", &[selection]).expect("Should stage lines");
+
+        // The deletion of "line 2" was not selected, so both lines are staged
+        let staged = get_staged_file_contents(path, "lines.txt").expect("Should get contents");
+        let staged_content = staged.new_content.unwrap();
+        assert!(staged_content.starts_with("line 1\nline 2\nline 2 changed\nline 3\n"));
+        assert!(staged_content.contains("line 19\n"));
+        assert!(!staged_content.contains("line 19 changed"));
+    }
+
+    #[test]
+    fn test_stage_lines_stages_deletion_from_second_hunk() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let diff = get_unstaged_file_diff(path, "lines.txt").expect("Should return diff");
+        let selection = select_line(&diff, "line 19\n", LineType::Deletion);
+
+        stage_lines(path, "lines.txt", &[selection]).expect("Should stage lines");
+
+        let staged = get_staged_file_contents(path, "lines.txt").expect("Should get contents");
+        let staged_content = staged.new_content.unwrap();
+        assert!(staged_content.contains("line 2\n"));
+        assert!(staged_content.ends_with("line 18\nline 20\n"));
+    }
+
+    #[test]
+    fn test_stage_lines_partially_adds_untracked_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("new.txt"), "one\ntwo\nthree\n").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        let diff = get_unstaged_file_diff(path_str, "new.txt").expect("Should return diff");
+        let selections = vec![
+            select_line(&diff, "one\n", LineType::Addition),
+            select_line(&diff, "three\n", LineType::Addition),
+        ];
+
+        stage_lines(path_str, "new.txt", &selections).expect("Should stage lines");
+
+        let staged = get_staged_file_contents(path_str, "new.txt").expect("Should get contents");
+        assert_eq!(staged.new_content, Some("one\nthree\n".to_string()));
+    }
+
+    #[test]
+    fn test_stage_lines_rejects_stale_selection() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path().to_str().unwrap();
+
+        let selection = DiffLineSelection {
+            line_type: LineType::Addition,
+            old_line_no: None,
+            new_line_no: Some(10),
+        };
+
+        let result = stage_lines(path, "lines.txt", &[selection]);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("no longer match"));
+    }
+
+    #[test]
+    fn test_unstage_lines_unstages_only_selected_addition() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path().to_str().unwrap();
+
+        stage_file(path, "lines.txt").expect("Should stage file");
+        let diff = get_staged_file_diff(path, "lines.txt").expect("Should return diff");
+        let selection = select_line(&diff, "line 2 changed\n", LineType::Addition);
+
+        unstage_lines(path, "lines.txt", &[selection]).expect("Should unstage lines");
+
+        // The deletion of "line 2" stays staged, its replacement does not
+        let staged = get_staged_file_contents(path, "lines.txt").expect("Should get contents");
+        let staged_content = staged.new_content.unwrap();
+        assert!(staged_content.starts_with("line 1\nline 3\n"));
+        assert!(staged_content.contains("line 19 changed\n"));
+    }
+
+    #[test]
+    fn test_unstage_lines_every_line_in_empty_repository() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let path = temp_dir.path();
+
+        Command::new("git")
+            .args(["init"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to init git repo");
+        std::fs::write(path.join("first.txt"), "one\ntwo\n").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        stage_file(path_str, "first.txt").expect("Should stage file");
+        let diff = get_staged_file_diff(path_str, "first.txt").expect("Should return diff");
+        let selections = vec![
+            select_line(&diff, "one\n", LineType::Addition),
+            select_line(&diff, "two\n", LineType::Addition),
+        ];
+
+        unstage_lines(path_str, "first.txt", &selections).expect("Should unstage lines");
+
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].unstaged_status, Some(FileStatus::Untracked));
+    }
+
+    // Tests for unstage_file
+
+    #[test]
+    fn test_unstage_file_removes_staged_modification() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify and stage
+        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "file.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        let path_str = path.to_str().unwrap();
+
+        // Verify file is staged
+        let changes_before = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes_before.iter().any(|c| c.staged_status.is_some()));
+
+        // Unstage the file
+        unstage_file(path_str, "file.txt").expect("Should unstage file");
+
+        // Verify file is now unstaged only
+        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
+        let staged_count = changes_after
+            .iter()
+            .filter(|c| c.staged_status.is_some())
+            .count();
+        assert_eq!(staged_count, 0);
+
+        // Should still have unstaged changes
+        let unstaged_count = changes_after
+            .iter()
+            .filter(|c| c.unstaged_status.is_some())
+            .count();
+        assert_eq!(unstaged_count, 1);
+    }
+
+    #[test]
+    fn test_unstage_file_removes_new_file_from_index() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create and stage new file
+        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "newfile.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        let path_str = path.to_str().unwrap();
+
+        // Verify file is staged as Added
+        let changes_before = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes_before
+            .iter()
+            .any(|c| c.path == "newfile.txt" && c.staged_status == Some(FileStatus::Added)));
+
+        // Unstage the file
+        unstage_file(path_str, "newfile.txt").expect("Should unstage file");
+
+        // File should now be untracked
+        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes_after
+            .iter()
+            .any(|c| c.path == "newfile.txt" && c.unstaged_status == Some(FileStatus::Untracked)));
+    }
+
+    #[test]
+    fn test_unstage_all_resets_index_to_head() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("file.txt"), "modified").expect("Failed to write file");
+        std::fs::write(path.join("new.txt"), "new").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        stage_all(path_str).expect("Should stage all");
+        unstage_all(path_str).expect("Should unstage all");
+
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes.iter().all(|c| c.section == "unstaged"));
+        assert!(changes
+            .iter()
+            .any(|c| c.path == "new.txt" && c.unstaged_status == Some(FileStatus::Untracked)));
+        assert!(changes
+            .iter()
+            .any(|c| c.path == "file.txt" && c.unstaged_status == Some(FileStatus::Modified)));
+    }
+
+    #[test]
+    fn test_unstage_all_in_empty_repository() {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let path = temp_dir.path();
+
+        Command::new("git")
+            .args(["init"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to init git repo");
+        std::fs::write(path.join("first.txt"), "first").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        stage_file(path_str, "first.txt").expect("Should stage file");
+        unstage_all(path_str).expect("Should unstage all");
+
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].unstaged_status, Some(FileStatus::Untracked));
+    }
+
+    // Tests for discard_file
+
+    #[test]
+    fn test_discard_file_restores_modified_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify file (unstaged)
+        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+
+        // Verify file has unstaged changes
+        let changes_before = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes_before
+            .iter()
+            .any(|c| c.path == "file.txt" && c.unstaged_status.is_some()));
+
+        // Discard changes
+        discard_file(path_str, "file.txt", false).expect("Should discard changes");
+
+        // Verify file content is restored
+        let content = std::fs::read_to_string(path.join("file.txt")).expect("Should read file");
+        assert_eq!(content, "content");
+
+        // No more changes
+        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes_after.iter().all(|c| c.path != "file.txt"));
+    }
+
+    #[test]
+    fn test_discard_file_deletes_untracked_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Create untracked file
+        std::fs::write(path.join("untracked.txt"), "untracked content")
+            .expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+
+        // Verify file exists and is untracked
+        assert!(path.join("untracked.txt").exists());
+        let changes_before = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes_before.iter().any(|c| c.path == "untracked.txt"
+            && c.unstaged_status == Some(FileStatus::Untracked)));
+
+        // Discard (delete) the file
+        discard_file(path_str, "untracked.txt", false).expect("Should discard file");
+
+        // Verify file is deleted
+        assert!(!path.join("untracked.txt").exists());
+
+        // No more changes for this file
+        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes_after.iter().all(|c| c.path != "untracked.txt"));
+    }
+
+    #[test]
+    fn test_discard_file_restores_to_staged_version() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        // Modify and stage
+        std::fs::write(path.join("file.txt"), "staged version").expect("Failed to write file");
+        Command::new("git")
+            .args(["add", "file.txt"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to stage file");
+
+        // Modify again (unstaged changes on top of staged)
+        std::fs::write(path.join("file.txt"), "unstaged version").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+
+        // Discard unstaged changes
+        discard_file(path_str, "file.txt", false).expect("Should discard changes");
+
+        // File should now match staged version
+        let content = std::fs::read_to_string(path.join("file.txt")).expect("Should read file");
+        assert_eq!(content, "staged version");
+
+        // Should still have staged changes
+        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
+        assert!(changes_after
+            .iter()
+            .any(|c| c.path == "file.txt" && c.staged_status.is_some()));
+        // Should not have unstaged changes
+        assert!(changes_after
+            .iter()
+            .all(|c| c.path != "file.txt" || c.unstaged_status.is_none()));
+    }
+
+    #[test]
+    fn test_discard_file_error_when_no_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path().to_str().unwrap();
+
+        // File exists but has no changes
+        let result = discard_file(path, "file.txt", false);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("no changes to discard"));
+    }
+
+    // Tests for create_commit
+
+    #[test]
+    fn test_create_commit_commits_staged_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("file.txt"), "committed").expect("Failed to write file");
+        std::fs::write(path.join("other.txt"), "not staged").expect("Failed to write file");
+
+        let path_str = path.to_str().unwrap();
+        stage_file(path_str, "file.txt").expect("Should stage file");
+
+        let commit = create_commit(path_str, "Update file\n\nWith a body", false)
+            .expect("Should create commit")
+            .commit
+            .expect("Commit should not be rejected");
+
+        assert_eq!(commit.message, "Update file");
+        assert_eq!(commit.author, "Test User");
+        assert_eq!(commit.email, "test@example.com");
+
+        let commits = list_commits(path_str, None).expect("Should return commits");
+        assert_eq!(commits.len(), 3);
+        assert_eq!(commits[0].id, commit.id);
+
+        let files = get_commit_files(path_str, &commit.id).expect("Should return changed files");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "file.txt");
+
+        // The unstaged file is left alone
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "other.txt");
+    }
+
+    #[test]
+    fn test_create_commit_refuses_when_nothing_staged() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+
+        std::fs::write(path.join("file.txt"), "unstaged").expect("Failed to write file");
+
+        let result = create_commit(path.to_str().unwrap(), "Nothing here", false);
 
-    let status = file_status
-        .map(|e| e.status())
-        .ok_or_else(|| format!("File '{}' has no changes to discard", file_path))?;
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Nothing to commit"));
+    }
 
-    let workdir = repo
-        .workdir()
-        .ok_or_else(|| "Repository has no working directory".to_string())?;
-    let full_path = workdir.join(file_path);
+    #[test]
+    fn test_create_commit_refuses_empty_message() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
 
-    // Handle untracked files - just delete them
-    if status.is_wt_new() && !status.is_index_new() {
-        if full_path.exists() {
-            std::fs::remove_file(&full_path)
-                .map_err(|e| format!("Failed to delete file: {}", e))?;
-        }
-        return Ok(());
+        std::fs::write(path.join("file.txt"), "staged").expect("Failed to write file");
+        let path_str = path.to_str().unwrap();
+        stage_file(path_str, "file.txt").expect("Should stage file");
+
+        let result = create_commit(path_str, "  \n# only a comment\n", false);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("message cannot be empty"));
     }
 
-    // For tracked files, checkout from index (which reflects staged state or HEAD)
-    let mut checkout_opts = CheckoutBuilder::new();
-    checkout_opts.force();
-    checkout_opts.path(file_path);
+    #[test]
+    fn test_create_commit_amend_replaces_last_commit() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
 
-    repo.checkout_index(None, Some(&mut checkout_opts))
-        .map_err(|e| format!("Failed to discard changes: {}", e))?;
+        let before = list_commits(path_str, None).expect("Should return commits");
 
-    Ok(())
-}
+        std::fs::write(path.join("amended.txt"), "amended").expect("Failed to write file");
+        stage_file(path_str, "amended.txt").expect("Should stage file");
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::process::Command;
-    use tempfile::TempDir;
+        let commit = create_commit(path_str, "Add file and more", true)
+            .expect("Should amend")
+            .commit
+            .expect("Commit should not be rejected");
 
-    fn create_test_repo() -> TempDir {
+        let after = list_commits(path_str, None).expect("Should return commits");
+        assert_eq!(after.len(), 2);
+        assert_ne!(after[0].id, before[0].id);
+        assert_eq!(after[0].id, commit.id);
+        assert_eq!(after[0].message, "Add file and more");
+        assert_eq!(after[1].id, before[1].id);
+
+        let files = get_commit_files(path_str, &commit.id).expect("Should return changed files");
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn test_create_commit_initial_commit_in_empty_repository() {
         let temp_dir = TempDir::new().expect("Failed to create temp directory");
         let path = temp_dir.path();
 
-        // Initialize git repo
         Command::new("git")
             .args(["init"])
             .current_dir(path)
             .output()
             .expect("Failed to init git repo");
-
-        // Configure git user for the test repo
         Command::new("git")
             .args(["config", "user.email", "test@example.com"])
             .current_dir(path)
             .output()
             .expect("Failed to set git email");
-
         Command::new("git")
             .args(["config", "user.name", "Test User"])
             .current_dir(path)
             .output()
             .expect("Failed to set git name");
 
-        // Create initial commit
-        std::fs::write(path.join("README.md"), "# Test").expect("Failed to write file");
+        let path_str = path.to_str().unwrap();
+        assert!(create_commit(path_str, "Empty", false).is_err());
+
+        std::fs::write(path.join("first.txt"), "first").expect("Failed to write file");
+        stage_file(path_str, "first.txt").expect("Should stage file");
+
+        create_commit(path_str, "Initial commit", false).expect("Should create commit");
+
+        let commits = list_commits(path_str, None).expect("Should return commits");
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].message, "Initial commit");
+        assert!(get_current_branch(path_str).is_ok());
+    }
+
+    #[test]
+    fn test_get_last_commit_message_returns_full_message() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+        create_commit(path_str, "Subject\n\nBody line", false).expect("Should create commit");
+
+        let message = get_last_commit_message(path_str).expect("Should get message");
+
+        assert_eq!(message, "Subject\n\nBody line\n");
+    }
+
+    // Tests for commit hooks
+
+    #[cfg(unix)]
+    fn install_hook(dir: &std::path::Path, name: &str, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::create_dir_all(dir).expect("Failed to create hooks dir");
+        let hook_path = dir.join(name);
+        std::fs::write(&hook_path, script).expect("Failed to write hook");
+        std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))
+            .expect("Failed to make hook executable");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_commit_rejected_by_pre_commit_hook() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        install_hook(
+            &path.join(".git/hooks"),
+            "pre-commit",
+            "#!/bin/sh\necho 'checking'\necho 'formatting failed' >&2\nexit 3\n",
+        );
+
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+
+        let result = create_commit(path_str, "Blocked", false).expect("Should run hooks");
+
+        assert!(result.commit.is_none());
+        assert_eq!(result.hooks.len(), 1);
+        assert_eq!(result.hooks[0].name, "pre-commit");
+        assert!(!result.hooks[0].success);
+        assert_eq!(result.hooks[0].exit_code, Some(3));
+        assert_eq!(result.hooks[0].stdout, "checking\n");
+        assert_eq!(result.hooks[0].stderr, "formatting failed\n");
+
+        let commits = list_commits(path_str, None).expect("Should return commits");
+        assert_eq!(commits.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_commit_uses_message_from_commit_msg_hook() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        install_hook(
+            &path.join(".git/hooks"),
+            "commit-msg",
+            "#!/bin/sh\nprintf '\\nReviewed-by: Hook\\n' >> \"$1\"\n",
+        );
+
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+
+        let result = create_commit(path_str, "With trailer", false).expect("Should commit");
+
+        assert!(result.commit.is_some());
+        assert_eq!(result.hooks.len(), 1);
+        assert!(result.hooks[0].success);
+
+        let message = get_last_commit_message(path_str).expect("Should get message");
+        assert_eq!(message, "With trailer\n\nReviewed-by: Hook\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_commit_runs_hooks_from_core_hooks_path() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
         Command::new("git")
-            .args(["add", "."])
+            .args(["config", "core.hooksPath", "custom-hooks"])
             .current_dir(path)
             .output()
-            .expect("Failed to add files");
-        Command::new("git")
-            .args(["commit", "-m", "Initial commit"])
-            .current_dir(path)
+            .expect("Failed to set hooks path");
+
+        let hooks_dir = path.join("custom-hooks");
+        install_hook(&hooks_dir, "pre-commit", "#!/bin/sh\nexit 0\n");
+        install_hook(&hooks_dir, "prepare-commit-msg", "#!/bin/sh\necho \"$2\"\n");
+        install_hook(
+            &hooks_dir,
+            "post-commit",
+            "#!/bin/sh\ngit log -1 --format=%s\n",
+        );
+        // Hooks in the default location are ignored when core.hooksPath is set
+        install_hook(
+            &path.join(".git/hooks"),
+            "pre-commit",
+            "#!/bin/sh\nexit 1\n",
+        );
+
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+
+        let result = create_commit(path_str, "Hooked", false).expect("Should commit");
+
+        assert!(result.commit.is_some());
+        let names: Vec<&str> = result.hooks.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["pre-commit", "prepare-commit-msg", "post-commit"]
+        );
+        assert_eq!(result.hooks[1].stdout, "message\n");
+        assert_eq!(result.hooks[2].stdout, "Hooked\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_commit_amend_passes_commit_source_to_prepare_commit_msg() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let head = git_in(path, &["rev-parse", "HEAD"]);
+        install_hook(
+            &path.join(".git/hooks"),
+            "prepare-commit-msg",
+            "#!/bin/sh\necho \"$2 $3\"\n",
+        );
+
+        let result = create_commit(path_str, "Amended", true).expect("Should amend");
+
+        assert!(result.commit.is_some());
+        assert_eq!(result.hooks[0].stdout, format!("commit {}\n", head));
+    }
+
+    // Tests for commit signing
+
+    #[test]
+    fn test_write_private_file_is_exclusive_and_private() {
+        let temp_dir = private_temp_dir("recap-test").expect("Should create temp dir");
+        let path = temp_dir.path().join("payload");
+
+        write_private_file(&path, b"data").expect("Should write file");
+        assert!(write_private_file(&path, b"other").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &std::path::Path| std::fs::metadata(p).unwrap().permissions().mode();
+            assert_eq!(mode(&path) & 0o777, 0o600);
+            assert_eq!(mode(temp_dir.path()) & 0o777, 0o700);
+        }
+    }
+
+    /// Generates an SSH key and configures the repo to sign commits with it.
+    /// Returns the directory holding the key pair (key and key.pub).
+    fn configure_ssh_signing(repo_path: &std::path::Path) -> TempDir {
+        let key_dir = TempDir::new().expect("Failed to create temp directory");
+        let key_path = key_dir.path().join("key");
+
+        Command::new("ssh-keygen")
+            .args(["-t", "ed25519", "-N", "", "-q", "-f"])
+            .arg(&key_path)
             .output()
-            .expect("Failed to create commit");
+            .expect("Failed to generate SSH key");
+
+        for (key, value) in [
+            ("gpg.format", "ssh"),
+            ("user.signingkey", key_path.to_str().unwrap()),
+            ("commit.gpgsign", "true"),
+        ] {
+            Command::new("git")
+                .args(["config", key, value])
+                .current_dir(repo_path)
+                .output()
+                .expect("Failed to set git config");
+        }
 
-        // Create second commit
-        std::fs::write(path.join("file.txt"), "content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(path)
+        key_dir
+    }
+
+    /// Generates an OpenPGP key in a private keyring and configures the repo to
+    /// sign commits with it through a gpg wrapper using that keyring.
+    /// Returns the directory holding the keyring.
+    #[cfg(unix)]
+    fn configure_gpg_signing(repo_path: &std::path::Path) -> TempDir {
+        use std::os::unix::fs::PermissionsExt;
+
+        let gpg_dir = TempDir::new().expect("Failed to create temp directory");
+        let home = gpg_dir.path().join("home");
+        std::fs::create_dir(&home).expect("Failed to create gpg home");
+        std::fs::set_permissions(&home, std::fs::Permissions::from_mode(0o700))
+            .expect("Failed to set gpg home permissions");
+
+        Command::new("gpg")
+            .arg("--homedir")
+            .arg(&home)
+            .args(["--batch", "--pinentry-mode", "loopback", "--passphrase", ""])
+            .args([
+                "--quick-gen-key",
+                "Test User <test@example.com>",
+                "ed25519",
+                "sign",
+            ])
             .output()
-            .expect("Failed to add files");
-        Command::new("git")
-            .args(["commit", "-m", "Add file"])
+            .expect("Failed to generate gpg key");
+
+        let wrapper = gpg_dir.path().join("gpg-wrapper");
+        std::fs::write(
+            &wrapper,
+            format!(
+                "#!/bin/sh\nexec gpg --homedir '{}' \"$@\"\n",
+                home.display()
+            ),
+        )
+        .expect("Failed to write gpg wrapper");
+        std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755))
+            .expect("Failed to make gpg wrapper executable");
+
+        for (key, value) in [
+            ("gpg.program", wrapper.to_str().unwrap()),
+            ("commit.gpgsign", "true"),
+        ] {
+            Command::new("git")
+                .args(["config", key, value])
+                .current_dir(repo_path)
+                .output()
+                .expect("Failed to set git config");
+        }
+
+        gpg_dir
+    }
+
+    fn head_commit_object(repo_path: &std::path::Path) -> String {
+        let output = Command::new("git")
+            .args(["cat-file", "-p", "HEAD"])
+            .current_dir(repo_path)
+            .output()
+            .expect("Failed to read commit");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_create_commit_signs_with_ssh_key() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let key_dir = configure_ssh_signing(path);
+
+        std::fs::write(path.join("file.txt"), "signed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+
+        let result = create_commit(path_str, "Signed commit", false).expect("Should commit");
+        assert!(result.commit.is_some());
+
+        let commit_object = head_commit_object(path);
+        assert!(commit_object.contains("gpgsig -----BEGIN SSH SIGNATURE-----"));
+
+        // git itself accepts the signature
+        let public_key = std::fs::read_to_string(key_dir.path().join("key.pub")).unwrap();
+        let allowed_signers = key_dir.path().join("allowed_signers");
+        std::fs::write(&allowed_signers, format!("test@example.com {}", public_key))
+            .expect("Failed to write allowed signers");
+        let verify = Command::new("git")
+            .args([
+                "-c",
+                &format!("gpg.ssh.allowedSignersFile={}", allowed_signers.display()),
+            ])
+            .args(["verify-commit", "HEAD"])
             .current_dir(path)
             .output()
-            .expect("Failed to create commit");
+            .expect("Failed to verify commit");
+        assert!(verify.status.success());
+    }
 
-        temp_dir
+    #[cfg(unix)]
+    #[test]
+    fn test_create_commit_signs_with_gpg_key() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let _gpg_dir = configure_gpg_signing(path);
+
+        std::fs::write(path.join("file.txt"), "signed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+
+        let result = create_commit(path_str, "Signed commit", true).expect("Should amend");
+        assert!(result.commit.is_some());
+
+        let commit_object = head_commit_object(path);
+        assert!(commit_object.contains("gpgsig -----BEGIN PGP SIGNATURE-----"));
     }
 
     #[test]
-    fn test_list_commits_returns_commits() {
+    fn test_create_commit_fails_when_signing_fails() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
 
-        let commits = list_commits(path, None).expect("Should return commits");
+        for (key, value) in [
+            ("gpg.format", "ssh"),
+            ("user.signingkey", "/nonexistent/key"),
+            ("commit.gpgsign", "true"),
+        ] {
+            Command::new("git")
+                .args(["config", key, value])
+                .current_dir(path)
+                .output()
+                .expect("Failed to set git config");
+        }
+
+        std::fs::write(path.join("file.txt"), "signed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
 
+        let result = create_commit(path_str, "Unsigned", false);
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("Failed to sign commit"));
+        let commits = list_commits(path_str, None).expect("Should return commits");
         assert_eq!(commits.len(), 2);
-        assert_eq!(commits[0].message, "Add file");
-        assert_eq!(commits[1].message, "Initial commit");
+    }
+
+    // Tests for signature verification
+
+    fn write_allowed_signers(repo_path: &std::path::Path, key_dir: &TempDir) {
+        let public_key = std::fs::read_to_string(key_dir.path().join("key.pub")).unwrap();
+        let allowed_signers = key_dir.path().join("allowed_signers");
+        std::fs::write(&allowed_signers, format!("test@example.com {}", public_key))
+            .expect("Failed to write allowed signers");
+
+        Command::new("git")
+            .args(["config", "gpg.ssh.allowedSignersFile"])
+            .arg(&allowed_signers)
+            .current_dir(repo_path)
+            .output()
+            .expect("Failed to set git config");
     }
 
     #[test]
-    fn test_list_commits_respects_limit() {
+    fn test_list_commits_reports_unsigned_commits() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path_str = temp_dir.path().to_str().unwrap();
 
-        let commits = list_commits(path, Some(1)).expect("Should return commits");
+        let commits = list_commits(path_str, None).expect("Should return commits");
 
-        assert_eq!(commits.len(), 1);
-        assert_eq!(commits[0].message, "Add file");
+        assert!(commits
+            .iter()
+            .all(|c| c.signature_status == SignatureStatus::Unsigned && c.signer.is_none()));
     }
 
     #[test]
-    fn test_list_commits_includes_author_info() {
+    fn test_verify_commit_signatures_good_ssh_signature() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let key_dir = configure_ssh_signing(path);
+        write_allowed_signers(path, &key_dir);
 
-        let commits = list_commits(path, None).expect("Should return commits");
+        std::fs::write(path.join("file.txt"), "signed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+        let result = create_commit(path_str, "Signed commit", false).expect("Should commit");
 
-        assert_eq!(commits[0].author, "Test User");
-        assert_eq!(commits[0].email, "test@example.com");
+        let commit = result.commit.expect("Commit should not be rejected");
+        assert_eq!(commit.signature_status, SignatureStatus::Good);
+        assert_eq!(commit.signer.as_deref(), Some("test@example.com"));
+
+        // Listing only reports that the commit is signed, without verifying it
+        let commits = list_commits(path_str, None).expect("Should return commits");
+        assert_eq!(commits[0].signature_status, SignatureStatus::Signed);
+        assert!(commits[0].signer.is_none());
+        assert_eq!(commits[1].signature_status, SignatureStatus::Unsigned);
+
+        let ids = vec![commits[0].id.clone(), commits[1].id.clone()];
+        let verified = verify_commit_signatures(path_str, &ids).expect("Should verify");
+        assert_eq!(verified[0].commit_id, commits[0].id);
+        assert_eq!(verified[0].status, SignatureStatus::Good);
+        assert_eq!(verified[0].signer.as_deref(), Some("test@example.com"));
+        assert_eq!(verified[1].status, SignatureStatus::Unsigned);
     }
 
     #[test]
-    fn test_list_commits_invalid_path() {
-        let result = list_commits("/nonexistent/path", None);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Failed to open repository"));
+    fn test_verify_commit_signatures_unknown_key_without_allowed_signers() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let _key_dir = configure_ssh_signing(path);
+
+        std::fs::write(path.join("file.txt"), "signed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+        create_commit(path_str, "Signed commit", false).expect("Should commit");
+        let head = git_in(path, &["rev-parse", "HEAD"]);
+
+        let verified = verify_commit_signatures(path_str, &[head]).expect("Should verify");
+
+        assert_eq!(verified[0].status, SignatureStatus::UnknownKey);
+        assert!(verified[0]
+            .signer
+            .as_deref()
+            .is_some_and(|s| s.starts_with("SHA256:")));
     }
 
     #[test]
-    fn test_list_commits_not_a_repo() {
-        let temp_dir = TempDir::new().expect("Failed to create temp directory");
-        let path = temp_dir.path().to_str().unwrap();
+    fn test_verify_commit_signatures_bad_signature() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let key_dir = configure_ssh_signing(path);
+        write_allowed_signers(path, &key_dir);
+
+        // Sign one commit buffer but attach the signature to a different one
+        let repo = Repository::open(path).unwrap();
+        let config = repo.config().unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let signature = repo.signature().unwrap();
+        let tree = head.tree().unwrap();
+        let signed_buffer = repo
+            .commit_create_buffer(&signature, &signature, "Original", &tree, &[&head])
+            .unwrap();
+        let tampered_buffer = repo
+            .commit_create_buffer(&signature, &signature, "Tampered", &tree, &[&head])
+            .unwrap();
+        let commit_signature =
+            sign_commit_buffer(&config, &signature, signed_buffer.as_str().unwrap())
+                .expect("Should sign buffer");
+        let oid = repo
+            .commit_signed(tampered_buffer.as_str().unwrap(), &commit_signature, None)
+            .unwrap();
+        update_head(&repo, oid, "commit: Tampered").unwrap();
+
+        let verified =
+            verify_commit_signatures(path_str, &[oid.to_string()]).expect("Should verify");
+
+        assert_eq!(verified[0].status, SignatureStatus::Bad);
+    }
 
-        let result = list_commits(path, None);
-        assert!(result.is_err());
+    #[cfg(unix)]
+    #[test]
+    fn test_verify_commit_signatures_good_gpg_signature() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let _gpg_dir = configure_gpg_signing(path);
+
+        std::fs::write(path.join("file.txt"), "signed").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+        create_commit(path_str, "Signed commit", false).expect("Should commit");
+        let head = git_in(path, &["rev-parse", "HEAD"]);
+
+        let verified = verify_commit_signatures(path_str, &[head]).expect("Should verify");
+
+        assert_eq!(verified[0].status, SignatureStatus::Good);
+        assert!(verified[0]
+            .signer
+            .as_deref()
+            .is_some_and(|s| s.contains("Test User <test@example.com>")));
     }
 
     #[test]
-    fn test_commit_has_valid_sha() {
+    fn test_parse_gpg_status_expired() {
+        let expired_key = "[GNUPG:] NEWSIG\n[GNUPG:] KEYEXPIRED 1700000000\n\
+            [GNUPG:] EXPKEYSIG 0123456789ABCDEF Test User <test@example.com>\n";
+        let expired_signature = "[GNUPG:] EXPSIG 0123456789ABCDEF Test User <test@example.com>\n";
+        let revoked = "[GNUPG:] REVKEYSIG 0123456789ABCDEF Test User <test@example.com>\n";
+
+        assert_eq!(
+            parse_gpg_status(expired_key),
+            (
+                SignatureStatus::Expired,
+                Some("Test User <test@example.com>".to_string())
+            )
+        );
+        assert_eq!(
+            parse_gpg_status(expired_signature).0,
+            SignatureStatus::Expired
+        );
+        assert_eq!(parse_gpg_status(revoked).0, SignatureStatus::Bad);
+        assert_eq!(parse_gpg_status("").0, SignatureStatus::UnknownKey);
+    }
+
+    // Tests for stashes
+
+    #[test]
+    fn test_list_stashes_empty() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path_str = temp_dir.path().to_str().unwrap();
+
+        let stashes = list_stashes(path_str).expect("Should list stashes");
+
+        assert!(stashes.is_empty());
+    }
+
+    #[test]
+    fn test_create_stash_saves_and_cleans_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(path.join("file.txt"), "stashed").expect("Failed to write file");
+
+        let stash = create_stash(path_str, Some("My stash"), false, false).expect("Should stash");
+
+        assert_eq!(stash.index, 0);
+        assert!(stash.message.starts_with("On "));
+        assert!(stash.message.ends_with(": My stash"));
+        assert!(stash.timestamp > 0);
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "content"
+        );
+        assert_eq!(list_stashes(path_str).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_create_stash_default_message() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(path.join("file.txt"), "stashed").expect("Failed to write file");
 
-        let commits = list_commits(path, None).expect("Should return commits");
+        let stash = create_stash(path_str, None, false, false).expect("Should stash");
 
-        // SHA should be 40 hex characters
-        assert_eq!(commits[0].id.len(), 40);
-        assert!(commits[0].id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(stash.message.starts_with("WIP on "));
+        assert!(stash.message.ends_with(" Add file"));
     }
 
     #[test]
-    fn test_commit_has_timestamp() {
+    fn test_create_stash_without_changes_fails() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path_str = temp_dir.path().to_str().unwrap();
 
-        let commits = list_commits(path, None).expect("Should return commits");
+        let result = create_stash(path_str, None, false, false);
 
-        // Timestamp should be a reasonable Unix timestamp (after 2020)
-        assert!(commits[0].timestamp > 1577836800); // 2020-01-01
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("No local changes"));
     }
 
-    // Tests for get_commit_files
-
     #[test]
-    fn test_get_commit_files_returns_added_file() {
+    fn test_create_stash_leaves_untracked_files_by_default() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
 
-        let commits = list_commits(path, None).expect("Should return commits");
-        let latest_commit = &commits[0]; // "Add file" commit
+        std::fs::write(path.join("file.txt"), "stashed").expect("Failed to write file");
+        std::fs::write(path.join("new.txt"), "new").expect("Failed to write file");
 
-        let files =
-            get_commit_files(path, &latest_commit.id).expect("Should return changed files");
+        create_stash(path_str, None, false, false).expect("Should stash");
 
+        assert!(path.join("new.txt").exists());
+        let files = get_stash_files(path_str, 0).expect("Should get stash files");
         assert_eq!(files.len(), 1);
         assert_eq!(files[0].path, "file.txt");
-        assert_eq!(files[0].status, FileStatus::Added);
-        assert!(files[0].additions > 0);
     }
 
     #[test]
-    fn test_get_commit_files_initial_commit() {
+    fn test_create_stash_including_untracked_files() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
 
-        let commits = list_commits(path, None).expect("Should return commits");
-        let initial_commit = &commits[1]; // "Initial commit"
+        std::fs::write(path.join("new.txt"), "new").expect("Failed to write file");
 
-        let files =
-            get_commit_files(path, &initial_commit.id).expect("Should return changed files");
+        create_stash(path_str, None, true, false).expect("Should stash");
 
+        assert!(!path.join("new.txt").exists());
+        let files = get_stash_files(path_str, 0).expect("Should get stash files");
         assert_eq!(files.len(), 1);
-        assert_eq!(files[0].path, "README.md");
+        assert_eq!(files[0].path, "new.txt");
         assert_eq!(files[0].status, FileStatus::Added);
+
+        let diff = get_stash_file_diff(path_str, 0, "new.txt").expect("Should get diff");
+        assert_eq!(diff.hunks.len(), 1);
+        assert_eq!(diff.hunks[0].lines[0].content, "new");
     }
 
     #[test]
-    fn test_get_commit_files_modified_file() {
+    fn test_create_stash_staged_only() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
 
-        // Modify existing file
-        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(path)
-            .output()
-            .expect("Failed to add files");
-        Command::new("git")
-            .args(["commit", "-m", "Modify file"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create commit");
+        std::fs::write(path.join("file.txt"), "staged").expect("Failed to write file");
+        stage_file(path_str, "file.txt").expect("Should stage file");
+        std::fs::write(path.join("README.md"), "# Unstaged").expect("Failed to write file");
 
-        let path_str = path.to_str().unwrap();
-        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
-        let modify_commit = &commits[0];
+        create_stash(path_str, None, false, true).expect("Should stash");
 
-        let files =
-            get_commit_files(path_str, &modify_commit.id).expect("Should return changed files");
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "content"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path.join("README.md")).unwrap(),
+            "# Unstaged"
+        );
+        let changes = get_working_changes_ex(path_str).expect("Should get changes");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "README.md");
 
+        let files = get_stash_files(path_str, 0).expect("Should get stash files");
         assert_eq!(files.len(), 1);
         assert_eq!(files[0].path, "file.txt");
-        assert_eq!(files[0].status, FileStatus::Modified);
+
+        // git understands the stash it created
+        let show = Command::new("git")
+            .args(["stash", "show", "--name-only", "stash@{0}"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to show stash");
+        assert_eq!(String::from_utf8_lossy(&show.stdout).trim(), "file.txt");
     }
 
     #[test]
-    fn test_get_commit_files_deleted_file() {
+    fn test_create_stash_staged_only_without_staged_changes_fails() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
-
-        // Delete file
-        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete file");
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(path)
-            .output()
-            .expect("Failed to add files");
-        Command::new("git")
-            .args(["commit", "-m", "Delete file"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create commit");
-
         let path_str = path.to_str().unwrap();
-        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
-        let delete_commit = &commits[0];
-
-        let files =
-            get_commit_files(path_str, &delete_commit.id).expect("Should return changed files");
 
-        assert_eq!(files.len(), 1);
-        assert_eq!(files[0].path, "file.txt");
-        assert_eq!(files[0].status, FileStatus::Deleted);
-    }
+        std::fs::write(path.join("file.txt"), "unstaged").expect("Failed to write file");
 
-    #[test]
-    fn test_get_commit_files_invalid_commit() {
-        let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let result = create_stash(path_str, None, false, true);
 
-        let result = get_commit_files(path, "0000000000000000000000000000000000000000");
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Failed to find commit"));
+        assert!(result.unwrap_err().contains("No staged changes"));
+        assert!(list_stashes(path_str).unwrap().is_empty());
     }
 
     #[test]
-    fn test_get_commit_files_invalid_commit_id_format() {
+    fn test_apply_stash_keeps_stash() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
 
-        let result = get_commit_files(path, "not-a-valid-sha");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Invalid commit ID"));
+        std::fs::write(path.join("file.txt"), "stashed").expect("Failed to write file");
+        create_stash(path_str, None, false, false).expect("Should stash");
+
+        let result = apply_stash(path_str, 0).expect("Should apply stash");
+
+        assert!(result.conflicts.is_empty());
+        assert!(!result.dropped);
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "stashed"
+        );
+        assert_eq!(list_stashes(path_str).unwrap().len(), 1);
     }
 
     #[test]
-    fn test_get_commit_range_files_for_consecutive_commits() {
+    fn test_pop_stash_restores_and_drops() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
 
-        let commits = list_commits(path, None).expect("Should return commits");
-        let commit_ids = vec![commits[1].id.clone(), commits[0].id.clone()];
+        std::fs::write(path.join("file.txt"), "first").expect("Failed to write file");
+        create_stash(path_str, Some("first"), false, false).expect("Should stash");
+        std::fs::write(path.join("file.txt"), "second").expect("Failed to write file");
+        create_stash(path_str, Some("second"), false, false).expect("Should stash");
 
-        let files = get_commit_range_files(path, &commit_ids).expect("Should return changed files");
+        let result = pop_stash(path_str, 1).expect("Should pop stash");
 
-        assert!(!files.is_empty());
-        assert!(files.iter().any(|file| file.path == "README.md"));
-        assert!(files.iter().any(|file| file.path == "file.txt"));
+        assert!(result.conflicts.is_empty());
+        assert!(result.dropped);
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "first"
+        );
+        let stashes = list_stashes(path_str).unwrap();
+        assert_eq!(stashes.len(), 1);
+        assert!(stashes[0].message.ends_with(": second"));
     }
 
     #[test]
-    fn test_get_commit_range_files_rejects_non_consecutive_selection() {
+    fn test_pop_stash_with_conflicts_keeps_stash() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
 
-        std::fs::write(path.join("extra.txt"), "extra content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(path)
-            .output()
-            .expect("Failed to add files");
+        std::fs::write(path.join("file.txt"), "stashed").expect("Failed to write file");
+        create_stash(path_str, None, false, false).expect("Should stash");
+
+        std::fs::write(path.join("file.txt"), "committed").expect("Failed to write file");
         Command::new("git")
-            .args(["commit", "-m", "Add extra file"])
+            .args(["commit", "-am", "Change file"])
             .current_dir(path)
             .output()
-            .expect("Failed to create commit");
+            .expect("Failed to commit");
+
+        let result = pop_stash(path_str, 0).expect("Should pop stash with conflicts");
+
+        assert_eq!(result.conflicts, vec!["file.txt".to_string()]);
+        assert!(!result.dropped);
+        assert_eq!(list_stashes(path_str).unwrap().len(), 1);
+        let content = std::fs::read_to_string(path.join("file.txt")).unwrap();
+        assert!(content.contains("<<<<<<<"));
+    }
 
+    #[test]
+    fn test_apply_stash_refuses_to_overwrite_local_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let commits = list_commits(path_str, None).expect("Should return commits");
-        let non_consecutive_ids = vec![commits[0].id.clone(), commits[2].id.clone()];
 
-        let result = get_commit_range_files(path_str, &non_consecutive_ids);
+        std::fs::write(path.join("file.txt"), "stashed").expect("Failed to write file");
+        create_stash(path_str, None, false, false).expect("Should stash");
+        std::fs::write(path.join("file.txt"), "local").expect("Failed to write file");
+
+        let result = apply_stash(path_str, 0);
 
         assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .contains("Unable to display diff for multiple non-consecutive commits"));
+        let error = result.unwrap_err();
+        assert!(error.contains("would be overwritten"));
+        assert!(error.contains("file.txt"));
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "local"
+        );
     }
 
-    // Tests for get_file_diff
+    #[test]
+    fn test_drop_stash() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(path.join("file.txt"), "stashed").expect("Failed to write file");
+        create_stash(path_str, None, false, false).expect("Should stash");
+
+        drop_stash(path_str, 0).expect("Should drop stash");
+
+        assert!(list_stashes(path_str).unwrap().is_empty());
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "content"
+        );
+    }
 
     #[test]
-    fn test_get_file_diff_returns_diff() {
+    fn test_drop_stash_not_found() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path_str = temp_dir.path().to_str().unwrap();
 
-        let commits = list_commits(path, None).expect("Should return commits");
-        let latest_commit = &commits[0]; // "Add file" commit
+        let result = drop_stash(path_str, 0);
 
-        let diff = get_file_diff(path, &latest_commit.id, "file.txt").expect("Should return diff");
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found"));
+    }
+
+    #[test]
+    fn test_get_stash_file_diff() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(path.join("file.txt"), "stashed").expect("Failed to write file");
+        create_stash(path_str, None, false, false).expect("Should stash");
+
+        let diff = get_stash_file_diff(path_str, 0, "file.txt").expect("Should get diff");
 
         assert_eq!(diff.new_path, "file.txt");
         assert!(!diff.is_binary);
-        assert!(!diff.hunks.is_empty());
+        let lines = &diff.hunks[0].lines;
+        assert!(lines
+            .iter()
+            .any(|l| l.line_type == LineType::Deletion && l.content == "content"));
+        assert!(lines
+            .iter()
+            .any(|l| l.line_type == LineType::Addition && l.content == "stashed"));
 
-        // Check that hunks have lines
-        let hunk = &diff.hunks[0];
-        assert!(!hunk.lines.is_empty());
+        let missing = get_stash_file_diff(path_str, 0, "README.md");
+        assert!(missing.is_err());
+    }
 
-        // Added file should have all additions
-        let additions: Vec<_> = hunk
-            .lines
-            .iter()
-            .filter(|l| l.line_type == LineType::Addition)
-            .collect();
-        assert!(!additions.is_empty());
+    // Tests for tags
+
+    #[test]
+    fn test_list_tags_empty() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+
+        let tags = list_tags(path_str).expect("Should list tags");
+
+        assert!(tags.is_empty());
     }
 
     #[test]
-    fn test_get_file_diff_modified_file() {
+    fn test_list_tags_lightweight_and_annotated() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let commits = list_commits(path_str, None).unwrap();
 
-        // Modify existing file
-        std::fs::write(path.join("file.txt"), "line1\nline2\nmodified").expect("Failed to write");
         Command::new("git")
-            .args(["add", "."])
+            .args(["tag", "v0.1", &commits[1].id])
             .current_dir(path)
             .output()
-            .expect("Failed to add files");
+            .expect("Failed to create tag");
         Command::new("git")
-            .args(["commit", "-m", "Modify file"])
+            .args(["tag", "-a", "v0.2", "-m", "Second release"])
             .current_dir(path)
             .output()
-            .expect("Failed to create commit");
+            .expect("Failed to create tag");
+
+        let tags = list_tags(path_str).expect("Should list tags");
+
+        assert_eq!(tags.len(), 2);
+        let lightweight = tags.iter().find(|t| t.name == "v0.1").unwrap();
+        assert_eq!(lightweight.commit_id, commits[1].id);
+        assert!(!lightweight.is_annotated);
+        assert!(lightweight.tagger.is_none());
+        assert!(lightweight.message.is_none());
+        let annotated = tags.iter().find(|t| t.name == "v0.2").unwrap();
+        assert_eq!(annotated.commit_id, commits[0].id);
+        assert!(annotated.is_annotated);
+        assert_eq!(annotated.tagger.as_deref(), Some("Test User"));
+        assert_eq!(annotated.email.as_deref(), Some("test@example.com"));
+        assert_eq!(annotated.message.as_deref(), Some("Second release"));
+    }
 
+    #[test]
+    fn test_create_annotated_tag() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
+        let commits = list_commits(path_str, None).unwrap();
 
-        let diff =
-            get_file_diff(path_str, &commits[0].id, "file.txt").expect("Should return diff");
-
-        assert_eq!(diff.new_path, "file.txt");
-        assert!(!diff.is_binary);
+        let tag = create_tag(path_str, "v1.0", Some(&commits[1].id), Some("Release 1.0"))
+            .expect("Should create tag");
 
-        // Should have both deletions and additions
-        let has_deletion = diff
-            .hunks
-            .iter()
-            .any(|h| h.lines.iter().any(|l| l.line_type == LineType::Deletion));
-        let has_addition = diff
-            .hunks
-            .iter()
-            .any(|h| h.lines.iter().any(|l| l.line_type == LineType::Addition));
+        assert_eq!(tag.name, "v1.0");
+        assert_eq!(tag.commit_id, commits[1].id);
+        assert!(tag.is_annotated);
+        assert_eq!(tag.message.as_deref(), Some("Release 1.0"));
 
-        assert!(has_deletion || has_addition);
+        let object_type = Command::new("git")
+            .args(["cat-file", "-t", "v1.0"])
+            .current_dir(path)
+            .output()
+            .expect("Failed to read tag");
+        assert_eq!(String::from_utf8_lossy(&object_type.stdout).trim(), "tag");
     }
 
     #[test]
-    fn test_get_file_diff_file_not_in_commit() {
+    fn test_create_lightweight_tag_on_head() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path_str = temp_dir.path().to_str().unwrap();
+        let head = list_commits(path_str, Some(1)).unwrap().remove(0);
 
-        let commits = list_commits(path, None).expect("Should return commits");
-        let latest_commit = &commits[0];
+        let tag = create_tag(path_str, "v1.0", None, None).expect("Should create tag");
 
-        let result = get_file_diff(path, &latest_commit.id, "nonexistent.txt");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("not found in commit"));
+        assert_eq!(tag.commit_id, head.id);
+        assert!(!tag.is_annotated);
+        assert_eq!(tag.timestamp, head.timestamp);
     }
 
     #[test]
-    fn test_get_file_diff_line_numbers() {
+    fn test_create_tag_rejects_existing_and_invalid_names() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
-
-        let commits = list_commits(path, None).expect("Should return commits");
-        let latest_commit = &commits[0];
+        let path_str = temp_dir.path().to_str().unwrap();
+        create_tag(path_str, "v1.0", None, None).expect("Should create tag");
 
-        let diff = get_file_diff(path, &latest_commit.id, "file.txt").expect("Should return diff");
+        let existing = create_tag(path_str, "v1.0", None, Some("Again"));
+        assert!(existing.unwrap_err().contains("already exists"));
 
-        // For added file, lines should have new_line_no set
-        for hunk in &diff.hunks {
-            for line in &hunk.lines {
-                if line.line_type == LineType::Addition {
-                    assert!(line.new_line_no.is_some());
-                }
-            }
-        }
+        let invalid = create_tag(path_str, "bad tag", None, None);
+        assert!(invalid.unwrap_err().contains("not a valid tag name"));
     }
 
-    // Tests for get_current_branch
+    #[test]
+    fn test_delete_tag() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+        create_tag(path_str, "v1.0", None, Some("Release")).expect("Should create tag");
+
+        delete_tag(path_str, "v1.0").expect("Should delete tag");
+
+        assert!(list_tags(path_str).unwrap().is_empty());
+        assert!(delete_tag(path_str, "v1.0")
+            .unwrap_err()
+            .contains("not found"));
+    }
 
     #[test]
-    fn test_get_current_branch_returns_branch_name() {
+    fn test_list_commits_from_tag() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path_str = temp_dir.path().to_str().unwrap();
+        let commits = list_commits(path_str, None).unwrap();
+        create_tag(path_str, "v0.1", Some(&commits[1].id), Some("First")).expect("Should tag");
 
-        let branch = get_current_branch(path).expect("Should return branch name");
+        let history = list_commits_from(path_str, "v0.1", None).expect("Should list commits");
 
-        // Default branch could be "master" or "main" depending on git config
-        assert!(branch == "master" || branch == "main");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, commits[1].id);
+        assert!(list_commits_from(path_str, "missing", None).is_err());
     }
 
     #[test]
-    fn test_get_current_branch_new_branch() {
+    fn test_compare_revisions_between_tags() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path();
+        let path_str = temp_dir.path().to_str().unwrap();
+        create_tag(path_str, "v0.1", Some("HEAD~1"), None).expect("Should tag");
+        create_tag(path_str, "v0.2", None, Some("Second")).expect("Should tag");
 
-        // Create and checkout new branch
-        Command::new("git")
-            .args(["checkout", "-b", "feature-branch"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create branch");
+        let files = compare_revisions(path_str, "v0.1", "v0.2").expect("Should compare");
 
-        let path_str = path.to_str().unwrap();
-        let branch = get_current_branch(path_str).expect("Should return branch name");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "file.txt");
+        assert_eq!(files[0].status, FileStatus::Added);
+
+        let diff =
+            get_compare_file_diff(path_str, "v0.1", "v0.2", "file.txt").expect("Should get diff");
+        assert_eq!(diff.hunks[0].lines[0].content, "content");
 
-        assert_eq!(branch, "feature-branch");
+        let unchanged = get_compare_file_diff(path_str, "v0.1", "v0.2", "README.md");
+        assert!(unchanged.is_err());
     }
 
-    #[test]
-    fn test_get_current_branch_detached_head() {
-        let temp_dir = create_test_repo();
-        let path = temp_dir.path();
+    // Tests for remotes
 
-        let path_str = path.to_str().unwrap();
-        let commits = list_commits(path_str, None).expect("Should return commits");
+    /// Creates a bare repository holding the test repo history, to act as "origin"
+    fn create_bare_origin() -> TempDir {
+        let source = create_test_repo();
+        let bare = TempDir::new().expect("Failed to create temp directory");
 
-        // Checkout specific commit (detached HEAD)
         Command::new("git")
-            .args(["checkout", &commits[1].id])
-            .current_dir(path)
+            .args(["clone", "--bare"])
+            .arg(source.path())
+            .arg(bare.path())
             .output()
-            .expect("Failed to checkout commit");
+            .expect("Failed to create bare repo");
 
-        let branch = get_current_branch(path_str).expect("Should return something");
+        bare
+    }
 
-        // Should return the commit SHA when in detached HEAD
-        assert_eq!(branch.len(), 40);
-        assert!(branch.chars().all(|c| c.is_ascii_hexdigit()));
+    fn git_in(path: &std::path::Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(path)
+            .output()
+            .expect("Failed to run git");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
     }
 
-    #[test]
-    fn test_get_current_branch_invalid_path() {
-        let result = get_current_branch("/nonexistent/path");
-        assert!(result.is_err());
+    /// Commits `content` to `file` in a clone and pushes the current branch
+    fn push_change(path: &std::path::Path, file: &str, content: &str) {
+        std::fs::write(path.join(file), content).expect("Failed to write file");
+        git_in(path, &["add", file]);
+        git_in(path, &["commit", "-m", content]);
+        git_in(path, &["push", "origin", "HEAD"]);
     }
 
-    // Tests for validate_repo
+    fn no_progress(_: RemoteProgress) {}
 
     #[test]
-    fn test_validate_repo_returns_info() {
-        let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+    fn test_list_remotes() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
 
-        let info = validate_repo(path).expect("Should return repo info");
+        let remotes = list_remotes(clone.path().to_str().unwrap()).expect("Should list remotes");
 
-        assert!(info.path.len() > 0);
-        assert!(info.name.len() > 0);
-        assert!(info.branch == "master" || info.branch == "main");
+        assert_eq!(remotes.len(), 1);
+        assert_eq!(remotes[0].name, "origin");
+        assert!(remotes[0].url.is_some());
+        assert!(remotes[0].push_url.is_none());
     }
 
     #[test]
-    fn test_validate_repo_invalid_path() {
-        let result = validate_repo("/nonexistent/path");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Not a valid git repository"));
+    fn test_fetch_updates_remote_branches_and_reports_progress() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let other = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        push_change(other.path(), "file.txt", "from other");
+        let pushed = git_in(other.path(), &["rev-parse", "HEAD"]);
+
+        let events = std::cell::RefCell::new(Vec::new());
+        fetch(path_str, Some("origin"), false, &|progress| {
+            events.borrow_mut().push(progress)
+        })
+        .expect("Should fetch");
+
+        let branch = get_current_branch(path_str).unwrap();
+        let fetched = git_in(clone.path(), &["rev-parse", &format!("origin/{}", branch)]);
+        assert_eq!(fetched, pushed);
+        let events = events.into_inner();
+        assert!(!events.is_empty());
+        assert!(events
+            .iter()
+            .all(|e| e.operation == "fetch" && e.remote == "origin"));
     }
 
     #[test]
-    fn test_validate_repo_not_a_repo() {
-        let temp_dir = TempDir::new().expect("Failed to create temp directory");
-        let path = temp_dir.path().to_str().unwrap();
-
-        let result = validate_repo(path);
-        assert!(result.is_err());
-    }
+    fn test_fetch_all_with_prune() {
+        let origin = create_bare_origin();
+        let other = clone_test_repo(origin.path());
+        git_in(other.path(), &["push", "origin", "HEAD:refs/heads/topic"]);
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        assert!(!git_in(clone.path(), &["branch", "-r", "--list", "origin/topic"]).is_empty());
 
-    #[test]
-    fn test_validate_repo_path_is_normalized() {
-        let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        git_in(other.path(), &["push", "origin", "--delete", "topic"]);
 
-        let info = validate_repo(path).expect("Should return repo info");
+        fetch(path_str, None, false, &no_progress).expect("Should fetch");
+        assert!(!git_in(clone.path(), &["branch", "-r", "--list", "origin/topic"]).is_empty());
 
-        // Path should be absolute
-        assert!(info.path.starts_with('/'));
+        fetch(path_str, None, true, &no_progress).expect("Should fetch with prune");
+        assert!(git_in(clone.path(), &["branch", "-r", "--list", "origin/topic"]).is_empty());
     }
 
-    // Tests for get_file_contents
-
     #[test]
-    fn test_get_file_contents_added_file() {
-        let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+    fn test_fetch_unknown_remote() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+
+        let result = fetch(
+            clone.path().to_str().unwrap(),
+            Some("upstream"),
+            false,
+            &no_progress,
+        );
 
-        let commits = list_commits(path, None).expect("Should return commits");
-        let add_commit = &commits[0]; // "Add file" commit
+        assert!(result.unwrap_err().contains("not found"));
+    }
 
-        let contents =
-            get_file_contents(path, &add_commit.id, "file.txt").expect("Should return contents");
+    #[test]
+    fn test_pull_fast_forwards() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let other = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        push_change(other.path(), "file.txt", "from other");
+
+        let result = pull(path_str, PullMode::FastForwardOnly, &no_progress).expect("Should pull");
+
+        assert_eq!(result, PullResult::FastForwarded);
+        assert_eq!(
+            git_in(clone.path(), &["rev-parse", "HEAD"]),
+            git_in(other.path(), &["rev-parse", "HEAD"])
+        );
+        assert_eq!(
+            std::fs::read_to_string(clone.path().join("file.txt")).unwrap(),
+            "from other"
+        );
+        assert!(get_working_changes_ex(path_str).unwrap().is_empty());
 
-        assert!(!contents.is_binary);
-        assert!(contents.old_content.is_none()); // File didn't exist before
-        assert_eq!(contents.new_content, Some("content".to_string()));
+        let again = pull(path_str, PullMode::FastForwardOnly, &no_progress).expect("Should pull");
+        assert_eq!(again, PullResult::UpToDate);
     }
 
     #[test]
-    fn test_get_file_contents_modified_file() {
-        let temp_dir = create_test_repo();
-        let path = temp_dir.path();
-
-        // Modify existing file
-        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(path)
-            .output()
-            .expect("Failed to add files");
-        Command::new("git")
-            .args(["commit", "-m", "Modify file"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create commit");
+    fn test_pull_fast_forward_only_refuses_diverged_branch() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let other = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        push_change(other.path(), "file.txt", "from other");
+        std::fs::write(clone.path().join("local.txt"), "local").expect("Failed to write file");
+        git_in(clone.path(), &["add", "local.txt"]);
+        git_in(clone.path(), &["commit", "-m", "Local"]);
+
+        let result = pull(path_str, PullMode::FastForwardOnly, &no_progress);
+
+        assert!(result.unwrap_err().contains("diverged"));
+    }
 
-        let path_str = path.to_str().unwrap();
-        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
+    #[test]
+    fn test_pull_rebases_local_commits() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let other = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        push_change(other.path(), "file.txt", "from other");
+        std::fs::write(clone.path().join("local.txt"), "local").expect("Failed to write file");
+        git_in(clone.path(), &["add", "local.txt"]);
+        git_in(clone.path(), &["commit", "-m", "Local"]);
+
+        let result = pull(path_str, PullMode::Rebase, &no_progress).expect("Should pull");
+
+        assert_eq!(result, PullResult::Rebased);
+        assert_eq!(
+            git_in(clone.path(), &["rev-parse", "HEAD~1"]),
+            git_in(other.path(), &["rev-parse", "HEAD"])
+        );
+        assert_eq!(git_in(clone.path(), &["log", "-1", "--format=%s"]), "Local");
+        assert_eq!(
+            std::fs::read_to_string(clone.path().join("file.txt")).unwrap(),
+            "from other"
+        );
+        assert!(clone.path().join("local.txt").exists());
+    }
 
-        let contents = get_file_contents(path_str, &commits[0].id, "file.txt")
-            .expect("Should return contents");
+    #[test]
+    fn test_pull_rebase_signs_rebased_commits() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let other = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        let _key_dir = configure_ssh_signing(clone.path());
+        push_change(other.path(), "file.txt", "from other");
+        std::fs::write(clone.path().join("local.txt"), "local").expect("Failed to write file");
+        stage_file(path_str, "local.txt").expect("Should stage file");
+        create_commit(path_str, "Local", false).expect("Should commit");
+
+        let result = pull(path_str, PullMode::Rebase, &no_progress).expect("Should pull");
+
+        assert_eq!(result, PullResult::Rebased);
+        let commits = list_commits(path_str, Some(1)).expect("Should list commits");
+        assert_eq!(commits[0].message, "Local");
+        assert_eq!(commits[0].signature_status, SignatureStatus::Signed);
+        assert_eq!(
+            git_in(clone.path(), &["rev-parse", "HEAD~1"]),
+            git_in(other.path(), &["rev-parse", "HEAD"])
+        );
+    }
 
-        assert!(!contents.is_binary);
-        assert_eq!(contents.old_content, Some("content".to_string()));
-        assert_eq!(contents.new_content, Some("modified content".to_string()));
+    #[test]
+    fn test_pull_rebase_aborts_on_conflicts() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let other = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        push_change(other.path(), "file.txt", "from other");
+        std::fs::write(clone.path().join("file.txt"), "local").expect("Failed to write file");
+        git_in(clone.path(), &["commit", "-am", "Local"]);
+        let local_head = git_in(clone.path(), &["rev-parse", "HEAD"]);
+
+        let result = pull(path_str, PullMode::Rebase, &no_progress);
+
+        let error = result.unwrap_err();
+        assert!(error.contains("conflicts"));
+        assert!(error.contains("file.txt"));
+        assert_eq!(git_in(clone.path(), &["rev-parse", "HEAD"]), local_head);
+        assert_eq!(
+            git_in(clone.path(), &["rev-parse", "--abbrev-ref", "HEAD"]),
+            get_current_branch(path_str).unwrap()
+        );
+        assert!(get_rebase_status(path_str).unwrap().is_none());
+        assert_eq!(
+            std::fs::read_to_string(clone.path().join("file.txt")).unwrap(),
+            "local"
+        );
     }
 
     #[test]
-    fn test_get_file_contents_deleted_file() {
+    fn test_pull_without_upstream_fails() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path();
 
-        // Delete file
-        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete file");
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(path)
-            .output()
-            .expect("Failed to add files");
-        Command::new("git")
-            .args(["commit", "-m", "Delete file"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create commit");
+        let result = pull(
+            temp_dir.path().to_str().unwrap(),
+            PullMode::FastForwardOnly,
+            &no_progress,
+        );
 
-        let path_str = path.to_str().unwrap();
-        let commits = list_commits(path_str, Some(1)).expect("Should return commits");
+        assert!(result.unwrap_err().contains("no upstream"));
+    }
 
-        let contents = get_file_contents(path_str, &commits[0].id, "file.txt")
-            .expect("Should return contents");
+    #[test]
+    fn test_push_current_branch() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        let branch = get_current_branch(path_str).unwrap();
+        std::fs::write(clone.path().join("file.txt"), "pushed").expect("Failed to write file");
+        git_in(clone.path(), &["commit", "-am", "Push me"]);
+
+        push(path_str, None, false, &no_progress).expect("Should push");
+
+        assert_eq!(
+            git_in(origin.path(), &["rev-parse", &branch]),
+            git_in(clone.path(), &["rev-parse", "HEAD"])
+        );
+    }
 
-        assert!(!contents.is_binary);
-        assert_eq!(contents.old_content, Some("content".to_string()));
-        assert!(contents.new_content.is_none()); // File was deleted
+    #[test]
+    fn test_push_new_branch_sets_upstream() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        create_branch(path_str, "topic", None, true).expect("Should create branch");
+        std::fs::write(clone.path().join("file.txt"), "topic").expect("Failed to write file");
+        git_in(clone.path(), &["commit", "-am", "Topic"]);
+
+        push(path_str, Some("origin"), true, &no_progress).expect("Should push");
+
+        assert_eq!(
+            git_in(origin.path(), &["rev-parse", "topic"]),
+            git_in(clone.path(), &["rev-parse", "HEAD"])
+        );
+        let branches = list_branches(path_str).unwrap();
+        let topic = branches.iter().find(|b| b.name == "topic").unwrap();
+        assert_eq!(topic.upstream.as_deref(), Some("origin/topic"));
+        assert_eq!((topic.ahead, topic.behind), (0, 0));
     }
 
     #[test]
-    fn test_get_file_contents_file_not_in_commit() {
-        let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+    fn test_push_rejects_non_fast_forward() {
+        let origin = create_bare_origin();
+        let clone = clone_test_repo(origin.path());
+        let other = clone_test_repo(origin.path());
+        let path_str = clone.path().to_str().unwrap();
+        push_change(other.path(), "file.txt", "from other");
+        std::fs::write(clone.path().join("file.txt"), "local").expect("Failed to write file");
+        git_in(clone.path(), &["commit", "-am", "Local"]);
 
-        let commits = list_commits(path, None).expect("Should return commits");
+        let result = push(path_str, None, false, &no_progress);
 
-        let result = get_file_contents(path, &commits[0].id, "nonexistent.txt");
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("not found in commit"));
+        assert_eq!(
+            git_in(origin.path(), &["rev-parse", "HEAD"]),
+            git_in(other.path(), &["rev-parse", "HEAD"])
+        );
     }
 
-    // Tests for get_working_changes
+    // Tests for revert and cherry-pick
+
+    /// Adds two commits on a "topic" branch: one appending to lines.txt, one adding
+    /// topic.txt. Leaves the original branch checked out and returns the two SHAs.
+    fn create_topic_branch(path: &std::path::Path) -> (String, String) {
+        git_in(path, &["checkout", "-b", "topic"]);
+        std::fs::write(path.join("README.md"), "# Test\n\nTopic notes\n")
+            .expect("Failed to write file");
+        git_in(path, &["commit", "-am", "Document topic"]);
+        let first = git_in(path, &["rev-parse", "HEAD"]);
+        std::fs::write(path.join("topic.txt"), "topic").expect("Failed to write file");
+        git_in(path, &["add", "topic.txt"]);
+        git_in(path, &["commit", "-m", "Add topic"]);
+        let second = git_in(path, &["rev-parse", "HEAD"]);
+        git_in(path, &["checkout", "-"]);
+
+        (first, second)
+    }
 
     #[test]
-    fn test_get_working_changes_no_changes() {
+    fn test_revert_commit_auto_commit() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
-
-        let changes = get_working_changes(path).expect("Should return changes");
-
-        assert!(changes.is_empty());
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let head = list_commits(path_str, Some(1)).unwrap().remove(0);
+
+        let result =
+            revert_commit(path_str, std::slice::from_ref(&head.id), true).expect("Should revert");
+
+        assert_eq!(result.applied, vec![head.id.clone()]);
+        assert_eq!(result.created.len(), 1);
+        assert!(result.conflicts.is_empty());
+        assert!(!path.join("file.txt").exists());
+        let new_head = list_commits(path_str, Some(1)).unwrap().remove(0);
+        assert_eq!(new_head.id, result.created[0]);
+        assert_eq!(new_head.message, "Revert \"Add file\"");
+        assert!(get_working_changes_ex(path_str).unwrap().is_empty());
     }
 
     #[test]
-    fn test_get_working_changes_modified_file() {
+    fn test_revert_commit_without_commit_stages_changes() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
-
-        // Modify file without committing
-        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
-
         let path_str = path.to_str().unwrap();
-        let changes = get_working_changes(path_str).expect("Should return changes");
+        let head = list_commits(path_str, Some(1)).unwrap().remove(0);
+
+        let result =
+            revert_commit(path_str, std::slice::from_ref(&head.id), false).expect("Should revert");
 
+        assert!(result.created.is_empty());
+        assert!(result
+            .message
+            .as_deref()
+            .is_some_and(|m| m.contains(&format!("This reverts commit {}.", head.id))));
+        assert_eq!(list_commits(path_str, Some(1)).unwrap()[0].id, head.id);
+        let changes = get_working_changes_ex(path_str).unwrap();
         assert_eq!(changes.len(), 1);
         assert_eq!(changes[0].path, "file.txt");
-        assert_eq!(changes[0].status, FileStatus::Modified);
+        assert!(changes[0].staged_status.is_some());
     }
 
     #[test]
-    fn test_get_working_changes_untracked_file() {
+    fn test_cherry_pick_range_in_graph_order() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
-
-        // Create new file without adding
-        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write file");
-
         let path_str = path.to_str().unwrap();
-        let changes = get_working_changes(path_str).expect("Should return changes");
-
-        assert_eq!(changes.len(), 1);
-        assert_eq!(changes[0].path, "newfile.txt");
-        assert_eq!(changes[0].status, FileStatus::Untracked);
+        let (first, second) = create_topic_branch(path);
+
+        let result = cherry_pick_commit(path_str, &[second.clone(), first.clone()], true)
+            .expect("Should cherry-pick");
+
+        assert_eq!(result.applied, vec![first, second]);
+        assert_eq!(result.created.len(), 2);
+        let commits = list_commits(path_str, Some(2)).unwrap();
+        assert_eq!(commits[0].message, "Add topic");
+        assert_eq!(commits[1].message, "Document topic");
+        assert!(path.join("topic.txt").exists());
+        assert!(std::fs::read_to_string(path.join("README.md"))
+            .unwrap()
+            .contains("Topic notes"));
     }
 
     #[test]
-    fn test_get_working_changes_staged_file() {
+    fn test_cherry_pick_keeps_unrelated_local_changes() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
-
-        // Create and stage new file
-        std::fs::write(path.join("staged.txt"), "staged content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "staged.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to add file");
-
         let path_str = path.to_str().unwrap();
-        let changes = get_working_changes(path_str).expect("Should return changes");
+        let (_, second) = create_topic_branch(path);
+        std::fs::write(path.join("file.txt"), "local").expect("Failed to write file");
 
-        assert_eq!(changes.len(), 1);
-        assert_eq!(changes[0].path, "staged.txt");
-        assert_eq!(changes[0].status, FileStatus::Added);
+        cherry_pick_commit(path_str, &[second], true).expect("Should cherry-pick");
+
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "local"
+        );
+        assert!(path.join("topic.txt").exists());
     }
 
     #[test]
-    fn test_get_working_changes_deleted_file() {
+    fn test_cherry_pick_reports_conflicts() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let (first, second) = create_topic_branch(path);
+        std::fs::write(path.join("README.md"), "# Test\n\nOther notes\n")
+            .expect("Failed to write file");
+        git_in(path, &["commit", "-am", "Other notes"]);
+        let head = git_in(path, &["rev-parse", "HEAD"]);
+
+        let result = cherry_pick_commit(path_str, &[first.clone(), second.clone()], true)
+            .expect("Should stop on conflicts");
+
+        assert!(result.applied.is_empty());
+        assert_eq!(result.stopped_at, Some(first.clone()));
+        assert_eq!(result.remaining, vec![second]);
+        assert_eq!(result.conflicts.len(), 1);
+        assert_eq!(result.conflicts[0].path, "README.md");
+        assert_eq!(
+            result.conflicts[0].conflict_type,
+            ConflictType::BothModified
+        );
 
-        // Delete tracked file
-        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete file");
+        assert_eq!(git_in(path, &["rev-parse", "HEAD"]), head);
+        let repo = Repository::open(path).unwrap();
+        assert_eq!(repo.state(), git2::RepositoryState::CherryPick);
+        assert!(repo.index().unwrap().has_conflicts());
+        assert!(std::fs::read_to_string(path.join("README.md"))
+            .unwrap()
+            .contains("<<<<<<<"));
+    }
 
+    #[test]
+    fn test_revert_refuses_staged_changes() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let changes = get_working_changes(path_str).expect("Should return changes");
+        let head = list_commits(path_str, Some(1)).unwrap().remove(0);
+        std::fs::write(path.join("README.md"), "# Staged").expect("Failed to write file");
+        stage_file(path_str, "README.md").expect("Should stage file");
 
-        assert_eq!(changes.len(), 1);
-        assert_eq!(changes[0].path, "file.txt");
-        assert_eq!(changes[0].status, FileStatus::Deleted);
+        let result = revert_commit(path_str, &[head.id], true);
+
+        assert!(result.unwrap_err().contains("staged changes"));
     }
 
+    // Tests for reset_to_commit and undo_reset
+
     #[test]
-    fn test_get_working_changes_multiple_files() {
+    fn test_reset_to_commit_hard_backs_up_and_undoes() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let original_head = git_in(path, &["rev-parse", "HEAD"]);
+        std::fs::write(path.join("README.md"), "# Edited").expect("Failed to write file");
+        std::fs::write(path.join("staged.txt"), "staged").expect("Failed to write file");
+        stage_file(path_str, "staged.txt").expect("Should stage file");
+        std::fs::write(path.join("notes.txt"), "notes").expect("Failed to write file");
+
+        let result = reset_to_commit(path_str, "HEAD~1", ResetMode::Hard).expect("Should reset");
+
+        assert_eq!(result.head, git_in(path, &["rev-parse", "HEAD"]));
+        assert_ne!(result.head, original_head);
+        assert!(!path.join("file.txt").exists());
+        assert!(!path.join("staged.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(path.join("README.md")).unwrap(),
+            "# Test"
+        );
+        let backup = format!("refs/recap/backups/{}", result.undo_token);
+        assert_eq!(
+            git_in(path, &["rev-parse", &format!("{}/head", backup)]),
+            original_head
+        );
+        assert_eq!(
+            git_in(path, &["show", &format!("{}/changes:notes.txt", backup)]),
+            "notes"
+        );
 
-        // Create multiple changes
-        std::fs::write(path.join("file.txt"), "modified").expect("Failed to write");
-        std::fs::write(path.join("new.txt"), "new").expect("Failed to write");
+        let undo = undo_reset(path_str, &result.undo_token).expect("Should undo reset");
+
+        assert_eq!(undo.head, original_head);
+        assert_eq!(git_in(path, &["rev-parse", "HEAD"]), original_head);
+        assert_eq!(
+            std::fs::read_to_string(path.join("README.md")).unwrap(),
+            "# Edited"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path.join("notes.txt")).unwrap(),
+            "notes"
+        );
+        assert!(path.join("file.txt").exists());
+        let staged = git_in(path, &["diff", "--cached", "--name-only"]);
+        assert_eq!(staged, "staged.txt");
+        assert!(git_in(path, &["for-each-ref", &backup]).is_empty());
+    }
 
+    #[test]
+    fn test_reset_to_commit_soft_keeps_changes_staged() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let changes = get_working_changes(path_str).expect("Should return changes");
 
-        assert_eq!(changes.len(), 2);
-    }
+        reset_to_commit(path_str, "HEAD~1", ResetMode::Soft).expect("Should reset");
 
-    // Tests for get_working_file_diff
+        assert!(path.join("file.txt").exists());
+        assert_eq!(
+            git_in(path, &["diff", "--cached", "--name-only"]),
+            "file.txt"
+        );
+    }
 
     #[test]
-    fn test_get_working_file_diff_modified() {
+    fn test_reset_to_commit_mixed_unstages_changes() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let original_head = git_in(path, &["rev-parse", "HEAD"]);
 
-        // Modify file
-        std::fs::write(path.join("file.txt"), "line1\nline2\nnew line").expect("Failed to write");
+        let result = reset_to_commit(path_str, "HEAD~1", ResetMode::Mixed).expect("Should reset");
 
-        let path_str = path.to_str().unwrap();
-        let diff = get_working_file_diff(path_str, "file.txt").expect("Should return diff");
+        assert!(path.join("file.txt").exists());
+        assert!(git_in(path, &["diff", "--cached", "--name-only"]).is_empty());
+        assert_eq!(git_in(path, &["status", "--porcelain"]), "?? file.txt");
 
-        assert_eq!(diff.new_path, "file.txt");
-        assert!(!diff.is_binary);
-        assert!(!diff.hunks.is_empty());
+        undo_reset(path_str, &result.undo_token).expect("Should undo reset");
 
-        // Should have additions
-        let has_addition = diff
-            .hunks
-            .iter()
-            .any(|h| h.lines.iter().any(|l| l.line_type == LineType::Addition));
-        assert!(has_addition);
+        assert_eq!(git_in(path, &["rev-parse", "HEAD"]), original_head);
+        assert!(git_in(path, &["status", "--porcelain"]).is_empty());
     }
 
     #[test]
-    fn test_get_working_file_diff_untracked() {
+    fn test_undo_reset_refuses_other_branch() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let branch = get_current_branch(path_str).unwrap();
+        git_in(path, &["branch", "other"]);
+        let original_head = git_in(path, &["rev-parse", "HEAD"]);
+
+        let result = reset_to_commit(path_str, "HEAD~1", ResetMode::Hard).expect("Should reset");
+        let backup = format!("refs/recap/backups/{}/branch", result.undo_token);
+        assert_eq!(
+            git_in(path, &["symbolic-ref", &backup]),
+            format!("refs/heads/{}", branch)
+        );
+        git_in(path, &["checkout", "-q", "other"]);
 
-        // Create untracked file
-        std::fs::write(path.join("untracked.txt"), "new file content").expect("Failed to write");
+        let error = undo_reset(path_str, &result.undo_token).unwrap_err();
 
-        let path_str = path.to_str().unwrap();
-        let diff = get_working_file_diff(path_str, "untracked.txt").expect("Should return diff");
+        assert_eq!(
+            error,
+            format!(
+                "Cannot undo the reset: it was made on '{}'. Check out '{}' first",
+                branch, branch
+            )
+        );
+        assert_eq!(git_in(path, &["rev-parse", "other"]), original_head);
 
-        assert_eq!(diff.new_path, "untracked.txt");
-        assert!(!diff.is_binary);
+        git_in(path, &["checkout", "-q", &branch]);
+        undo_reset(path_str, &result.undo_token).expect("Should undo reset");
+        assert_eq!(git_in(path, &["rev-parse", &branch]), original_head);
+        let old_backup = format!("refs/recap/backups/{}/", result.undo_token);
+        assert!(git_in(path, &["for-each-ref", &old_backup]).is_empty());
+    }
 
-        // All lines should be additions
-        for hunk in &diff.hunks {
-            for line in &hunk.lines {
-                assert_eq!(line.line_type, LineType::Addition);
+    #[test]
+    fn test_undo_reset_unknown_token() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
+
+        let result = undo_reset(path_str, "missing");
+
+        assert!(result.unwrap_err().contains("No reset backup found"));
+    }
+
+    // Tests for get_repo_state and get_conflicts
+
+    /// Changes file.txt on an "other" branch and on the current branch, then
+    /// merges "other", leaving file.txt conflicted. `theirs` None deletes it there.
+    fn create_merge_conflict(path: &std::path::Path, theirs: Option<&str>) {
+        git_in(path, &["checkout", "-b", "other"]);
+        match theirs {
+            Some(content) => {
+                std::fs::write(path.join("file.txt"), content).expect("Failed to write file");
+                git_in(path, &["commit", "-am", "Theirs"]);
+            }
+            None => {
+                git_in(path, &["rm", "-q", "file.txt"]);
+                git_in(path, &["commit", "-m", "Theirs"]);
             }
         }
+        git_in(path, &["checkout", "-"]);
+        std::fs::write(path.join("file.txt"), "ours\n").expect("Failed to write file");
+        git_in(path, &["commit", "-am", "Ours"]);
+        git_in(path, &["merge", "other"]);
     }
 
     #[test]
-    fn test_get_working_file_diff_no_changes() {
+    fn test_get_repo_state_clean() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path_str = temp_dir.path().to_str().unwrap();
 
-        let result = get_working_file_diff(path, "file.txt");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("no changes"));
+        let state = get_repo_state(path_str).expect("Should get state");
+
+        assert_eq!(state.state, RepoState::Clean);
+        assert!(state.incoming.is_none());
+        assert!(state.conflicts.is_empty());
     }
 
     #[test]
-    fn test_get_working_file_diff_nonexistent() {
+    fn test_get_repo_state_during_merge() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
-
-        let result = get_working_file_diff(path, "nonexistent.txt");
-        assert!(result.is_err());
+        let path = temp_dir.path();
+        create_merge_conflict(path, Some("theirs\n"));
+
+        let state = get_repo_state(path.to_str().unwrap()).expect("Should get state");
+
+        assert_eq!(state.state, RepoState::Merge);
+        assert_eq!(state.incoming, Some(git_in(path, &["rev-parse", "other"])));
+        assert!(state
+            .message
+            .is_some_and(|m| m.starts_with("Merge branch 'other'")));
+        assert_eq!(state.conflicts.len(), 1);
+        assert_eq!(state.conflicts[0].path, "file.txt");
+        assert_eq!(state.conflicts[0].conflict_type, ConflictType::BothModified);
     }
 
-    // Tests for get_working_file_contents
-
     #[test]
-    fn test_get_working_file_contents_modified() {
+    fn test_get_conflicts_three_way_contents() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        create_merge_conflict(path, Some("theirs\n"));
+
+        let conflicts = get_conflicts(path.to_str().unwrap()).expect("Should get conflicts");
+
+        assert_eq!(conflicts.len(), 1);
+        let conflict = &conflicts[0];
+        assert_eq!(conflict.path, "file.txt");
+        assert!(!conflict.is_binary);
+        assert_eq!(conflict.ancestor.as_deref(), Some("content"));
+        assert_eq!(conflict.ours.as_deref(), Some("ours\n"));
+        assert_eq!(conflict.theirs.as_deref(), Some("theirs\n"));
+        assert_eq!(conflict.regions.len(), 1);
+        let region = &conflict.regions[0];
+        assert_eq!(region.start_line, 1);
+        assert_eq!(region.ours_label, "HEAD");
+        assert_eq!(region.theirs_label, "other");
+        assert_eq!(region.ours, vec!["ours"]);
+        assert_eq!(region.theirs, vec!["theirs"]);
+    }
 
-        // Modify file
-        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write");
+    #[test]
+    fn test_get_conflicts_deleted_by_them() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        create_merge_conflict(path, None);
 
-        let path_str = path.to_str().unwrap();
-        let contents =
-            get_working_file_contents(path_str, "file.txt").expect("Should return contents");
+        let conflicts = get_conflicts(path.to_str().unwrap()).expect("Should get conflicts");
 
-        assert!(!contents.is_binary);
-        assert_eq!(contents.old_content, Some("content".to_string()));
-        assert_eq!(contents.new_content, Some("modified content".to_string()));
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].conflict_type, ConflictType::DeletedByThem);
+        assert_eq!(conflicts[0].ours.as_deref(), Some("ours\n"));
+        assert!(conflicts[0].theirs.is_none());
+        assert!(conflicts[0].regions.is_empty());
     }
 
     #[test]
-    fn test_get_working_file_contents_new_file() {
+    fn test_get_working_changes_ex_reports_conflicted() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        create_merge_conflict(path, Some("theirs\n"));
 
-        // Create new file
-        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write");
+        let changes = get_working_changes_ex(path.to_str().unwrap()).expect("Should get changes");
 
-        let path_str = path.to_str().unwrap();
-        let contents =
-            get_working_file_contents(path_str, "newfile.txt").expect("Should return contents");
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "file.txt");
+        assert_eq!(changes[0].unstaged_status, Some(FileStatus::Conflicted));
+        assert!(changes[0].staged_status.is_none());
+    }
 
-        assert!(!contents.is_binary);
-        assert!(contents.old_content.is_none()); // File didn't exist in HEAD
-        assert_eq!(contents.new_content, Some("new content".to_string()));
+    #[test]
+    fn test_parse_conflict_regions_diff3() {
+        let content =
+            "start\n<<<<<<< ours\na\n||||||| base\nb\n=======\nc\nd\n>>>>>>> theirs\nend\n";
+
+        let regions = parse_conflict_regions(content);
+
+        assert_eq!(
+            regions,
+            vec![ConflictRegion {
+                start_line: 2,
+                end_line: 9,
+                ours_label: "ours".to_string(),
+                theirs_label: "theirs".to_string(),
+                ours: vec!["a".to_string()],
+                base: Some(vec!["b".to_string()]),
+                theirs: vec!["c".to_string(), "d".to_string()],
+            }]
+        );
     }
 
+    // Tests for conflict resolution, continue_operation and abort_operation
+
     #[test]
-    fn test_get_working_file_contents_deleted_file() {
+    fn test_resolve_conflict_ours_and_theirs() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        create_merge_conflict(path, Some("theirs\n"));
 
-        // Delete file
-        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete");
+        resolve_conflict(path_str, "file.txt", ConflictResolution::Theirs).expect("Should resolve");
+
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "theirs\n"
+        );
+        let state = get_repo_state(path_str).unwrap();
+        assert_eq!(state.state, RepoState::Merge);
+        assert!(state.conflicts.is_empty());
+        let result = resolve_conflict(path_str, "file.txt", ConflictResolution::Ours);
+        assert!(result.unwrap_err().contains("no unresolved conflict"));
+    }
 
+    #[test]
+    fn test_resolve_conflict_union() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let contents =
-            get_working_file_contents(path_str, "file.txt").expect("Should return contents");
+        create_merge_conflict(path, Some("theirs\n"));
 
-        assert!(!contents.is_binary);
-        assert_eq!(contents.old_content, Some("content".to_string()));
-        assert!(contents.new_content.is_none()); // File was deleted
+        resolve_conflict(path_str, "file.txt", ConflictResolution::Union).expect("Should resolve");
+
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "ours\ntheirs\n"
+        );
+        assert!(get_conflicts(path_str).unwrap().is_empty());
     }
 
     #[test]
-    fn test_get_working_file_contents_nonexistent() {
+    fn test_resolve_conflict_deleted_side() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        create_merge_conflict(path, None);
 
-        let result = get_working_file_contents(path, "nonexistent.txt");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("not found"));
-    }
+        let union = resolve_conflict(path_str, "file.txt", ConflictResolution::Union);
+        assert!(union.unwrap_err().contains("deleted on one side"));
 
-    // Tests for list_branches
+        resolve_conflict(path_str, "file.txt", ConflictResolution::Theirs).expect("Should resolve");
+
+        assert!(!path.join("file.txt").exists());
+        assert!(get_conflicts(path_str).unwrap().is_empty());
+        assert_eq!(
+            git_in(path, &["diff", "--cached", "--name-status"]),
+            "D\tfile.txt"
+        );
+    }
 
     #[test]
-    fn test_list_branches_returns_current_branch() {
+    fn test_mark_resolved_with_content() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        create_merge_conflict(path, Some("theirs\n"));
 
-        let branches = list_branches(path).expect("Should return branches");
+        mark_resolved(path_str, "file.txt", Some("merged\n")).expect("Should resolve");
 
-        assert!(!branches.is_empty());
-        // Should have at least one branch marked as current
-        let current = branches.iter().find(|b| b.is_current);
-        assert!(current.is_some());
-        // Current branch should be master or main
-        let current_name = &current.unwrap().name;
-        assert!(current_name == "master" || current_name == "main");
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "merged\n"
+        );
+        assert_eq!(git_in(path, &["show", ":file.txt"]), "merged");
     }
 
     #[test]
-    fn test_list_branches_multiple_branches() {
+    fn test_continue_operation_creates_merge_commit() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        create_merge_conflict(path, Some("theirs\n"));
 
-        // Create additional branches
-        Command::new("git")
-            .args(["branch", "feature-a"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create branch");
-        Command::new("git")
-            .args(["branch", "feature-b"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create branch");
+        let unresolved = continue_operation(path_str, None);
+        assert!(unresolved.unwrap_err().contains("still have conflicts"));
 
-        let path_str = path.to_str().unwrap();
-        let branches = list_branches(path_str).expect("Should return branches");
+        resolve_conflict(path_str, "file.txt", ConflictResolution::Ours).expect("Should resolve");
+        let result = continue_operation(path_str, None).expect("Should commit");
 
-        // Should have 3 local branches
-        let local_branches: Vec<_> = branches.iter().filter(|b| !b.is_remote).collect();
-        assert_eq!(local_branches.len(), 3);
+        let commit = result.commit.expect("Should create a commit");
+        assert!(commit.message.starts_with("Merge branch 'other'"));
+        assert_eq!(
+            git_in(path, &["rev-parse", "HEAD^2"]),
+            git_in(path, &["rev-parse", "other"])
+        );
+        assert_eq!(get_repo_state(path_str).unwrap().state, RepoState::Clean);
     }
 
     #[test]
-    fn test_list_branches_current_first() {
+    fn test_continue_operation_after_cherry_pick() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
-
-        // Create another branch
-        Command::new("git")
-            .args(["branch", "zzz-last"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create branch");
-
         let path_str = path.to_str().unwrap();
-        let branches = list_branches(path_str).expect("Should return branches");
+        let (first, _) = create_topic_branch(path);
+        std::fs::write(path.join("README.md"), "# Test\n\nOther notes\n")
+            .expect("Failed to write file");
+        git_in(path, &["commit", "-am", "Other notes"]);
+        cherry_pick_commit(path_str, &[first], true).expect("Should stop on conflicts");
 
-        // Current branch should be first
-        assert!(branches[0].is_current);
+        resolve_conflict(path_str, "README.md", ConflictResolution::Theirs)
+            .expect("Should resolve");
+        continue_operation(path_str, None).expect("Should commit");
+
+        assert_eq!(
+            list_commits(path_str, Some(1)).unwrap()[0].message,
+            "Document topic"
+        );
+        assert!(git_in(path, &["rev-parse", "--verify", "-q", "HEAD^2"]).is_empty());
+        assert_eq!(get_repo_state(path_str).unwrap().state, RepoState::Clean);
     }
 
     #[test]
-    fn test_list_branches_has_commit_id() {
+    fn test_abort_operation_keeps_unrelated_changes() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        create_merge_conflict(path, Some("theirs\n"));
+        std::fs::write(path.join("README.md"), "# Local").expect("Failed to write file");
 
-        let branches = list_branches(path).expect("Should return branches");
+        abort_operation(path_str).expect("Should abort");
 
-        // All branches should have a commit ID
-        for branch in &branches {
-            assert!(!branch.commit_id.is_empty());
-            assert_eq!(branch.commit_id.len(), 40);
-        }
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "ours\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path.join("README.md")).unwrap(),
+            "# Local"
+        );
+        assert_eq!(get_repo_state(path_str).unwrap().state, RepoState::Clean);
+        assert!(abort_operation(path_str)
+            .unwrap_err()
+            .contains("No merge, revert or cherry-pick"));
     }
 
-    #[test]
-    fn test_list_branches_invalid_path() {
-        let result = list_branches("/nonexistent/path");
-        assert!(result.is_err());
+    // Tests for interactive rebase
+
+    /// Commits `content` to `file` with `message` and returns the new commit's SHA
+    fn commit_file(path: &std::path::Path, file: &str, content: &str, message: &str) -> String {
+        std::fs::write(path.join(file), content).expect("Failed to write file");
+        git_in(path, &["add", file]);
+        git_in(path, &["commit", "-m", message]);
+        git_in(path, &["rev-parse", "HEAD"])
     }
 
-    // Tests for checkout_branch
+    fn todo(action: RebaseAction, commit: &str) -> RebaseTodoItem {
+        RebaseTodoItem {
+            action,
+            commit: commit.to_string(),
+            message: None,
+        }
+    }
+
+    fn log_messages(path: &std::path::Path) -> Vec<String> {
+        git_in(path, &["log", "--format=%s"])
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
 
     #[test]
-    fn test_checkout_branch_switches_branch() {
+    fn test_start_rebase_reorders_rewords_and_drops() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
-
-        // Create a new branch
-        Command::new("git")
-            .args(["branch", "feature"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create branch");
-
         let path_str = path.to_str().unwrap();
+        let branch = git_in(path, &["symbolic-ref", "HEAD"]);
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        let a = commit_file(path, "a.txt", "a", "Add a");
+        let b = commit_file(path, "b.txt", "b", "Add b");
+        let c = commit_file(path, "c.txt", "c", "Add c");
+
+        let status = start_rebase(
+            path_str,
+            &base,
+            vec![
+                todo(RebaseAction::Pick, &c),
+                RebaseTodoItem {
+                    message: Some("Add a, reworded".to_string()),
+                    ..todo(RebaseAction::Reword, &a)
+                },
+                todo(RebaseAction::Drop, &b),
+            ],
+        )
+        .expect("Should rebase");
 
-        // Checkout the new branch
-        checkout_branch(path_str, "feature").expect("Should checkout branch");
-
-        // Verify we're on the new branch
-        let current = get_current_branch(path_str).expect("Should get current branch");
-        assert_eq!(current, "feature");
+        assert!(status.completed);
+        assert_eq!(status.head, git_in(path, &["rev-parse", "HEAD"]));
+        assert_eq!(
+            log_messages(path)[..3],
+            ["Add a, reworded", "Add c", "Add file"]
+        );
+        assert!(!path.join("b.txt").exists());
+        assert_eq!(git_in(path, &["symbolic-ref", "HEAD"]), branch);
+        assert_eq!(git_in(path, &["rev-parse", "ORIG_HEAD"]), c);
+        assert!(get_rebase_status(path_str).unwrap().is_none());
     }
 
     #[test]
-    fn test_checkout_branch_nonexistent() {
+    fn test_start_rebase_squash_and_fixup() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        let a = commit_file(path, "a.txt", "a", "Add a");
+        let b = commit_file(path, "b.txt", "b", "Add b");
+        let c = commit_file(path, "c.txt", "c", "Add c");
+
+        start_rebase(
+            path_str,
+            &base,
+            vec![
+                todo(RebaseAction::Pick, &a),
+                todo(RebaseAction::Squash, &b),
+                todo(RebaseAction::Fixup, &c),
+            ],
+        )
+        .expect("Should rebase");
 
-        let result = checkout_branch(path, "nonexistent-branch");
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("not found"));
+        assert_eq!(git_in(path, &["rev-parse", "HEAD~1"]), base);
+        assert_eq!(
+            git_in(path, &["log", "-1", "--format=%B"]),
+            "Add a\n\nAdd b"
+        );
+        for file in ["a.txt", "b.txt", "c.txt"] {
+            assert!(path.join(file).exists());
+        }
+        assert!(git_in(path, &["status", "--porcelain"]).is_empty());
     }
 
     #[test]
-    fn test_checkout_branch_with_uncommitted_changes() {
+    fn test_rebase_stops_on_conflict_and_continues() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        let one = commit_file(path, "file.txt", "one\n", "One");
+        let two = commit_file(path, "file.txt", "two\n", "Two");
+
+        let status = start_rebase(
+            path_str,
+            &base,
+            vec![
+                todo(RebaseAction::Pick, &two),
+                todo(RebaseAction::Pick, &one),
+            ],
+        )
+        .expect("Should stop on conflicts");
+
+        assert!(!status.completed);
+        assert_eq!(status.head, base);
+        assert_eq!(status.stopped_at.map(|item| item.commit), Some(two.clone()));
+        assert_eq!(status.remaining.len(), 1);
+        assert_eq!(status.conflicts[0].path, "file.txt");
+        assert_eq!(get_repo_state(path_str).unwrap().state, RepoState::Rebase);
+        assert!(continue_rebase(path_str)
+            .unwrap_err()
+            .contains("still have conflicts"));
 
-        // Create a new branch
-        Command::new("git")
-            .args(["branch", "feature"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create branch");
+        mark_resolved(path_str, "file.txt", Some("two\n")).expect("Should resolve");
+        let status = continue_rebase(path_str).expect("Should continue");
+        assert!(!status.completed);
+        assert_eq!(status.stopped_at.map(|item| item.commit), Some(one));
 
-        // Make uncommitted changes
-        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
+        mark_resolved(path_str, "file.txt", Some("one\n")).expect("Should resolve");
+        let status = continue_rebase(path_str).expect("Should continue");
 
-        let path_str = path.to_str().unwrap();
-        let result = checkout_branch(path_str, "feature");
+        assert!(status.completed);
+        assert_eq!(log_messages(path)[..3], ["One", "Two", "Add file"]);
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "one\n"
+        );
+        assert_eq!(get_repo_state(path_str).unwrap().state, RepoState::Clean);
+    }
 
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("uncommitted changes"));
+    /// Starts a rebase that swaps two commits changing file.txt, stopping on the
+    /// first one. Returns the (base, one, two) commits.
+    fn create_stopped_rebase(path: &std::path::Path) -> (String, String, String) {
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        let one = commit_file(path, "file.txt", "one\n", "One");
+        let two = commit_file(path, "file.txt", "two\n", "Two");
+        let status = start_rebase(
+            path.to_str().unwrap(),
+            &base,
+            vec![
+                todo(RebaseAction::Pick, &two),
+                todo(RebaseAction::Pick, &one),
+            ],
+        )
+        .expect("Should stop on conflicts");
+        assert!(!status.completed);
+
+        (base, one, two)
     }
 
     #[test]
-    fn test_checkout_branch_with_untracked_files_allowed() {
+    fn test_stopped_rebase_is_visible_to_git() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        create_stopped_rebase(path);
 
-        // Create a new branch
-        Command::new("git")
-            .args(["branch", "feature"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create branch");
+        let repo = Repository::open(path).unwrap();
+        assert_eq!(repo.state(), git2::RepositoryState::RebaseMerge);
+        assert!(git_in(path, &["status"]).contains("rebase in progress"));
 
-        // Create untracked file (should not block checkout)
-        std::fs::write(path.join("untracked.txt"), "new file").expect("Failed to write file");
+        // Aborting from the command line forgets the todo list too
+        git_in(path, &["rebase", "--abort"]);
+        assert_eq!(repo.state(), git2::RepositoryState::Clean);
+        assert!(get_rebase_status(path_str).unwrap().is_none());
+    }
 
+    #[test]
+    fn test_continue_rebase_refuses_moved_head() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let result = checkout_branch(path_str, "feature");
-
-        // Untracked files should not block checkout
-        assert!(result.is_ok());
+        let branch = get_current_branch(path_str).unwrap();
+        let (_, _, two) = create_stopped_rebase(path);
+        mark_resolved(path_str, "file.txt", Some("two\n")).expect("Should resolve");
+
+        // Switching behind Recap's back, e.g. with the git CLI
+        git_in(path, &["checkout", "-qf", &branch]);
+        let error = continue_rebase(path_str).unwrap_err();
+
+        assert!(error.contains("HEAD moved"));
+        assert_eq!(git_in(path, &["rev-parse", &branch]), two);
+        assert_eq!(get_repo_state(path_str).unwrap().state, RepoState::Rebase);
+        abort_rebase(path_str).expect("Should abort");
+        assert_eq!(git_in(path, &["rev-parse", &branch]), two);
     }
 
     #[test]
-    fn test_checkout_branch_updates_working_directory() {
+    fn test_rebase_in_progress_blocks_head_changes() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
+        let branch = get_current_branch(path_str).unwrap();
+        git_in(path, &["branch", "other"]);
+        create_stopped_rebase(path);
+        let blocked = |result: Result<(), String>, action: &str| {
+            assert_eq!(
+                result.unwrap_err(),
+                format!(
+                    "Cannot {} while a rebase is in progress: continue or abort it first",
+                    action
+                )
+            );
+        };
 
-        // Get the current branch name (master or main)
-        let main_branch = get_current_branch(path_str).expect("Should get branch");
-
-        // Create a new branch and add a file
-        Command::new("git")
-            .args(["checkout", "-b", "feature"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to create branch");
-
-        std::fs::write(path.join("feature-file.txt"), "feature content")
-            .expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(path)
-            .output()
-            .expect("Failed to add files");
-        Command::new("git")
-            .args(["commit", "-m", "Add feature file"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to commit");
+        blocked(
+            checkout_branch(path_str, "other", CheckoutMode::Safe).map(|_| ()),
+            "check out",
+        );
+        blocked(
+            create_branch(path_str, "new", None, true).map(|_| ()),
+            "check out",
+        );
+        blocked(
+            reset_to_commit(path_str, "HEAD~1", ResetMode::Hard).map(|_| ()),
+            "reset",
+        );
+        blocked(
+            create_commit(path_str, "Sneaky", false).map(|_| ()),
+            "commit",
+        );
+        blocked(
+            create_stash(path_str, None, false, false).map(|_| ()),
+            "stash",
+        );
+        blocked(
+            pull(path_str, PullMode::Rebase, &no_progress).map(|_| ()),
+            "pull",
+        );
 
-        // Go back to main/master using git command
-        Command::new("git")
-            .args(["checkout", &main_branch])
-            .current_dir(path)
-            .output()
-            .expect("Failed to checkout main");
+        assert!(git_in(path, &["branch", "--list", "new"]).is_empty());
+        mark_resolved(path_str, "file.txt", Some("two\n")).expect("Should resolve");
+        continue_rebase(path_str).expect("Should continue");
+        mark_resolved(path_str, "file.txt", Some("one\n")).expect("Should resolve");
+        let status = continue_rebase(path_str).expect("Should continue");
+        assert!(status.completed);
+        assert_eq!(get_current_branch(path_str).unwrap(), branch);
+    }
 
-        // File should not exist on main branch
-        assert!(!path.join("feature-file.txt").exists());
+    #[test]
+    fn test_abort_rebase_restores_branch() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let branch = git_in(path, &["symbolic-ref", "HEAD"]);
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        let one = commit_file(path, "file.txt", "one\n", "One");
+        let two = commit_file(path, "file.txt", "two\n", "Two");
+        start_rebase(
+            path_str,
+            &base,
+            vec![
+                todo(RebaseAction::Pick, &two),
+                todo(RebaseAction::Pick, &one),
+            ],
+        )
+        .expect("Should stop on conflicts");
 
-        // Checkout feature branch using our function
-        checkout_branch(path_str, "feature").expect("Should checkout branch");
+        abort_rebase(path_str).expect("Should abort");
 
-        // File should now exist
-        assert!(path.join("feature-file.txt").exists());
+        assert_eq!(git_in(path, &["symbolic-ref", "HEAD"]), branch);
+        assert_eq!(git_in(path, &["rev-parse", "HEAD"]), two);
+        assert!(git_in(path, &["status", "--porcelain"]).is_empty());
+        assert!(get_rebase_status(path_str).unwrap().is_none());
+        assert!(abort_rebase(path_str)
+            .unwrap_err()
+            .contains("No rebase is in progress"));
     }
 
     #[test]
-    fn test_checkout_branch_invalid_path() {
-        let result = checkout_branch("/nonexistent/path", "main");
-        assert!(result.is_err());
+    fn test_start_rebase_validates_todo() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        let a = commit_file(path, "a.txt", "a", "Add a");
+
+        let squash_first = start_rebase(path_str, &base, vec![todo(RebaseAction::Squash, &a)]);
+        assert!(squash_first.unwrap_err().contains("can't be squashed"));
+
+        let outside = start_rebase(path_str, &a, vec![todo(RebaseAction::Pick, &base)]);
+        assert!(outside.unwrap_err().contains("is not between"));
+
+        std::fs::write(path.join("a.txt"), "dirty").expect("Failed to write file");
+        let dirty = start_rebase(path_str, &base, vec![todo(RebaseAction::Pick, &a)]);
+        assert!(dirty.unwrap_err().contains("local changes"));
     }
 
-    // Tests for get_working_changes_ex with staged/unstaged split
+    // Tests for create_fixup_commit and autosquash
 
     #[test]
-    fn test_get_working_changes_ex_returns_empty_when_no_changes() {
+    fn test_create_fixup_commit() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let a = commit_file(path, "a.txt", "a", "Add a");
+        commit_file(path, "b.txt", "b", "Add b");
+        std::fs::write(path.join("a.txt"), "a fixed").expect("Failed to write file");
+        stage_file(path_str, "a.txt").expect("Should stage file");
 
-        let changes = get_working_changes_ex(path).expect("Should return changes");
+        let result = create_fixup_commit(path_str, &a).expect("Should commit");
 
-        assert!(changes.is_empty());
+        assert!(result.commit.is_some());
+        assert_eq!(log_messages(path)[0], "fixup! Add a");
     }
 
     #[test]
-    fn test_get_working_changes_ex_unstaged_file_appears_in_unstaged_only() {
+    fn test_autosquash_moves_fixups_onto_targets() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
-
-        // Modify file without staging
-        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
-
         let path_str = path.to_str().unwrap();
-        let changes = get_working_changes_ex(path_str).expect("Should return changes");
-
-        assert_eq!(changes.len(), 1);
-        assert_eq!(changes[0].path, "file.txt");
-        assert!(changes[0].unstaged_status.is_some());
-        assert!(changes[0].staged_status.is_none());
-        assert_eq!(changes[0].unstaged_status, Some(FileStatus::Modified));
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        git_in(path, &["checkout", "-b", "feature"]);
+        let a = commit_file(path, "a.txt", "a", "Add a");
+        let b = commit_file(path, "b.txt", "b", "Add b");
+        std::fs::write(path.join("a.txt"), "a fixed").expect("Failed to write file");
+        stage_file(path_str, "a.txt").expect("Should stage file");
+        create_fixup_commit(path_str, &a).expect("Should commit");
+        std::fs::write(path.join("b.txt"), "b fixed").expect("Failed to write file");
+        stage_file(path_str, "b.txt").expect("Should stage file");
+        create_fixup_commit(path_str, &b).expect("Should commit");
+
+        // The base defaults to where the branch left the default branch
+        let status = autosquash(path_str, None).expect("Should autosquash");
+
+        assert!(status.completed);
+        assert_eq!(log_messages(path)[..3], ["Add b", "Add a", "Add file"]);
+        assert_eq!(git_in(path, &["rev-parse", "HEAD~2"]), base);
+        assert_eq!(git_in(path, &["show", "HEAD~1:a.txt"]), "a fixed");
+        assert_eq!(git_in(path, &["show", "HEAD:b.txt"]), "b fixed");
     }
 
     #[test]
-    fn test_get_working_changes_ex_staged_file_appears_in_staged_only() {
+    fn test_autosquash_drops_squash_subject_from_message() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        commit_file(path, "a.txt", "a", "Add a");
+        commit_file(
+            path,
+            "a.txt",
+            "a, more",
+            "squash! Add a\n\nAlso handle more cases",
+        );
+        commit_file(path, "a.txt", "a, more, again", "squash! Add a");
 
-        // Modify and stage file
-        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "file.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        let status = autosquash(path_str, Some(&base)).expect("Should autosquash");
 
-        let path_str = path.to_str().unwrap();
-        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+        assert!(status.completed);
+        assert_eq!(
+            get_last_commit_message(path_str).unwrap(),
+            "Add a\n\nAlso handle more cases\n"
+        );
+        assert_eq!(git_in(path, &["rev-parse", "HEAD~1"]), base);
+    }
 
-        assert_eq!(changes.len(), 1);
-        assert_eq!(changes[0].path, "file.txt");
-        assert!(changes[0].staged_status.is_some());
-        assert!(changes[0].unstaged_status.is_none());
-        assert_eq!(changes[0].staged_status, Some(FileStatus::Modified));
+    #[test]
+    fn test_squash_message_body() {
+        assert_eq!(squash_message_body("squash! Add a\n\nMore\n"), "More");
+        assert_eq!(squash_message_body("squash! Add a\n"), "");
+        assert_eq!(squash_message_body("Add b\n\nBody\n"), "Add b\n\nBody");
     }
 
     #[test]
-    fn test_get_working_changes_ex_file_with_both_staged_and_unstaged_appears_twice() {
+    fn test_autosquash_without_fixups() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let base = git_in(path, &["rev-parse", "HEAD"]);
+        commit_file(path, "a.txt", "a", "Add a");
 
-        // Modify and stage file
-        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "file.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        let result = autosquash(path_str, Some(&base));
 
-        // Modify again (now has both staged and unstaged changes)
-        std::fs::write(path.join("file.txt"), "staged and unstaged content")
-            .expect("Failed to write file");
+        assert!(result.unwrap_err().contains("No fixup commits"));
+    }
+
+    #[test]
+    fn test_autosquash_target_nested_prefixes() {
+        assert_eq!(
+            autosquash_target("fixup! squash! Add a"),
+            Some((RebaseAction::Fixup, "Add a"))
+        );
+        assert_eq!(
+            autosquash_target("squash! Add a"),
+            Some((RebaseAction::Squash, "Add a"))
+        );
+        assert_eq!(autosquash_target("Add a"), None);
+    }
 
+    // Tests for the undo journal
+
+    #[test]
+    fn test_undo_discard_of_modified_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+        std::fs::write(path.join("file.txt"), "unsaved work").expect("Failed to write file");
 
-        assert_eq!(changes.len(), 2);
+        discard_file(path_str, "file.txt", false).expect("Should discard");
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "content"
+        );
 
-        // Find staged entry
-        let staged = changes
-            .iter()
-            .find(|c| c.staged_status.is_some())
-            .expect("Should have staged entry");
-        assert_eq!(staged.path, "file.txt");
-        assert_eq!(staged.staged_status, Some(FileStatus::Modified));
-        assert!(staged.unstaged_status.is_none());
+        let entries = list_undo_entries(path_str).expect("Should list entries");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].description, "Discard changes to file.txt");
+        assert_eq!(entries[0].paths, vec!["file.txt"]);
 
-        // Find unstaged entry
-        let unstaged = changes
-            .iter()
-            .find(|c| c.unstaged_status.is_some())
-            .expect("Should have unstaged entry");
-        assert_eq!(unstaged.path, "file.txt");
-        assert_eq!(unstaged.unstaged_status, Some(FileStatus::Modified));
-        assert!(unstaged.staged_status.is_none());
+        let undone = undo(path_str, &entries[0].id).expect("Should undo");
+
+        assert_eq!(undone.id, entries[0].id);
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "unsaved work"
+        );
+        assert!(list_undo_entries(path_str).unwrap().is_empty());
+        assert!(git_in(path, &["for-each-ref", "refs/recap/undo/"]).is_empty());
     }
 
     #[test]
-    fn test_get_working_changes_ex_tracks_line_counts_separately() {
+    fn test_undo_discard_of_untracked_file() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        std::fs::create_dir(path.join("notes")).expect("Failed to create dir");
+        std::fs::write(path.join("notes/todo.txt"), "todo").expect("Failed to write file");
 
-        // Create file with staged changes
-        std::fs::write(path.join("file.txt"), "line1\nline2").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "file.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        discard_file(path_str, "notes/todo.txt", false).expect("Should discard");
+        assert!(!path.join("notes/todo.txt").exists());
 
-        // Modify with unstaged changes
-        std::fs::write(path.join("file.txt"), "line1\nline2\nline3").expect("Failed to write file");
+        let id = list_undo_entries(path_str).unwrap()[0].id.clone();
+        undo(path_str, &id).expect("Should undo");
 
-        let path_str = path.to_str().unwrap();
-        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+        assert_eq!(
+            std::fs::read_to_string(path.join("notes/todo.txt")).unwrap(),
+            "todo"
+        );
+    }
 
-        assert_eq!(changes.len(), 2);
+    #[test]
+    fn test_undo_discard_hunk_newest_first() {
+        let temp_dir = create_repo_with_two_hunks();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let before = std::fs::read_to_string(path.join("lines.txt")).unwrap();
+        let diff = get_unstaged_file_diff(path_str, "lines.txt").expect("Should return diff");
+        discard_hunk(
+            path_str,
+            "lines.txt",
+            &diff.hunks[0].header,
+            &diff.hunks[0].hash,
+            false,
+        )
+        .expect("Should discard hunk");
+        let after_first = std::fs::read_to_string(path.join("lines.txt")).unwrap();
+        discard_file(path_str, "lines.txt", false).expect("Should discard");
+
+        let entries = list_undo_entries(path_str).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].description, "Discard changes to lines.txt");
+        assert_eq!(entries[1].description, "Discard a change to lines.txt");
+
+        undo(path_str, &entries[0].id).expect("Should undo");
+        assert_eq!(
+            std::fs::read_to_string(path.join("lines.txt")).unwrap(),
+            after_first
+        );
+        undo(path_str, &entries[1].id).expect("Should undo");
+        assert_eq!(
+            std::fs::read_to_string(path.join("lines.txt")).unwrap(),
+            before
+        );
+    }
 
-        let staged = changes.iter().find(|c| c.staged_status.is_some()).unwrap();
-        let unstaged = changes.iter().find(|c| c.unstaged_status.is_some()).unwrap();
+    #[test]
+    fn test_undo_unknown_entry() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
 
-        // Staged should have counts for staging "line1\nline2"
-        assert!(staged.staged_additions > 0);
-        assert_eq!(staged.unstaged_additions, 0);
+        let result = undo(path_str, "missing");
 
-        // Unstaged should have counts for adding "line3"
-        assert!(unstaged.unstaged_additions > 0);
-        assert_eq!(unstaged.staged_additions, 0);
+        assert!(result.unwrap_err().contains("No undo entry"));
     }
 
+    // Tests for discarding untracked directories
+
     #[test]
-    fn test_get_working_changes_ex_new_file_staged_only() {
+    fn test_discard_file_untracked_directory() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        std::fs::create_dir_all(path.join("scratch/nested")).expect("Failed to create dir");
+        std::fs::write(path.join("scratch/a.txt"), "a").expect("Failed to write file");
+        std::fs::write(path.join("scratch/nested/b.txt"), "b").expect("Failed to write file");
 
-        // Create and stage new file
-        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "newfile.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        discard_file(path_str, "scratch/", false).expect("Should discard directory");
 
-        let path_str = path.to_str().unwrap();
-        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+        assert!(!path.join("scratch").exists());
+        let entries = list_undo_entries(path_str).unwrap();
+        assert_eq!(
+            entries[0].description,
+            "Discard untracked directory scratch"
+        );
+        let mut paths = entries[0].paths.clone();
+        paths.sort();
+        assert_eq!(paths, vec!["scratch/a.txt", "scratch/nested/b.txt"]);
+
+        undo(path_str, &entries[0].id).expect("Should undo");
+        assert_eq!(
+            std::fs::read_to_string(path.join("scratch/nested/b.txt")).unwrap(),
+            "b"
+        );
+    }
 
-        assert_eq!(changes.len(), 1);
-        assert_eq!(changes[0].path, "newfile.txt");
-        assert_eq!(changes[0].staged_status, Some(FileStatus::Added));
-        assert!(changes[0].unstaged_status.is_none());
+    #[test]
+    fn test_discard_file_untracked_directory_keeps_ignored_files() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        commit_file(path, ".gitignore", ".env\ncache/\n", "Ignore secrets");
+        std::fs::create_dir_all(path.join("app/cache")).expect("Failed to create dir");
+        std::fs::write(path.join("app/main.txt"), "main").expect("Failed to write file");
+        std::fs::write(path.join("app/.env"), "SECRET=1").expect("Failed to write file");
+        std::fs::write(path.join("app/cache/blob"), "blob").expect("Failed to write file");
+
+        discard_file(path_str, "app", false).expect("Should discard directory");
+
+        assert!(!path.join("app/main.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(path.join("app/.env")).unwrap(),
+            "SECRET=1"
+        );
+        assert!(path.join("app/cache/blob").exists());
+        let entries = list_undo_entries(path_str).unwrap();
+        assert_eq!(entries[0].paths, vec!["app/main.txt"]);
     }
 
     #[test]
-    fn test_get_working_changes_ex_untracked_file_appears_in_unstaged() {
+    fn test_discard_file_refuses_ignored_file() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        commit_file(path, ".gitignore", "*.log\n", "Ignore logs");
+        std::fs::write(path.join("x.log"), "log").expect("Failed to write file");
 
-        // Create new file without adding
-        std::fs::write(path.join("untracked.txt"), "untracked content").expect("Failed to write file");
+        let result = discard_file(path_str, "x.log", false);
 
+        assert_eq!(
+            result.unwrap_err(),
+            "File 'x.log' has no changes to discard"
+        );
+        assert!(path.join("x.log").exists());
+        assert!(list_undo_entries(path_str).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_discard_file_refuses_ignored_directory() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let changes = get_working_changes_ex(path_str).expect("Should return changes");
+        commit_file(path, ".gitignore", "build/\n", "Ignore build");
+        std::fs::create_dir(path.join("build")).expect("Failed to create dir");
+        std::fs::write(path.join("build/out.bin"), "bin").expect("Failed to write file");
 
-        assert_eq!(changes.len(), 1);
-        assert_eq!(changes[0].path, "untracked.txt");
-        assert_eq!(changes[0].unstaged_status, Some(FileStatus::Untracked));
-        assert!(changes[0].staged_status.is_none());
-    }
+        let result = discard_file(path_str, "build", false);
 
-    // Tests for get_staged_file_diff
+        assert_eq!(result.unwrap_err(), "'build' only contains ignored files");
+        assert!(path.join("build/out.bin").exists());
+        assert!(list_undo_entries(path_str).unwrap().is_empty());
+    }
 
+    #[cfg(all(unix, not(target_os = "macos")))]
     #[test]
-    fn test_get_staged_file_diff_shows_staged_changes_only() {
+    fn test_discard_file_moves_untracked_to_trash() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        let data_home = TempDir::new().expect("Failed to create temp directory");
+        std::env::set_var("XDG_DATA_HOME", data_home.path());
+        std::fs::write(path.join("notes.txt"), "notes").expect("Failed to write file");
+        std::fs::create_dir(path.join("scratch")).expect("Failed to create dir");
+        std::fs::write(path.join("scratch/a.txt"), "a").expect("Failed to write file");
+        std::fs::write(path.join("draft.txt"), "draft\n").expect("Failed to write file");
+        let draft = get_unstaged_file_diff(path_str, "draft.txt").expect("Should return diff");
+
+        let file_result = discard_file(path_str, "notes.txt", true);
+        let dir_result = discard_file(path_str, "scratch", true);
+        let hunk_result = discard_hunk(
+            path_str,
+            "draft.txt",
+            &draft.hunks[0].header,
+            &draft.hunks[0].hash,
+            true,
+        );
+        std::env::remove_var("XDG_DATA_HOME");
+
+        file_result.expect("Should trash file");
+        dir_result.expect("Should trash directory");
+        hunk_result.expect("Should trash file of discarded hunk");
+        assert!(!path.join("notes.txt").exists());
+        assert!(!path.join("scratch").exists());
+        assert!(!path.join("draft.txt").exists());
+        let trash = data_home.path().join("Trash");
+        assert_eq!(
+            std::fs::read_to_string(trash.join("files/notes.txt")).unwrap(),
+            "notes"
+        );
+        assert_eq!(
+            std::fs::read_to_string(trash.join("files/scratch/a.txt")).unwrap(),
+            "a"
+        );
+        assert_eq!(
+            std::fs::read_to_string(trash.join("files/draft.txt")).unwrap(),
+            "draft\n"
+        );
+        let info = std::fs::read_to_string(trash.join("info/notes.txt.trashinfo")).unwrap();
+        assert!(info.contains(&format!(
+            "Path={}",
+            path.canonicalize().unwrap().join("notes.txt").display()
+        )));
+    }
 
-        // Modify and stage the file
-        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "file.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+    #[test]
+    fn test_discard_file_refuses_directory_with_tracked_files() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        commit_file(path, "docs.txt", "docs", "Add docs");
+        std::fs::create_dir(path.join("docs")).expect("Failed to create dir");
+        git_in(path, &["mv", "docs.txt", "docs/guide.txt"]);
 
-        // Modify again (unstaged changes)
-        std::fs::write(path.join("file.txt"), "staged and unstaged content")
-            .expect("Failed to write file");
+        let result = discard_file(path_str, "docs", false);
 
-        let path_str = path.to_str().unwrap();
-        let diff = get_staged_file_diff(path_str, "file.txt").expect("Should return diff");
+        assert!(result.unwrap_err().contains("contains tracked files"));
+        assert!(path.join("docs/guide.txt").exists());
+    }
 
-        // Staged diff should only show "staged content", not the unstaged changes
-        assert_eq!(diff.new_path, "file.txt");
-        assert!(!diff.is_binary);
-        assert!(!diff.hunks.is_empty());
+    // Tests for unstage_paths and discard_paths
 
-        // The diff should contain "staged content" (what was staged)
-        let content: String = diff
-            .hunks
-            .iter()
-            .flat_map(|h| &h.lines)
-            .map(|l| &l.content[..])
-            .collect();
-        assert!(content.contains("staged content"));
-        assert!(!content.contains("unstaged"));
+    fn specs(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|p| p.to_string()).collect()
     }
 
     #[test]
-    fn test_get_staged_file_diff_returns_error_for_unstaged_only_file() {
+    fn test_unstage_paths_directory_and_glob() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        std::fs::create_dir(path.join("gen")).expect("Failed to create dir");
+        std::fs::write(path.join("gen/a.rs"), "a").expect("Failed to write file");
+        std::fs::write(path.join("gen/b.rs"), "b").expect("Failed to write file");
+        std::fs::write(path.join("notes.md"), "notes").expect("Failed to write file");
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
+        git_in(path, &["add", "-A"]);
+
+        unstage_paths(path_str, &specs(&["gen", "*.md"])).expect("Should unstage");
+
+        assert_eq!(
+            git_in(path, &["diff", "--cached", "--name-only"]),
+            "file.txt"
+        );
+        assert!(path.join("gen/a.rs").exists());
+    }
 
-        // Modify file without staging
-        std::fs::write(path.join("file.txt"), "unstaged changes").expect("Failed to write file");
+    #[test]
+    fn test_unstage_paths_nothing_staged() {
+        let temp_dir = create_test_repo();
+        let path_str = temp_dir.path().to_str().unwrap();
 
-        let path_str = path.to_str().unwrap();
-        let result = get_staged_file_diff(path_str, "file.txt");
+        let result = unstage_paths(path_str, &specs(&["src"]));
 
-        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("No staged changes"));
     }
 
     #[test]
-    fn test_get_staged_file_diff_new_file() {
+    fn test_discard_paths_mixed_and_undo() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
+        std::fs::create_dir_all(path.join("gen/deep")).expect("Failed to create dir");
+        std::fs::write(path.join("gen/deep/out.o"), "obj").expect("Failed to write file");
+        std::fs::write(path.join("debug.log"), "log").expect("Failed to write file");
+        std::fs::write(path.join("README.md"), "# Kept").expect("Failed to write file");
+
+        discard_paths(path_str, &specs(&["file.txt", "gen/", "*.log"]), false)
+            .expect("Should discard");
+
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "content"
+        );
+        assert!(!path.join("gen").exists());
+        assert!(!path.join("debug.log").exists());
+        assert_eq!(
+            std::fs::read_to_string(path.join("README.md")).unwrap(),
+            "# Kept"
+        );
 
-        // Create and stage new file
-        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "newfile.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        let entries = list_undo_entries(path_str).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].description, "Discard changes to 3 files");
+        undo(path_str, &entries[0].id).expect("Should undo");
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "changed"
+        );
+        assert!(path.join("gen/deep/out.o").exists());
+        assert!(path.join("debug.log").exists());
+    }
 
+    #[test]
+    fn test_discard_paths_leaves_ignored_files() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let diff = get_staged_file_diff(path_str, "newfile.txt").expect("Should return diff");
+        commit_file(path, ".gitignore", "*.env\ntarget/\n", "Ignore");
+        std::fs::create_dir_all(path.join("target/debug")).expect("Failed to create dir");
+        std::fs::write(path.join("target/debug/app"), "bin").expect("Failed to write file");
+        std::fs::create_dir(path.join("tool")).expect("Failed to create dir");
+        std::fs::write(path.join("tool/run.sh"), "run").expect("Failed to write file");
+        std::fs::write(path.join("tool/local.env"), "KEY=1").expect("Failed to write file");
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
+
+        discard_paths(path_str, &specs(&["target", "tool", "file.txt"]), false)
+            .expect("Should discard");
+
+        assert!(path.join("target/debug/app").exists());
+        assert!(!path.join("tool/run.sh").exists());
+        assert!(path.join("tool/local.env").exists());
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "content"
+        );
+        let mut recorded = list_undo_entries(path_str).unwrap()[0].paths.clone();
+        recorded.sort();
+        assert_eq!(recorded, vec!["file.txt", "tool/run.sh"]);
+    }
 
-        assert_eq!(diff.new_path, "newfile.txt");
-        assert!(!diff.is_binary);
-        assert!(!diff.hunks.is_empty());
+    #[test]
+    fn test_discard_paths_only_ignored_directory() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        commit_file(path, ".gitignore", "node_modules/\n", "Ignore");
+        std::fs::create_dir(path.join("node_modules")).expect("Failed to create dir");
+        std::fs::write(path.join("node_modules/dep.js"), "dep").expect("Failed to write file");
 
-        // Should have at least one addition line (new file)
-        let has_addition = diff
-            .hunks
-            .iter()
-            .flat_map(|h| &h.lines)
-            .any(|l| l.line_type == LineType::Addition);
-        assert!(has_addition, "New file should have addition lines");
+        let result = discard_paths(path_str, &specs(&["node_modules"]), false);
+
+        assert!(result.unwrap_err().contains("No changes to discard"));
+        assert!(path.join("node_modules/dep.js").exists());
     }
 
-    // Tests for get_unstaged_file_diff
+    #[test]
+    fn test_discard_paths_directory_with_tracked_files() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        std::fs::create_dir(path.join("src")).expect("Failed to create dir");
+        commit_file(path, "src/main.rs", "fn main() {}", "Add main");
+        std::fs::write(path.join("src/main.rs"), "changed").expect("Failed to write file");
+        std::fs::create_dir(path.join("src/tmp")).expect("Failed to create dir");
+        std::fs::write(path.join("src/tmp/scratch.rs"), "scratch").expect("Failed to write file");
+
+        discard_paths(path_str, &specs(&["src"]), false).expect("Should discard");
+
+        assert_eq!(
+            std::fs::read_to_string(path.join("src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert!(!path.join("src/tmp").exists());
+        assert!(git_in(path, &["status", "--porcelain"]).is_empty());
+    }
 
     #[test]
-    fn test_get_unstaged_file_diff_shows_unstaged_changes_only() {
+    fn test_discard_paths_nothing_to_discard() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path();
+        let path_str = temp_dir.path().to_str().unwrap();
 
-        // Modify and stage
-        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "file.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        let result = discard_paths(path_str, &specs(&["README.md", "*.txt"]), false);
 
-        // Modify again (unstaged)
-        std::fs::write(path.join("file.txt"), "staged and unstaged content")
-            .expect("Failed to write file");
+        assert!(result.unwrap_err().contains("No changes to discard"));
+        assert!(list_undo_entries(path_str).unwrap().is_empty());
+    }
 
+    #[test]
+    fn test_discard_paths_reports_unmatched_pathspec() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let diff = get_unstaged_file_diff(path_str, "file.txt").expect("Should return diff");
+        std::fs::write(path.join("new.txt"), "new").expect("Failed to write file");
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
 
-        // Unstaged diff should show the difference between staged and working
-        assert_eq!(diff.new_path, "file.txt");
-        assert!(!diff.is_binary);
+        let result = discard_paths(path_str, &specs(&["new.txt", "file.txt", "nope/x"]), false);
 
-        // The diff should show the transition from "staged content" to final content
-        let has_context_or_change = diff
-            .hunks
-            .iter()
-            .flat_map(|h| &h.lines)
-            .any(|l| l.line_type == LineType::Addition || l.line_type == LineType::Context);
-        assert!(has_context_or_change);
+        assert_eq!(
+            result.unwrap_err(),
+            "Pathspec 'nope/x' did not match any files"
+        );
+        assert!(path.join("new.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "changed"
+        );
+        assert!(list_undo_entries(path_str).unwrap().is_empty());
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_get_unstaged_file_diff_unstaged_only_shows_vs_head() {
+    fn test_discard_paths_checks_removals_before_changing_anything() {
+        use std::os::unix::fs::PermissionsExt;
+
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let path_str = path.to_str().unwrap();
+        std::fs::write(path.join("file.txt"), "changed").expect("Failed to write file");
+        std::fs::create_dir(path.join("locked")).expect("Failed to create dir");
+        std::fs::write(path.join("locked/a.txt"), "a").expect("Failed to write file");
+        let set_mode = |mode| {
+            std::fs::set_permissions(path.join("locked"), std::fs::Permissions::from_mode(mode))
+                .expect("Failed to set permissions")
+        };
+        set_mode(0o555);
 
-        // Modify without staging
-        std::fs::write(path.join("file.txt"), "only unstaged").expect("Failed to write file");
+        let result = discard_paths(path_str, &specs(&["file.txt", "locked/a.txt"]), false);
+        set_mode(0o755);
 
+        assert!(result.unwrap_err().contains("Cannot remove"));
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "changed"
+        );
+        assert!(path.join("locked/a.txt").exists());
+        assert!(list_undo_entries(path_str).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_discard_paths_matches_deleted_tracked_file() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
         let path_str = path.to_str().unwrap();
-        let diff = get_unstaged_file_diff(path_str, "file.txt").expect("Should return diff");
+        std::fs::remove_file(path.join("file.txt")).expect("Failed to delete file");
 
-        assert_eq!(diff.new_path, "file.txt");
-        assert!(!diff.is_binary);
+        discard_paths(path_str, &specs(&["file.txt"]), false).expect("Should discard");
+
+        assert_eq!(
+            std::fs::read_to_string(path.join("file.txt")).unwrap(),
+            "content"
+        );
     }
 
+    // Tests for worktrees
+
     #[test]
-    fn test_get_unstaged_file_diff_returns_error_when_no_changes() {
+    fn test_validate_repo_main_is_not_worktree() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path().to_str().unwrap();
 
-        // No changes to file.txt
-        let result = get_unstaged_file_diff(path, "file.txt");
+        let info = validate_repo(path).expect("Should validate");
 
-        assert!(result.is_err());
+        assert!(!info.is_worktree);
+        assert!(info.main_repo_path.is_none());
     }
 
     #[test]
-    fn test_get_unstaged_file_diff_untracked_file() {
+    fn test_add_worktree_with_new_branch() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let wt_path = path.join("feature-wt");
 
-        // Create untracked file
-        std::fs::write(path.join("untracked.txt"), "new untracked").expect("Failed to write file");
-
-        let path_str = path.to_str().unwrap();
-        let diff = get_unstaged_file_diff(path_str, "untracked.txt").expect("Should return diff");
+        let info = add_worktree(
+            path.to_str().unwrap(),
+            wt_path.to_str().unwrap(),
+            "feature",
+            true,
+        )
+        .expect("Should add worktree");
+
+        assert!(info.is_worktree);
+        assert_eq!(info.branch, "feature");
+        assert_eq!(info.name, "feature-wt");
+        let main_info = validate_repo(path.to_str().unwrap()).unwrap();
+        assert_eq!(info.main_repo_path, Some(main_info.path));
+        assert_eq!(
+            std::fs::read_to_string(wt_path.join("file.txt")).unwrap(),
+            "content"
+        );
 
-        assert_eq!(diff.new_path, "untracked.txt");
-        assert!(!diff.is_binary);
+        // History is shared: a commit in the worktree is visible from the main repo
+        let sha = commit_file(&wt_path, "wt.txt", "wt", "Commit in worktree");
+        assert_eq!(git_in(path, &["rev-parse", "feature"]), sha);
     }
 
-    // Tests for unstage_file
-
     #[test]
-    fn test_unstage_file_removes_staged_modification() {
+    fn test_add_worktree_existing_branch() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        git_in(path, &["branch", "topic"]);
+        let wt_path = path.join("topic-wt");
+
+        let info = add_worktree(
+            path.to_str().unwrap(),
+            wt_path.to_str().unwrap(),
+            "topic",
+            false,
+        )
+        .expect("Should add worktree");
 
-        // Modify and stage
-        std::fs::write(path.join("file.txt"), "staged content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "file.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        assert_eq!(info.branch, "topic");
+        assert_eq!(
+            git_in(&wt_path, &["rev-parse", "--abbrev-ref", "HEAD"]),
+            "topic"
+        );
+    }
 
-        let path_str = path.to_str().unwrap();
+    #[test]
+    fn test_add_worktree_rejects_checked_out_branch() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let branch = get_current_branch(path.to_str().unwrap()).unwrap();
 
-        // Verify file is staged
-        let changes_before = get_working_changes_ex(path_str).expect("Should get changes");
-        assert!(changes_before.iter().any(|c| c.staged_status.is_some()));
+        let result = add_worktree(
+            path.to_str().unwrap(),
+            path.join("wt").to_str().unwrap(),
+            &branch,
+            false,
+        );
 
-        // Unstage the file
-        unstage_file(path_str, "file.txt").expect("Should unstage file");
+        assert!(result.unwrap_err().contains("already checked out"));
+        assert!(!path.join("wt").exists());
+    }
 
-        // Verify file is now unstaged only
-        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
-        let staged_count = changes_after
-            .iter()
-            .filter(|c| c.staged_status.is_some())
-            .count();
-        assert_eq!(staged_count, 0);
+    #[test]
+    fn test_add_worktree_failure_removes_new_branch() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        // A directory can't be created under a regular file
+        let wt_path = path.join("file.txt").join("wt");
+
+        let result = add_worktree(
+            path.to_str().unwrap(),
+            wt_path.to_str().unwrap(),
+            "feature",
+            true,
+        );
 
-        // Should still have unstaged changes
-        let unstaged_count = changes_after
-            .iter()
-            .filter(|c| c.unstaged_status.is_some())
-            .count();
-        assert_eq!(unstaged_count, 1);
+        assert!(result.unwrap_err().contains("Failed to add worktree"));
+        let repo = Repository::open(path).unwrap();
+        assert!(repo.find_branch("feature", BranchType::Local).is_err());
     }
 
     #[test]
-    fn test_unstage_file_removes_new_file_from_index() {
+    fn test_add_worktree_missing_branch() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
 
-        // Create and stage new file
-        std::fs::write(path.join("newfile.txt"), "new content").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "newfile.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        let result = add_worktree(
+            path.to_str().unwrap(),
+            path.join("wt").to_str().unwrap(),
+            "missing",
+            false,
+        );
 
-        let path_str = path.to_str().unwrap();
+        assert_eq!(result.unwrap_err(), "Branch 'missing' not found");
+    }
 
-        // Verify file is staged as Added
-        let changes_before = get_working_changes_ex(path_str).expect("Should get changes");
-        assert!(changes_before
-            .iter()
-            .any(|c| c.path == "newfile.txt" && c.staged_status == Some(FileStatus::Added)));
+    #[test]
+    fn test_list_worktrees_reports_branch_and_lock() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let wt_path = path.join("locked-wt");
+        git_in(
+            path,
+            &["worktree", "add", "-b", "locked", wt_path.to_str().unwrap()],
+        );
+        git_in(
+            path,
+            &[
+                "worktree",
+                "lock",
+                "--reason",
+                "on a USB drive",
+                "locked-wt",
+            ],
+        );
 
-        // Unstage the file
-        unstage_file(path_str, "newfile.txt").expect("Should unstage file");
+        let worktrees = list_worktrees(path.to_str().unwrap()).expect("Should list");
 
-        // File should now be untracked
-        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
-        assert!(changes_after
-            .iter()
-            .any(|c| c.path == "newfile.txt" && c.unstaged_status == Some(FileStatus::Untracked)));
+        assert_eq!(worktrees.len(), 2);
+        let main = &worktrees[0];
+        assert!(main.is_main);
+        assert!(main.is_current);
+        assert_eq!(
+            main.branch,
+            Some(get_current_branch(path.to_str().unwrap()).unwrap())
+        );
+        let linked = &worktrees[1];
+        assert_eq!(linked.name, "locked-wt");
+        assert!(!linked.is_main);
+        assert!(!linked.is_current);
+        assert_eq!(linked.branch.as_deref(), Some("locked"));
+        assert_eq!(linked.head, Some(git_in(path, &["rev-parse", "HEAD"])));
+        assert!(linked.is_locked);
+        assert_eq!(linked.lock_reason.as_deref(), Some("on a USB drive"));
+        assert!(!linked.is_prunable);
+
+        // Listing from the linked worktree sees the same worktrees
+        let from_linked = list_worktrees(wt_path.to_str().unwrap()).unwrap();
+        assert_eq!(from_linked.len(), 2);
+        assert!(!from_linked[0].is_current);
+        assert!(from_linked[1].is_current);
     }
 
-    // Tests for discard_file
-
     #[test]
-    fn test_discard_file_restores_modified_file() {
+    fn test_list_worktrees_detached_head() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let wt_path = path.join("detached-wt");
+        git_in(
+            path,
+            &["worktree", "add", "--detach", wt_path.to_str().unwrap()],
+        );
 
-        // Modify file (unstaged)
-        std::fs::write(path.join("file.txt"), "modified content").expect("Failed to write file");
-
-        let path_str = path.to_str().unwrap();
+        let worktrees = list_worktrees(path.to_str().unwrap()).unwrap();
 
-        // Verify file has unstaged changes
-        let changes_before = get_working_changes_ex(path_str).expect("Should get changes");
-        assert!(changes_before
-            .iter()
-            .any(|c| c.path == "file.txt" && c.unstaged_status.is_some()));
+        assert_eq!(worktrees[1].branch, None);
+        assert_eq!(
+            worktrees[1].head,
+            Some(git_in(path, &["rev-parse", "HEAD"]))
+        );
+    }
 
-        // Discard changes
-        discard_file(path_str, "file.txt").expect("Should discard changes");
+    #[test]
+    fn test_remove_worktree() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let wt_path = path.join("old-wt");
+        add_worktree(
+            path.to_str().unwrap(),
+            wt_path.to_str().unwrap(),
+            "old",
+            true,
+        )
+        .unwrap();
 
-        // Verify file content is restored
-        let content = std::fs::read_to_string(path.join("file.txt")).expect("Should read file");
-        assert_eq!(content, "content");
+        remove_worktree(path.to_str().unwrap(), "old-wt", false).expect("Should remove");
 
-        // No more changes
-        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
-        assert!(changes_after.iter().all(|c| c.path != "file.txt"));
+        assert!(!wt_path.exists());
+        assert_eq!(list_worktrees(path.to_str().unwrap()).unwrap().len(), 1);
+        // The branch itself is kept
+        assert!(!git_in(path, &["branch", "--list", "old"]).is_empty());
     }
 
     #[test]
-    fn test_discard_file_deletes_untracked_file() {
+    fn test_remove_worktree_refuses_dirty_unless_forced() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let wt_path = path.join("dirty-wt");
+        add_worktree(
+            path.to_str().unwrap(),
+            wt_path.to_str().unwrap(),
+            "dirty",
+            true,
+        )
+        .unwrap();
+        std::fs::write(wt_path.join("scratch.txt"), "wip").unwrap();
 
-        // Create untracked file
-        std::fs::write(path.join("untracked.txt"), "untracked content")
-            .expect("Failed to write file");
+        let result = remove_worktree(path.to_str().unwrap(), "dirty-wt", false);
+        assert_eq!(
+            result.unwrap_err(),
+            "Worktree 'dirty-wt' has uncommitted changes"
+        );
+        assert!(wt_path.exists());
 
-        let path_str = path.to_str().unwrap();
+        remove_worktree(path.to_str().unwrap(), "dirty-wt", true).expect("Should force remove");
+        assert!(!wt_path.exists());
+    }
 
-        // Verify file exists and is untracked
-        assert!(path.join("untracked.txt").exists());
-        let changes_before = get_working_changes_ex(path_str).expect("Should get changes");
-        assert!(changes_before.iter().any(|c| c.path == "untracked.txt"
-            && c.unstaged_status == Some(FileStatus::Untracked)));
+    #[test]
+    fn test_remove_worktree_refuses_locked_unless_forced() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let wt_path = path.join("keep-wt");
+        add_worktree(
+            path.to_str().unwrap(),
+            wt_path.to_str().unwrap(),
+            "keep",
+            true,
+        )
+        .unwrap();
+        git_in(path, &["worktree", "lock", "--reason", "in use", "keep-wt"]);
 
-        // Discard (delete) the file
-        discard_file(path_str, "untracked.txt").expect("Should discard file");
+        let result = remove_worktree(path.to_str().unwrap(), "keep-wt", false);
+        assert_eq!(result.unwrap_err(), "Worktree 'keep-wt' is locked: in use");
 
-        // Verify file is deleted
-        assert!(!path.join("untracked.txt").exists());
+        remove_worktree(path.to_str().unwrap(), "keep-wt", true).expect("Should force remove");
+        assert!(!wt_path.exists());
+    }
 
-        // No more changes for this file
-        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
-        assert!(changes_after.iter().all(|c| c.path != "untracked.txt"));
+    #[test]
+    fn test_remove_worktree_refuses_current() {
+        let temp_dir = create_test_repo();
+        let path = temp_dir.path();
+        let wt_path = path.join("here-wt");
+        add_worktree(
+            path.to_str().unwrap(),
+            wt_path.to_str().unwrap(),
+            "here",
+            true,
+        )
+        .unwrap();
+
+        let result = remove_worktree(wt_path.to_str().unwrap(), "here-wt", true);
+
+        assert!(result.unwrap_err().contains("currently open"));
+        assert!(wt_path.exists());
     }
 
     #[test]
-    fn test_discard_file_restores_to_staged_version() {
+    fn test_prune_worktrees() {
         let temp_dir = create_test_repo();
         let path = temp_dir.path();
+        let gone = path.join("gone-wt");
+        let locked = path.join("locked-wt");
+        add_worktree(path.to_str().unwrap(), gone.to_str().unwrap(), "gone", true).unwrap();
+        add_worktree(
+            path.to_str().unwrap(),
+            locked.to_str().unwrap(),
+            "locked",
+            true,
+        )
+        .unwrap();
+        git_in(path, &["worktree", "lock", "locked-wt"]);
+        std::fs::remove_dir_all(&gone).unwrap();
+        std::fs::remove_dir_all(&locked).unwrap();
+
+        let listed = list_worktrees(path.to_str().unwrap()).unwrap();
+        assert!(
+            listed
+                .iter()
+                .find(|wt| wt.name == "gone-wt")
+                .unwrap()
+                .is_prunable
+        );
 
-        // Modify and stage
-        std::fs::write(path.join("file.txt"), "staged version").expect("Failed to write file");
-        Command::new("git")
-            .args(["add", "file.txt"])
-            .current_dir(path)
-            .output()
-            .expect("Failed to stage file");
+        let pruned = prune_worktrees(path.to_str().unwrap()).expect("Should prune");
 
-        // Modify again (unstaged changes on top of staged)
-        std::fs::write(path.join("file.txt"), "unstaged version").expect("Failed to write file");
+        assert_eq!(pruned, vec!["gone-wt".to_string()]);
+        let names: Vec<String> = list_worktrees(path.to_str().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|wt| wt.name)
+            .collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"locked-wt".to_string()));
+    }
 
-        let path_str = path.to_str().unwrap();
+    // Tests for bare repositories
 
-        // Discard unstaged changes
-        discard_file(path_str, "file.txt").expect("Should discard changes");
+    #[test]
+    fn test_validate_repo_bare() {
+        let parent = TempDir::new().expect("Failed to create temp directory");
+        let source = create_test_repo();
+        let bare_path = parent.path().join("vendor.git");
+        git_in(
+            parent.path(),
+            &[
+                "clone",
+                "--bare",
+                source.path().to_str().unwrap(),
+                bare_path.to_str().unwrap(),
+            ],
+        );
 
-        // File should now match staged version
-        let content = std::fs::read_to_string(path.join("file.txt")).expect("Should read file");
-        assert_eq!(content, "staged version");
+        let info = validate_repo(bare_path.to_str().unwrap()).expect("Should accept bare repo");
 
-        // Should still have staged changes
-        let changes_after = get_working_changes_ex(path_str).expect("Should get changes");
-        assert!(changes_after
-            .iter()
-            .any(|c| c.path == "file.txt" && c.staged_status.is_some()));
-        // Should not have unstaged changes
-        assert!(changes_after
-            .iter()
-            .all(|c| c.path != "file.txt" || c.unstaged_status.is_none()));
+        assert!(info.is_bare);
+        assert!(!info.is_worktree);
+        assert_eq!(info.name, "vendor");
+        assert_eq!(
+            std::path::Path::new(&info.path).canonicalize().unwrap(),
+            bare_path.canonicalize().unwrap()
+        );
+        assert_eq!(
+            info.branch,
+            get_current_branch(source.path().to_str().unwrap()).unwrap()
+        );
     }
 
     #[test]
-    fn test_discard_file_error_when_no_changes() {
+    fn test_validate_repo_mirror() {
+        let parent = TempDir::new().expect("Failed to create temp directory");
+        let source = create_test_repo();
+        git_in(source.path(), &["tag", "v1.0"]);
+        let mirror_path = parent.path().join("mirror.git");
+        git_in(
+            parent.path(),
+            &[
+                "clone",
+                "--mirror",
+                source.path().to_str().unwrap(),
+                mirror_path.to_str().unwrap(),
+            ],
+        );
+
+        let info = validate_repo(mirror_path.to_str().unwrap()).expect("Should accept mirror");
+
+        assert!(info.is_bare);
+        assert_eq!(info.name, "mirror");
+    }
+
+    #[test]
+    fn test_validate_repo_non_bare_git_dir() {
         let temp_dir = create_test_repo();
-        let path = temp_dir.path().to_str().unwrap();
+        let git_dir = temp_dir.path().join(".git");
 
-        // File exists but has no changes
-        let result = discard_file(path, "file.txt");
+        let info = validate_repo(git_dir.to_str().unwrap()).expect("Should validate");
 
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("no changes to discard"));
+        assert!(!info.is_bare);
+        assert_eq!(
+            info.name,
+            temp_dir.path().file_name().unwrap().to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_bare_repo_history_apis() {
+        let bare = create_bare_origin();
+        let path = bare.path().to_str().unwrap();
+
+        let commits = list_commits(path, None).expect("Should list commits");
+        assert_eq!(commits.len(), 2);
+        let files = get_commit_files(path, &commits[0].id).expect("Should get commit files");
+        assert_eq!(files.len(), 1);
+        let compared =
+            compare_revisions(path, &commits[1].id, &commits[0].id).expect("Should compare");
+        assert_eq!(compared.len(), 1);
+        assert!(!list_branches(path)
+            .expect("Should list branches")
+            .is_empty());
+        create_tag(path, "v1.0", None, None).expect("Should create tag");
+        assert_eq!(list_tags(path).expect("Should list tags").len(), 1);
+        assert_eq!(get_repo_state(path).unwrap().state, RepoState::Clean);
+    }
+
+    #[test]
+    fn test_bare_repo_changes_view_not_available() {
+        let bare = create_bare_origin();
+        let path = bare.path().to_str().unwrap();
+        let expected = "Not available in a bare repository: it has no working directory";
+
+        assert_eq!(get_working_changes(path).unwrap_err(), expected);
+        assert_eq!(get_working_changes_ex(path).unwrap_err(), expected);
+        assert_eq!(
+            get_working_file_diff(path, "file.txt").unwrap_err(),
+            expected
+        );
+        assert_eq!(stage_file(path, "file.txt").unwrap_err(), expected);
+        assert_eq!(stage_all(path).unwrap_err(), expected);
+        assert_eq!(discard_file(path, "file.txt", false).unwrap_err(), expected);
+        assert_eq!(create_commit(path, "Message", false).unwrap_err(), expected);
+    }
+
+    #[test]
+    fn test_bare_repo_working_directory_operations_not_available() {
+        let bare = create_bare_origin();
+        let path = bare.path().to_str().unwrap();
+        let expected = "Not available in a bare repository: it has no working directory";
+        let head = list_commits(path, None).unwrap()[0].id.clone();
+
+        assert_eq!(
+            checkout_branch(path, "main", CheckoutMode::Safe).unwrap_err(),
+            expected
+        );
+        assert_eq!(
+            checkout_detached(path, &head, CheckoutMode::Safe).unwrap_err(),
+            expected
+        );
+        assert_eq!(
+            create_branch(path, "topic", None, true).unwrap_err(),
+            expected
+        );
+        assert_eq!(get_conflicts(path).unwrap_err(), expected);
+        assert_eq!(
+            resolve_conflict(path, "file.txt", ConflictResolution::Ours).unwrap_err(),
+            expected
+        );
+        assert_eq!(abort_operation(path).unwrap_err(), expected);
+        assert_eq!(
+            create_stash(path, None, false, false).unwrap_err(),
+            expected
+        );
+        assert_eq!(list_stashes(path).unwrap_err(), expected);
+        assert_eq!(start_rebase(path, &head, Vec::new()).unwrap_err(), expected);
+        assert_eq!(continue_rebase(path).unwrap_err(), expected);
+        assert_eq!(undo(path, "0").unwrap_err(), expected);
+        let ids = vec![head.clone()];
+        assert_eq!(revert_commit(path, &ids, true).unwrap_err(), expected);
+        assert_eq!(cherry_pick_commit(path, &ids, true).unwrap_err(), expected);
+        assert_eq!(
+            reset_to_commit(path, &head, ResetMode::Hard).unwrap_err(),
+            expected
+        );
+        assert_eq!(undo_reset(path, "token").unwrap_err(), expected);
+        assert_eq!(
+            pull(path, PullMode::FastForwardOnly, &no_progress).unwrap_err(),
+            expected
+        );
+        assert_eq!(autosquash(path, Some(&head)).unwrap_err(), expected);
+        // History-only operations still work
+        create_branch(path, "topic", None, false).expect("Should create branch");
     }
 }
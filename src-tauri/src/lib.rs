@@ -46,15 +46,35 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::errors::report_frontend_error,
             commands::git::list_commits,
+            commands::git::verify_commit_signatures,
             commands::git::get_commit_files,
             commands::git::get_commit_range_files,
             commands::git::get_file_diff,
+            commands::git::compare_revisions,
+            commands::git::get_compare_file_diff,
             commands::git::get_file_contents,
             commands::git::get_commit_range_file_contents,
             commands::git::get_current_branch,
             commands::git::list_branches,
             commands::git::checkout_branch,
+            commands::git::checkout_remote_branch,
+            commands::git::checkout_detached,
+            commands::git::create_branch,
+            commands::git::rename_branch,
+            commands::git::delete_branch,
+            commands::git::set_branch_upstream,
+            commands::git::list_tags,
+            commands::git::create_tag,
+            commands::git::delete_tag,
+            commands::git::list_remotes,
+            commands::git::fetch,
+            commands::git::pull,
+            commands::git::push,
             commands::git::validate_repo,
+            commands::git::list_worktrees,
+            commands::git::add_worktree,
+            commands::git::remove_worktree,
+            commands::git::prune_worktrees,
             commands::git::get_working_changes,
             commands::git::get_working_file_diff,
             commands::git::get_working_file_contents,
@@ -63,8 +83,45 @@ pub fn run() {
             commands::git::get_unstaged_file_diff,
             commands::git::get_staged_file_contents,
             commands::git::get_unstaged_file_contents,
+            commands::git::stage_file,
+            commands::git::stage_all,
             commands::git::unstage_file,
-            commands::git::discard_file
+            commands::git::unstage_all,
+            commands::git::unstage_paths,
+            commands::git::discard_file,
+            commands::git::discard_paths,
+            commands::git::list_undo_entries,
+            commands::git::undo,
+            commands::git::stage_hunk,
+            commands::git::unstage_hunk,
+            commands::git::discard_hunk,
+            commands::git::stage_lines,
+            commands::git::unstage_lines,
+            commands::git::create_commit,
+            commands::git::get_last_commit_message,
+            commands::git::revert_commit,
+            commands::git::cherry_pick_commit,
+            commands::git::reset_to_commit,
+            commands::git::undo_reset,
+            commands::git::get_repo_state,
+            commands::git::get_conflicts,
+            commands::git::resolve_conflict,
+            commands::git::mark_resolved,
+            commands::git::continue_operation,
+            commands::git::abort_operation,
+            commands::git::start_rebase,
+            commands::git::continue_rebase,
+            commands::git::abort_rebase,
+            commands::git::get_rebase_status,
+            commands::git::create_fixup_commit,
+            commands::git::autosquash,
+            commands::git::list_stashes,
+            commands::git::create_stash,
+            commands::git::apply_stash,
+            commands::git::pop_stash,
+            commands::git::drop_stash,
+            commands::git::get_stash_files,
+            commands::git::get_stash_file_diff
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -47,6 +47,8 @@ function getStatusLetter(status: FileStatus): string {
       return "C";
     case "Untracked":
       return "?";
+    case "Conflicted":
+      return "U";
     default:
       return "?";
   }
@@ -68,6 +70,8 @@ function getStatusBadgeClasses(status: FileStatus): string {
       return "bg-info/20 text-info";
     case "Untracked":
       return "bg-success/20 text-success";
+    case "Conflicted":
+      return "bg-danger/20 text-danger";
     default:
       return "bg-text-secondary/20 text-text-secondary";
   }
@@ -8,6 +8,9 @@ export interface RepoInfo {
   path: string;
   name: string;
   branch: string;
+  is_worktree: boolean;
+  main_repo_path: string | null;
+  is_bare: boolean;
 }
 
 export interface AddRepoButtonProps {
@@ -3,4 +3,10 @@ export interface Branch {
   is_current: boolean;
   is_remote: boolean;
   commit_id: string;
+  summary: string;
+  timestamp: number;
+  upstream: string | null;
+  ahead: number;
+  behind: number;
+  is_merged: boolean;
 }
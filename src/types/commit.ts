@@ -1,3 +1,15 @@
+/**
+ * Verification status of a commit signature.
+ * Mirrors the Rust SignatureStatus enum from the backend.
+ */
+export type SignatureStatus =
+  | "Unsigned"
+  | "Signed"
+  | "Good"
+  | "Expired"
+  | "Bad"
+  | "UnknownKey";
+
 /**
  * Represents a git commit with essential metadata.
  * Mirrors the Rust Commit struct from the backend.
@@ -13,4 +25,21 @@ export interface Commit {
   email: string;
   /** Unix timestamp of when the commit was authored */
   timestamp: number;
+  /** Whether the commit is signed; verify_commit_signatures checks the signature */
+  signature_status: SignatureStatus;
+  /** Identity of the signer (key user ID or SSH principal), if verified */
+  signer: string | null;
+}
+
+/**
+ * Result of verifying the signature of one commit.
+ * Mirrors the Rust SignatureVerification struct from the backend.
+ */
+export interface SignatureVerification {
+  /** The SHA hash of the commit */
+  commit_id: string;
+  /** Verification status of the commit signature */
+  status: SignatureStatus;
+  /** Identity of the signer (key user ID or SSH principal), if known */
+  signer: string | null;
 }
@@ -24,6 +24,10 @@ export interface DiffLine {
  * Mirrors the Rust DiffHunk struct from the backend.
  */
 export interface DiffHunk {
+  /** The hunk header line (e.g. "@@ -1,3 +1,4 @@ fn main") */
+  header: string;
+  /** Hash of the hunk's lines, used to detect stale hunk selections */
+  hash: string;
   /** Starting line in old file */
   old_start: number;
   /** Number of lines in old file */
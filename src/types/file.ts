@@ -9,7 +9,8 @@ export type FileStatus =
   | "Renamed"
   | "Copied"
   | "Unmodified"
-  | "Untracked";
+  | "Untracked"
+  | "Conflicted";
 
 /**
  * Represents a changed file in a commit.